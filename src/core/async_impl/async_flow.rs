@@ -1,19 +1,34 @@
 use crate::core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
+use crate::core::events::{self, EventContext, FlowEvent, FlowEventKind, FlowListener};
+use crate::core::scope::FlowScope;
+use crate::core::graph::FlowGraph;
+use crate::core::middleware::{MiddlewareChain, NodeMiddleware};
+use crate::core::runner::{self, RunEnv};
 use crate::core::sync_impl::NodeValue;
-use crate::core::telemetry::Telemetry;
-use crate::core::validation::ValidationResult;
+use crate::core::telemetry::{SpanContext, Telemetry};
+use crate::core::validation::{self, ValidationResult};
 use crate::core::{Executable, Executable::Async, Executable::Sync, Executable::Sealed};
 use async_trait::async_trait;
+use futures::channel::mpsc::{self, UnboundedReceiver};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 /// The logic that is specific to orchestration of async nodes.
 #[derive(Clone)]
 pub struct AsyncFlowLogic {
-    start: Executable,
-    telemetry: Option<Arc<dyn Telemetry>>,
+    pub(crate) start: Executable,
+    pub(crate) telemetry: Option<Arc<dyn Telemetry>>,
+    pub(crate) scope: Option<FlowScope>,
+    pub(crate) middleware: MiddlewareChain,
+    /// The span of the current run of this flow, which its nodes are nested in.
+    pub(crate) span: Option<SpanContext>,
+    /// The initial keys declared by the definition this flow was built from.
+    pub(crate) inputs: Option<Vec<String>>,
 }
 
+/// Resolves to the final action and shared state of a flow spawned by `AsyncFlow::run_streaming`.
+pub type FlowRunHandle = tokio::task::JoinHandle<(Option<String>, HashMap<String, NodeValue>)>;
+
 /// A flow really, just is a Node with orchestration logic
 /// to enforce that, we will create a NewType with a "factory" which prebuilds it.
 #[derive(Clone)]
@@ -34,9 +49,48 @@ impl std::ops::DerefMut for AsyncFlow {
     }
 }
 
+/// Lets a flow be nested as a node of another flow.
+impl From<AsyncFlow> for Executable {
+    fn from(flow: AsyncFlow) -> Self {
+        Executable::Async(flow.0)
+    }
+}
+
 impl AsyncFlow {
     pub fn new(start: Executable) -> AsyncFlow {
-        AsyncFlow(AsyncNode::new(AsyncFlowLogic { start, telemetry: None }))
+        AsyncFlow(AsyncNode::new(AsyncFlowLogic {
+            start,
+            telemetry: None,
+            scope: None,
+            middleware: Vec::new(),
+            span: None,
+            inputs: None,
+        }))
+    }
+
+    /// Wraps every node of this flow, and of the flows nested in it, with `middleware`.
+    pub fn with_middleware(mut self, middleware: impl NodeMiddleware + 'static) -> Self {
+        let behaviour: &mut dyn AsyncNodeLogic = &mut *self.behaviour;
+
+        if let Some(flow_logic) = behaviour.as_any_mut().downcast_mut::<AsyncFlowLogic>() {
+            flow_logic.middleware.push(Arc::new(middleware));
+        } else {
+            panic!("Error: Flow's logic is not of type FlowLogic");
+        }
+        self
+    }
+
+    /// Runs this flow in its own namespace: it only sees the mapped inputs of the
+    /// caller's shared state, and only the mapped outputs are written back.
+    pub fn scoped(mut self, scope: FlowScope) -> Self {
+        let behaviour: &mut dyn AsyncNodeLogic = &mut *self.behaviour;
+
+        if let Some(flow_logic) = behaviour.as_any_mut().downcast_mut::<AsyncFlowLogic>() {
+            flow_logic.scope = Some(scope);
+        } else {
+            panic!("Error: Flow's logic is not of type FlowLogic");
+        }
+        self
     }
 
     pub async fn run(&self, shared: &mut HashMap<String, NodeValue>) -> Option<String> {
@@ -49,12 +103,64 @@ impl AsyncFlow {
         shared: &mut HashMap<String, NodeValue>,
         telemetry: Option<Arc<dyn Telemetry>>,
     ) -> Option<String> {
-        let mut cloned_self = self.clone();
-        if let Some(logic) = cloned_self.behaviour.as_any_mut().downcast_mut::<AsyncFlowLogic>() {
-            logic.telemetry = telemetry.clone();
-        }
-        
-        cloned_self.0.run_with_telemetry(shared, telemetry.as_deref().map(|t| t as &dyn Telemetry)).await
+        runner::run_async_node(
+            &self.0,
+            shared,
+            RunEnv {
+                telemetry: telemetry.as_ref(),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Executes the workflow, reporting its progress to `listener`.
+    pub async fn run_with_listener(
+        &self,
+        shared: &mut HashMap<String, NodeValue>,
+        listener: Arc<dyn FlowListener>,
+    ) -> Option<String> {
+        self.run_with_telemetry_and_listener(shared, None, listener).await
+    }
+
+    /// Executes the workflow, recording telemetry if a logger is provided and reporting its
+    /// progress to `listener`. The events and the trace entries share the same run id.
+    pub async fn run_with_telemetry_and_listener(
+        &self,
+        shared: &mut HashMap<String, NodeValue>,
+        telemetry: Option<Arc<dyn Telemetry>>,
+        listener: Arc<dyn FlowListener>,
+    ) -> Option<String> {
+        let context = EventContext::new(listener, self.graph());
+        context.emit(FlowEventKind::FlowStarted);
+        let action = context
+            .clone()
+            .scope(self.run_with_telemetry(shared, telemetry))
+            .await;
+        context.emit(FlowEventKind::FlowFinished {
+            action: action.clone(),
+        });
+        action
+    }
+
+    /// Spawns the workflow on the tokio runtime and streams its events.
+    ///
+    /// The stream ends once the flow is done; the handle yields the final action and shared state.
+    pub fn run_streaming(
+        &self,
+        mut shared: HashMap<String, NodeValue>,
+    ) -> (UnboundedReceiver<FlowEvent>, FlowRunHandle) {
+        let (sender, receiver) = mpsc::unbounded();
+        let flow = self.clone();
+        let handle = tokio::spawn(async move {
+            let listener = move |event: &FlowEvent| {
+                // The caller may drop the stream and only care about the result.
+                let _ = sender.unbounded_send(event.clone());
+            };
+            let action = flow.run_with_listener(&mut shared, Arc::new(listener)).await;
+            (action, shared)
+        });
+        (receiver, handle)
     }
 
     pub fn start(&mut self, start: Executable) {
@@ -70,64 +176,79 @@ impl AsyncFlow {
         }
     }
 
-    /// Validates the data flow integrity of the entire workflow (Async).
-    pub fn validate(&self, initial_keys: Vec<String>) -> ValidationResult {
-        let mut result = ValidationResult::new();
-        let mut visited = HashSet::new();
-        let mut available_keys = initial_keys.into_iter().collect::<HashSet<_>>();
+    /// Remembers the initial keys declared by the definition this flow was built from.
+    pub(crate) fn declare_inputs(&mut self, inputs: Vec<String>) {
+        let behaviour: &mut dyn AsyncNodeLogic = &mut *self.behaviour;
 
-        let behaviour: &dyn AsyncNodeLogic = &*self.behaviour;
-        if let Some(flow_logic) = behaviour.as_any().downcast_ref::<AsyncFlowLogic>() {
-            self.validate_recursive(
-                &flow_logic.start,
-                &mut available_keys,
-                &mut visited,
-                &mut result,
-            );
+        if let Some(flow_logic) = behaviour.as_any_mut().downcast_mut::<AsyncFlowLogic>() {
+            flow_logic.inputs = Some(inputs);
+        } else {
+            panic!("Error: Flow's logic is not of type FlowLogic");
         }
+    }
 
-        result
+    pub(crate) fn declared_inputs(&self) -> Option<Vec<String>> {
+        let behaviour: &dyn AsyncNodeLogic = &*self.behaviour;
+        behaviour
+            .as_any()
+            .downcast_ref::<AsyncFlowLogic>()
+            .and_then(|flow_logic| flow_logic.inputs.clone())
     }
 
-    fn validate_recursive(
-        &self,
-        current: &Executable,
-        available_keys: &mut HashSet<String>,
-        visited: &mut HashSet<String>,
-        result: &mut ValidationResult,
-    ) {
-        let sealable = match current {
-            Executable::Sync(node) => node.behaviour.as_sealable(),
-            Executable::Async(node) => node.behaviour.as_sealable(),
-            Executable::Sealed(sealed) => Some(sealed.as_ref() as &dyn crate::core::semantic::Sealable),
-        };
+    /// Builds the static graph model of this flow.
+    pub fn graph(&self) -> FlowGraph {
+        FlowGraph::from_executable(&self.start_executable())
+    }
 
-        if let Some(s) = sealable {
-            let signature = s.signature();
-            let task_id = s.task_id();
+    /// Renders the flow's graph as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        self.graph().to_dot()
+    }
 
-            if visited.contains(&task_id) {
-                return;
-            }
-            visited.insert(task_id.clone());
-
-            for input in signature.inputs {
-                if !available_keys.contains(&input.name) {
-                    result.add_error(format!(
-                        "Node '{}' requires input '{}' which is missing from the shared state.",
-                        task_id, input.name
-                    ));
-                }
-            }
+    /// Renders the flow's graph as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        self.graph().to_mermaid()
+    }
+
+    pub(crate) fn start_executable(&self) -> Executable {
+        let behaviour: &dyn AsyncNodeLogic = &*self.behaviour;
+        match behaviour.as_any().downcast_ref::<AsyncFlowLogic>() {
+            Some(flow_logic) => flow_logic.start.clone(),
+            None => panic!("Error: Flow's logic is not of type FlowLogic"),
+        }
+    }
+
+    /// Validates the data flow integrity of the entire workflow (Async).
+    ///
+    /// If the flow is scoped, `initial_keys` are the keys of the caller's shared state.
+    pub fn validate(&self, initial_keys: Vec<String>) -> ValidationResult {
+        let mut result = ValidationResult::new();
+        let available_keys = initial_keys.into_iter().collect::<HashSet<_>>();
+        let graph = self.graph();
+        let start = &graph.root().id;
 
-            for output in signature.outputs {
-                available_keys.insert(output.name);
+        let behaviour: &dyn AsyncNodeLogic = &*self.behaviour;
+        if let Some(flow_logic) = behaviour.as_any().downcast_ref::<AsyncFlowLogic>() {
+            match &flow_logic.scope {
+                Some(scope) => {
+                    validation::validate_scoped(&graph, start, scope, &available_keys, &mut result);
+                }
+                None => {
+                    validation::validate_paths(&graph, start, available_keys, &mut result);
+                }
             }
         }
 
-        for successor in current.successors().values() {
-            let mut branch_keys = available_keys.clone();
-            self.validate_recursive(successor, &mut branch_keys, visited, result);
+        result
+    }
+}
+
+impl AsyncFlowLogic {
+    fn run_env(&self) -> RunEnv<'_> {
+        RunEnv {
+            telemetry: self.telemetry.as_ref(),
+            middleware: &self.middleware,
+            parent: self.span.as_ref(),
         }
     }
 }
@@ -139,7 +260,11 @@ impl AsyncNodeLogic for AsyncFlowLogic {
         params: &HashMap<String, NodeValue>,
         shared: &HashMap<String, NodeValue>,
     ) -> NodeValue {
-        serde_json::to_value((params, shared)).expect("If this works, I'll be so lit")
+        match &self.scope {
+            Some(scope) => serde_json::to_value((params, scope.enter(shared))),
+            None => serde_json::to_value((params, shared)),
+        }
+        .expect("If this works, I'll be so lit")
     }
 
     async fn exec(&self, input: NodeValue) -> NodeValue {
@@ -160,46 +285,93 @@ impl AsyncNodeLogic for AsyncFlowLogic {
         let mut current: Option<Executable> = Some(self.start.clone());
         let mut last_action: String = "".into();
 
+        // Progress is only reported if a listener is attached to this run.
+        let events = EventContext::current();
+
         // This is the orchestration logic
+        let mut node_id = events.as_ref().and_then(EventContext::start_id);
         while let Some(mut curr) = current {
-            last_action = match curr {
-                Sync(ref mut sync_node) => {
-                    let mut sync_clone = sync_node.clone();
-                    sync_clone.set_params(params.clone());
-                    let mut shared_clone = shared.clone();
-                    let telemetry_ref = self.telemetry.clone();
-
-                    match tokio::task::spawn_blocking(move || {
-                        let action = sync_clone
-                            .run_with_telemetry(&mut shared_clone, telemetry_ref.as_deref().map(|t| t as &dyn Telemetry))
-                            .unwrap_or("default".into());
-                        (action, shared_clone)
-                    })
-                    .await
-                    {
-                        Ok((next_action, modified_shared)) => {
-                            shared = modified_shared;
-                            next_action
-                        }
-                        Err(join_error) => {
-                            log::error!("A synchronous node panicked: {:?}", join_error);
-                            "default".into()
+            let node_events = events.as_ref().map(|context| {
+                context.for_node(node_id.clone().unwrap_or_else(|| events::node_id(&curr)))
+            });
+            let before = node_events.as_ref().map(|context| {
+                context.emit(FlowEventKind::NodeStarted);
+                shared.clone()
+            });
+
+            let step = async {
+                match curr {
+                    Sync(ref mut sync_node) => {
+                        let mut sync_clone = sync_node.clone();
+                        sync_clone.set_params(params.clone());
+                        let mut shared_clone = shared.clone();
+                        let telemetry_ref = self.telemetry.clone();
+                        let chain = self.middleware.clone();
+                        let span = self.span.clone();
+                        // Task-locals don't follow the blocking thread, so the node's event
+                        // context is attached to it again.
+                        let node_events = EventContext::current();
+
+                        match tokio::task::spawn_blocking(move || {
+                            let run = || {
+                                let env = RunEnv {
+                                    telemetry: telemetry_ref.as_ref(),
+                                    middleware: &chain,
+                                    parent: span.as_ref(),
+                                };
+                                let action = runner::run_node(&sync_clone, &mut shared_clone, env)
+                                    .unwrap_or("default".into());
+                                (action, shared_clone)
+                            };
+                            match node_events {
+                                Some(context) => context.sync_scope(run),
+                                None => run(),
+                            }
+                        })
+                        .await
+                        {
+                            Ok((next_action, modified_shared)) => {
+                                shared = modified_shared;
+                                next_action
+                            }
+                            Err(join_error) => {
+                                log::error!("A synchronous node panicked: {:?}", join_error);
+                                events::emit(FlowEventKind::Error {
+                                    message: format!("Node panicked: {}", join_error),
+                                });
+                                "default".into()
+                            }
                         }
                     }
-                }
-                Async(ref mut async_node) => {
-                    async_node.set_params(params.clone());
-                    async_node
-                        .run_with_telemetry(&mut shared, self.telemetry.as_deref())
-                        .await
-                        .unwrap_or("default".into())
-                }
-                Sealed(ref sealed_node) => {
-                    sealed_node.run(&mut shared, self.telemetry.as_deref())
-                        .await
+                    Async(ref mut async_node) => {
+                        async_node.set_params(params.clone());
+                        runner::run_async_node(async_node, &mut shared, self.run_env())
+                            .await
                         .unwrap_or("default".into())
+                    }
+                    Sealed(ref sealed_node) => {
+                        runner::run_sealed(sealed_node, &mut shared, self.run_env())
+                            .await
+                            .unwrap_or("default".into())
+                    }
                 }
             };
+            last_action = match node_events.clone() {
+                Some(context) => context.scope(step).await,
+                None => step.await,
+            };
+
+            if let (Some(context), Some(before)) = (&node_events, &before) {
+                if !events::is_async_flow(&curr) {
+                    context.emit_changes(before, &shared);
+                }
+                context.emit(FlowEventKind::NodeFinished {
+                    action: last_action.clone(),
+                });
+            }
+            if let (Some(context), Some(id)) = (&events, &node_id) {
+                node_id = context.next_id(id, &last_action);
+            }
 
             let next_executable = &curr.successors().get(&last_action).cloned();
             current = next_executable.clone();
@@ -233,7 +405,10 @@ impl AsyncNodeLogic for AsyncFlowLogic {
             ("default".into(), shared.clone())
         };
 
-        *shared = shared_post;
+        match &self.scope {
+            Some(scope) => scope.exit(&shared_post, shared),
+            None => *shared = shared_post,
+        }
         Some(last_action)
     }
 
@@ -397,4 +572,194 @@ mod tests {
         assert_eq!(shared.get("visited_async3"), Some(&json!(true)));
         assert_eq!(action, Some("default".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_run_streaming_reports_nested_progress() {
+        use futures::StreamExt;
+
+        let inner = AsyncFlow::new(Executable::Sync(Node::new(SimpleSyncLogic {
+            id: "sync2".to_string(),
+            next_action: None,
+        })));
+        let start = AsyncNode::new(SimpleAsyncLogic {
+            id: "async1".to_string(),
+            next_action: Some("default".to_string()),
+        })
+        .next(Executable::Async(
+            AsyncNode::new(SimpleAsyncLogic {
+                id: "async2".to_string(),
+                next_action: Some("default".to_string()),
+            })
+            .next(inner.into()),
+        ));
+        let flow = AsyncFlow::new(Executable::Async(start));
+        let ids: Vec<String> = flow.graph().nodes().iter().map(|node| node.id.clone()).collect();
+        assert_eq!(
+            ids,
+            [
+                "SimpleAsyncLogic#0",
+                "SimpleAsyncLogic#1",
+                "AsyncFlow#2",
+                "AsyncFlow#2/SimpleSyncLogic#3",
+            ]
+        );
+
+        let (events, handle) = flow.run_streaming(HashMap::new());
+        let events: Vec<FlowEvent> = events.collect().await;
+        let (action, shared) = handle.await.unwrap();
+        assert_eq!(action, Some("default".to_string()));
+        assert_eq!(shared.get("visited_sync2"), Some(&json!(true)));
+
+        let run_id = &events[0].run_id;
+        assert!(events.iter().all(|event| &event.run_id == run_id));
+
+        let summary: Vec<(Option<&str>, &str)> = events
+            .iter()
+            .map(|event| {
+                let kind = match &event.kind {
+                    FlowEventKind::FlowStarted => "flow_started",
+                    FlowEventKind::NodeStarted => "node_started",
+                    FlowEventKind::NodeFinished { .. } => "node_finished",
+                    FlowEventKind::SharedChanged { .. } => "shared_changed",
+                    FlowEventKind::LlmChunk { .. } => "llm_chunk",
+                    FlowEventKind::Error { .. } => "error",
+                    FlowEventKind::FlowFinished { .. } => "flow_finished",
+                };
+                (event.node_id.as_deref(), kind)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, "flow_started"),
+                (Some("SimpleAsyncLogic#0"), "node_started"),
+                (Some("SimpleAsyncLogic#0"), "shared_changed"),
+                (Some("SimpleAsyncLogic#0"), "node_finished"),
+                (Some("SimpleAsyncLogic#1"), "node_started"),
+                (Some("SimpleAsyncLogic#1"), "shared_changed"),
+                (Some("SimpleAsyncLogic#1"), "node_finished"),
+                (Some("AsyncFlow#2"), "node_started"),
+                (Some("AsyncFlow#2/SimpleSyncLogic#3"), "node_started"),
+                (Some("AsyncFlow#2/SimpleSyncLogic#3"), "shared_changed"),
+                (Some("AsyncFlow#2/SimpleSyncLogic#3"), "node_finished"),
+                (Some("AsyncFlow#2"), "node_finished"),
+                (None, "flow_finished"),
+            ]
+        );
+        assert_eq!(
+            events[2].kind,
+            FlowEventKind::SharedChanged {
+                updated: HashMap::from([("visited_async1".to_string(), json!(true))]),
+                removed: vec![],
+            }
+        );
+    }
+
+    /// Streams a chunk from its blocking `exec`, as a synchronous LLM node would.
+    #[derive(Clone)]
+    struct ChattyLogic;
+
+    impl NodeLogic for ChattyLogic {
+        fn exec(&self, _input: NodeValue) -> NodeValue {
+            events::emit(FlowEventKind::LlmChunk {
+                text: "hello".to_string(),
+            });
+            NodeValue::Null
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_share_the_telemetry_run_and_reach_sync_nodes() {
+        use crate::core::telemetry::MemoryTelemetry;
+        use std::sync::Mutex;
+
+        let flow = AsyncFlow::new(Executable::Sync(Node::new(ChattyLogic)));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let listener = {
+            let events = events.clone();
+            move |event: &FlowEvent| events.lock().unwrap().push(event.clone())
+        };
+        let telemetry = Arc::new(MemoryTelemetry::new());
+        flow.run_with_telemetry_and_listener(
+            &mut HashMap::new(),
+            Some(telemetry.clone()),
+            Arc::new(listener),
+        )
+        .await;
+
+        let events = events.lock().unwrap();
+        let chunk = events
+            .iter()
+            .find(|event| matches!(event.kind, FlowEventKind::LlmChunk { .. }))
+            .expect("the sync node's chunk is reported");
+        assert_eq!(chunk.node_id.as_deref(), Some("ChattyLogic#0"));
+
+        let traces = telemetry.get_traces();
+        assert!(!traces.is_empty());
+        assert!(traces.iter().all(|trace| trace.run_id == events[0].run_id));
+        assert!(events.iter().all(|event| event.run_id == events[0].run_id));
+    }
+
+    #[derive(Clone)]
+    struct ShoutLogic;
+
+    impl NodeLogic for ShoutLogic {
+        fn prep(
+            &self,
+            _params: &HashMap<String, NodeValue>,
+            shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            shared.get("text").cloned().unwrap_or(NodeValue::Null)
+        }
+
+        fn exec(&self, input: NodeValue) -> NodeValue {
+            json!(input.as_str().unwrap_or_default().to_uppercase())
+        }
+
+        fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            _prep_res: NodeValue,
+            exec_res: NodeValue,
+        ) -> Option<String> {
+            shared.insert("result".to_string(), exec_res);
+            None
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scoped_sub_flow_reused_without_collisions() {
+        let shout = AsyncFlow::new(Executable::Sync(Node::new(ShoutLogic)));
+        let first = shout
+            .clone()
+            .scoped(FlowScope::new().input("a", "text").output("result", "loud_a"));
+        let second = shout
+            .scoped(FlowScope::new().input("b", "text").output("result", "loud_b"));
+
+        let parent_start = match Executable::from(first) {
+            Async(node) => node.next(second.into()),
+            _ => unreachable!(),
+        };
+        let flow = AsyncFlow::new(Async(parent_start));
+
+        let mut shared = HashMap::new();
+        shared.insert("a".to_string(), json!("first"));
+        shared.insert("b".to_string(), json!("second"));
+        shared.insert("result".to_string(), json!("untouched"));
+
+        flow.run(&mut shared).await;
+
+        assert_eq!(shared.get("loud_a"), Some(&json!("FIRST")));
+        assert_eq!(shared.get("loud_b"), Some(&json!("SECOND")));
+        assert_eq!(shared.get("result"), Some(&json!("untouched")));
+        assert!(!shared.contains_key("text"));
+    }
 }
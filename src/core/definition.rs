@@ -0,0 +1,1143 @@
+//! Declarative flow definitions.
+//!
+//! A [`FlowDefinition`] describes nodes (by registered type name, with params, signature,
+//! instruction and model) and the action edges between them. A [`NodeRegistry`] maps type names
+//! to factories and turns the definition into a runnable, validated `AsyncFlow`.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "start": "summarize",
+//!   "inputs": ["document"],
+//!   "nodes": [
+//!     { "id": "summarize", "type": "llm", "signature": "document -> summary",
+//!       "instruction": "Summarize the document." },
+//!     { "id": "store", "type": "store", "params": { "table": "summaries" } }
+//!   ],
+//!   "edges": [ { "from": "summarize", "to": "store" } ]
+//! }
+//! ```
+//!
+//! Flows own their successors, so a run can't go back to a node it already left. Definitions
+//! with a cycle (an edge back to a node on the current path) are rejected, on the edge closing it.
+//! Several edges may still lead to the same node.
+
+use crate::core::async_impl::async_flow::AsyncFlow;
+use crate::core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
+use crate::core::export::short_type_name;
+use crate::core::semantic::signature::Signature;
+use crate::core::sync_impl::node::{Node, NodeLogic};
+use crate::core::sync_impl::NodeValue;
+use crate::core::validation::ValidationIssue;
+use crate::core::Executable;
+use serde::{Deserialize, Serialize};
+use std::any::TypeId;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// The current version of the definition format.
+pub const DEFINITION_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    DEFINITION_VERSION
+}
+
+fn default_action() -> String {
+    "default".to_string()
+}
+
+/// A whole flow, as stored in a JSON/YAML document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowDefinition {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    /// Id of the node the flow starts from.
+    pub start: String,
+    /// Keys expected in the initial shared state, used for validation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+    pub nodes: Vec<NodeDefinition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edges: Vec<EdgeDefinition>,
+}
+
+/// A single node of a [`FlowDefinition`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeDefinition {
+    /// Unique id of the node in the definition, used as `task_id` by semantic nodes.
+    pub id: String,
+    /// Name the node type was registered under in the [`NodeRegistry`].
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub params: HashMap<String, NodeValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<SignatureDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Seal the node once built (semantic nodes are always sealed).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub seal: bool,
+}
+
+/// Params are written in key order so that snapshots can be diffed.
+fn serialize_sorted<S: serde::Serializer>(
+    params: &HashMap<String, NodeValue>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(params.iter().collect::<std::collections::BTreeMap<_, _>>())
+}
+
+/// A signature, either in shorthand (`"a, b -> c"`) or with field descriptions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SignatureDefinition {
+    Shorthand(String),
+    Full(Signature),
+}
+
+impl SignatureDefinition {
+    /// Uses the shorthand form unless some field carries a description.
+    pub fn from_signature(signature: &Signature) -> Self {
+        let described = signature
+            .inputs
+            .iter()
+            .chain(&signature.outputs)
+            .any(|field| !field.description.is_empty());
+        if described {
+            SignatureDefinition::Full(signature.clone())
+        } else {
+            SignatureDefinition::Shorthand(signature.to_string())
+        }
+    }
+
+    pub fn to_signature(&self) -> Result<Signature, String> {
+        match self {
+            SignatureDefinition::Shorthand(s) => s.parse(),
+            SignatureDefinition::Full(signature) => Ok(signature.clone()),
+        }
+    }
+}
+
+impl FlowDefinition {
+    /// Serializes the definition as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("flow definitions are always serializable")
+    }
+
+    /// Serializes the definition as YAML.
+    #[cfg(feature = "yaml")]
+    pub fn to_yaml(&self) -> String {
+        serde_yaml_ng::to_string(self).expect("flow definitions are always serializable")
+    }
+}
+
+/// A transition: when `from` returns `action`, run `to`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EdgeDefinition {
+    pub from: String,
+    #[serde(default = "default_action")]
+    pub action: String,
+    pub to: String,
+}
+
+/// A 1-based position in the source document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A single problem found while building a definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefinitionIssue {
+    pub message: String,
+    /// Best-effort location of the offending node or edge in the source document.
+    pub position: Option<Position>,
+}
+
+impl std::fmt::Display for DefinitionIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.position {
+            Some(position) => write!(f, "{}: {}", position, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DefinitionError {
+    #[error("Failed to parse flow definition: {0}")]
+    Parse(DefinitionIssue),
+
+    #[error("Invalid flow definition:\n{}", .0.iter().map(|i| format!("  - {}", i)).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<DefinitionIssue>),
+}
+
+/// Builds an `Executable` out of a node definition.
+pub type NodeFactory = Arc<dyn Fn(&NodeDefinition) -> Result<Executable, String> + Send + Sync>;
+
+/// Maps node type names to the factories that instantiate them.
+///
+/// The registry remembers the type name each sealable node it builds was built under, by task id
+/// (a node built in code with the same task id is taken for it). Types registered
+/// through [`NodeRegistry::register_sync`], [`NodeRegistry::register_async`] and
+/// `register_semantic` are also remembered, so that flows made of them in code can be turned
+/// back into a definition with [`NodeRegistry::to_definition`], as long as each type has a
+/// single name.
+#[derive(Clone, Default)]
+pub struct NodeRegistry {
+    factories: HashMap<String, NodeFactory>,
+    type_names: HashMap<TypeId, Vec<String>>,
+    semantic_types: HashSet<TypeId>,
+    /// Type names of the nodes built from definitions, by task id.
+    built_types: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl NodeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a raw factory under `name`.
+    pub fn register<F>(&mut self, name: impl Into<String>, factory: F) -> &mut Self
+    where
+        F: Fn(&NodeDefinition) -> Result<Executable, String> + Send + Sync + 'static,
+    {
+        let name = name.into();
+        if self.factories.contains_key(&name) {
+            log::warn!("Node type '{}' was already registered, overwriting it.", name);
+        }
+        self.factories.insert(name, Arc::new(factory));
+        self
+    }
+
+    /// Remembers that nodes of type `T` are registered under `name`.
+    fn remember<T: 'static>(&mut self, name: &str) {
+        let names = self.type_names.entry(TypeId::of::<T>()).or_default();
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+
+    /// Registers a synchronous node type. The definition's params are also set on the node.
+    pub fn register_sync<L, F>(&mut self, name: impl Into<String>, factory: F) -> &mut Self
+    where
+        L: NodeLogic,
+        F: Fn(&NodeDefinition) -> Result<L, String> + Send + Sync + 'static,
+    {
+        let name = name.into();
+        self.remember::<L>(&name);
+        self.register(name, move |definition| {
+            let mut node = Node::new(factory(definition)?);
+            node.set_params(definition.params.clone());
+            Ok(Executable::Sync(node))
+        })
+    }
+
+    /// Registers an asynchronous node type. The definition's params are also set on the node.
+    pub fn register_async<L, F>(&mut self, name: impl Into<String>, factory: F) -> &mut Self
+    where
+        L: AsyncNodeLogic,
+        F: Fn(&NodeDefinition) -> Result<L, String> + Send + Sync + 'static,
+    {
+        let name = name.into();
+        self.remember::<L>(&name);
+        self.register(name, move |definition| {
+            let mut node = AsyncNode::new(factory(definition)?);
+            node.set_params(definition.params.clone());
+            Ok(Executable::Async(node))
+        })
+    }
+
+    /// Registers semantic LLM nodes bound to `client` under `name`.
+    ///
+    /// These nodes require a `signature` and an `instruction`, and use their id as task id.
+    #[cfg(feature = "llm")]
+    pub fn register_semantic<S>(
+        &mut self,
+        name: impl Into<String>,
+        client: crate::llm::Client<S>,
+    ) -> &mut Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let name = name.into();
+        self.remember::<crate::core::semantic::node::SemanticLLMLogic<S>>(&name);
+        self.semantic_types
+            .insert(TypeId::of::<crate::core::semantic::node::SemanticLLMLogic<S>>());
+        self.register(name, move |definition| {
+            let signature = definition
+                .signature
+                .as_ref()
+                .ok_or("Semantic nodes require a 'signature'")?
+                .to_signature()?;
+            let instruction = definition
+                .instruction
+                .clone()
+                .ok_or("Semantic nodes require an 'instruction'")?;
+
+            let mut builder = client
+                .semantic_node()
+                .signature(signature)
+                .instruction(instruction)
+                .task_id(definition.id.clone());
+            if let Some(model) = &definition.model {
+                builder = builder.model(model.clone());
+            }
+            Ok(builder.seal())
+        })
+    }
+
+    /// Parses a JSON definition and builds it.
+    pub fn load_json(&self, source: &str) -> Result<AsyncFlow, DefinitionError> {
+        let definition: FlowDefinition = serde_json::from_str(source).map_err(|e| {
+            DefinitionError::Parse(DefinitionIssue {
+                message: e.to_string(),
+                position: Some(Position {
+                    line: e.line(),
+                    column: e.column(),
+                }),
+            })
+        })?;
+        self.build_located(&definition, Some(source))
+    }
+
+    /// Parses a YAML definition and builds it.
+    #[cfg(feature = "yaml")]
+    pub fn load_yaml(&self, source: &str) -> Result<AsyncFlow, DefinitionError> {
+        let definition: FlowDefinition = serde_yaml_ng::from_str(source).map_err(|e| {
+            DefinitionError::Parse(DefinitionIssue {
+                message: e.to_string(),
+                position: e.location().map(|l| Position {
+                    line: l.line(),
+                    column: l.column(),
+                }),
+            })
+        })?;
+        self.build_located(&definition, Some(source))
+    }
+
+    /// Builds and validates an already parsed definition.
+    pub fn build(&self, definition: &FlowDefinition) -> Result<AsyncFlow, DefinitionError> {
+        self.build_located(definition, None)
+    }
+
+    /// Captures the topology, params, signatures, instructions and models of `flow`.
+    ///
+    /// Every node must come from a type registered in this registry; the others are reported
+    /// as issues, as are loops. A flow built from a definition keeps its declared inputs; otherwise they are
+    /// inferred from the signatures. Nodes are listed in depth-first order from the start node, and successors in
+    /// action order, so the output of an unchanged flow is stable.
+    pub fn to_definition(&self, flow: &AsyncFlow) -> Result<FlowDefinition, DefinitionError> {
+        let mut exporter = Exporter {
+            registry: self,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            ids: HashSet::new(),
+            path: Vec::new(),
+            issues: Vec::new(),
+        };
+        let start = exporter.export(&flow.start_executable());
+        if !exporter.issues.is_empty() {
+            return Err(DefinitionError::Invalid(exporter.issues));
+        }
+
+        let inputs = flow.declared_inputs().unwrap_or_else(|| {
+            // Initial inputs are whatever is consumed but never produced within the flow.
+            let mut consumed = BTreeSet::new();
+            let mut produced = HashSet::new();
+            for node in &exporter.nodes {
+                if let Some(signature) = node.signature.as_ref().and_then(|s| s.to_signature().ok()) {
+                    consumed.extend(signature.inputs.into_iter().map(|field| field.name));
+                    produced.extend(signature.outputs.into_iter().map(|field| field.name));
+                }
+            }
+            consumed.into_iter().filter(|key| !produced.contains(key)).collect()
+        });
+
+        Ok(FlowDefinition {
+            version: DEFINITION_VERSION,
+            start: start.expect("the start node was exported"),
+            inputs,
+            nodes: exporter.nodes,
+            edges: exporter.edges,
+        })
+    }
+
+    /// Snapshots `flow` as a JSON document.
+    pub fn save_json(&self, flow: &AsyncFlow) -> Result<String, DefinitionError> {
+        self.to_definition(flow).map(|definition| definition.to_json())
+    }
+
+    /// Snapshots `flow` as a YAML document.
+    #[cfg(feature = "yaml")]
+    pub fn save_yaml(&self, flow: &AsyncFlow) -> Result<String, DefinitionError> {
+        self.to_definition(flow).map(|definition| definition.to_yaml())
+    }
+
+    fn build_located(
+        &self,
+        definition: &FlowDefinition,
+        source: Option<&str>,
+    ) -> Result<AsyncFlow, DefinitionError> {
+        let locate = |key: &str, value: &str| source.and_then(|s| locate(s, key, value));
+        let mut issues = Vec::new();
+
+        if definition.version > DEFINITION_VERSION {
+            issues.push(DefinitionIssue {
+                message: format!(
+                    "Unsupported definition version {} (latest is {}).",
+                    definition.version, DEFINITION_VERSION
+                ),
+                position: locate("version", &definition.version.to_string()),
+            });
+        }
+
+        let mut nodes = HashMap::new();
+        for node in &definition.nodes {
+            if nodes.insert(node.id.as_str(), node).is_some() {
+                issues.push(DefinitionIssue {
+                    message: format!("Duplicate node id '{}'.", node.id),
+                    position: locate("id", &node.id),
+                });
+            }
+            if !self.factories.contains_key(&node.node_type) {
+                issues.push(DefinitionIssue {
+                    message: format!(
+                        "Node '{}' has unknown type '{}'.",
+                        node.id, node.node_type
+                    ),
+                    position: locate("id", &node.id),
+                });
+            }
+        }
+
+        if !nodes.contains_key(definition.start.as_str()) {
+            issues.push(DefinitionIssue {
+                message: format!("Start node '{}' is not defined.", definition.start),
+                position: locate("start", &definition.start),
+            });
+        }
+
+        let mut successors: HashMap<&str, Vec<&EdgeDefinition>> = HashMap::new();
+        for edge in &definition.edges {
+            for end in [&edge.from, &edge.to] {
+                if !nodes.contains_key(end.as_str()) {
+                    issues.push(DefinitionIssue {
+                        message: format!(
+                            "Edge '{}' --{}--> '{}' references unknown node '{}'.",
+                            edge.from, edge.action, edge.to, end
+                        ),
+                        position: locate(if end == &edge.from { "from" } else { "to" }, end),
+                    });
+                }
+            }
+            successors.entry(edge.from.as_str()).or_default().push(edge);
+        }
+
+        if !issues.is_empty() {
+            return Err(DefinitionError::Invalid(issues));
+        }
+
+        let mut builder = Builder {
+            registry: self,
+            nodes: &nodes,
+            successors: &successors,
+            built: HashMap::new(),
+            failed: HashSet::new(),
+            path: Vec::new(),
+            issues: Vec::new(),
+            locate: &locate,
+        };
+        let start = builder.build(&definition.start);
+        // Unreachable nodes are still built so that their mistakes get reported too.
+        for node in &definition.nodes {
+            builder.build(&node.id);
+        }
+        let mut issues = builder.issues;
+
+        let Some(start) = start else {
+            return Err(DefinitionError::Invalid(issues));
+        };
+        if !issues.is_empty() {
+            return Err(DefinitionError::Invalid(issues));
+        }
+
+        let mut reachable = HashSet::from([definition.start.as_str()]);
+        let mut pending = vec![definition.start.as_str()];
+        while let Some(id) = pending.pop() {
+            for edge in successors.get(id).into_iter().flatten() {
+                if reachable.insert(edge.to.as_str()) {
+                    pending.push(edge.to.as_str());
+                }
+            }
+        }
+        for node in &definition.nodes {
+            if !reachable.contains(node.id.as_str()) {
+                log::warn!(
+                    "Node '{}' is not reachable from start node '{}'.",
+                    node.id,
+                    definition.start
+                );
+            }
+        }
+
+        let mut flow = AsyncFlow::new(start);
+        flow.declare_inputs(definition.inputs.clone());
+        let validation = flow.validate(definition.inputs.clone());
+        for issue in &validation.issues {
+            match issue {
+                ValidationIssue::Error(message) => issues.push(DefinitionIssue {
+                    message: message.clone(),
+                    position: quoted_node(message)
+                        .filter(|id| nodes.contains_key(id))
+                        .and_then(|id| locate("id", id)),
+                }),
+                ValidationIssue::Warning(message) => log::warn!("{}", message),
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(flow)
+        } else {
+            Err(DefinitionError::Invalid(issues))
+        }
+    }
+}
+
+/// Instantiates nodes depth-first, attaching successors before a node is handed to its parent.
+struct Builder<'a> {
+    registry: &'a NodeRegistry,
+    nodes: &'a HashMap<&'a str, &'a NodeDefinition>,
+    successors: &'a HashMap<&'a str, Vec<&'a EdgeDefinition>>,
+    built: HashMap<String, Executable>,
+    /// Nodes that could not be built, already reported.
+    failed: HashSet<String>,
+    path: Vec<String>,
+    issues: Vec<DefinitionIssue>,
+    locate: &'a dyn Fn(&str, &str) -> Option<Position>,
+}
+
+impl Builder<'_> {
+    fn build(&mut self, id: &str) -> Option<Executable> {
+        if let Some(built) = self.built.get(id) {
+            return Some(built.clone());
+        }
+        if self.failed.contains(id) {
+            return None;
+        }
+        self.path.push(id.to_string());
+        let executable = self.instantiate(id).and_then(|executable| self.attach_successors(id, executable));
+        self.path.pop();
+
+        match &executable {
+            Some(executable) => {
+                self.built.insert(id.to_string(), executable.clone());
+            }
+            None => {
+                self.failed.insert(id.to_string());
+            }
+        }
+        executable
+    }
+
+    /// Runs the factory of `id`, sealing the node if its definition asks for it.
+    fn instantiate(&mut self, id: &str) -> Option<Executable> {
+        let definition = self.nodes[id];
+        let factory = &self.registry.factories[&definition.node_type];
+        let executable = match factory(definition) {
+            Ok(executable) => {
+                if let Some(task_id) = executable.task_id() {
+                    let mut built_types = self.registry.built_types.lock().unwrap();
+                    built_types.insert(task_id, definition.node_type.clone());
+                }
+                executable
+            }
+            Err(message) => {
+                self.issues.push(DefinitionIssue {
+                    message: format!("Node '{}' could not be built: {}", id, message),
+                    position: (self.locate)("id", id),
+                });
+                return None;
+            }
+        };
+        if !definition.seal {
+            return Some(executable);
+        }
+        match executable {
+            Executable::Sync(node) => node.seal().map(Executable::Sealed),
+            Executable::Async(node) => node.seal().map(Executable::Sealed),
+            sealed @ Executable::Sealed(_) => Ok(sealed),
+        }
+        .map_err(|message| {
+            self.issues.push(DefinitionIssue {
+                message: format!("Node '{}' could not be sealed: {}", id, message),
+                position: (self.locate)("id", id),
+            });
+        })
+        .ok()
+    }
+
+    fn attach_successors(&mut self, id: &str, mut executable: Executable) -> Option<Executable> {
+        let edges = self.successors.get(id).cloned().unwrap_or_default();
+        for edge in edges {
+            if self.path.contains(&edge.to) {
+                self.issues.push(DefinitionIssue {
+                    message: format!(
+                        "Edge '{}' --{}--> '{}' closes a cycle; flows can't return to an earlier node.",
+                        edge.from, edge.action, edge.to
+                    ),
+                    position: (self.locate)("to", &edge.to),
+                });
+                return None;
+            }
+            let successor = self.build(&edge.to)?;
+            executable = match attach(executable, &edge.action, successor) {
+                Ok(executable) => executable,
+                Err(message) => {
+                    self.issues.push(DefinitionIssue {
+                        message: format!("Node '{}': {}", id, message),
+                        position: (self.locate)("from", id),
+                    });
+                    return None;
+                }
+            };
+        }
+        Some(executable)
+    }
+}
+
+/// Walks a flow depth-first and records the definition of every node.
+struct Exporter<'a> {
+    registry: &'a NodeRegistry,
+    nodes: Vec<NodeDefinition>,
+    edges: Vec<EdgeDefinition>,
+    ids: HashSet<String>,
+    /// Ids of the nodes being exported, from the start node down.
+    path: Vec<String>,
+    issues: Vec<DefinitionIssue>,
+}
+
+impl Exporter<'_> {
+    /// Returns the id the executable was exported under.
+    fn export(&mut self, executable: &Executable) -> Option<String> {
+        let task_id = executable.task_id();
+        if let Some(task_id) = &task_id
+            && self.ids.contains(task_id)
+        {
+            // A reused task id is an edge to the node exported earlier, unless it loops back.
+            if self.path.contains(task_id) {
+                self.issues.push(DefinitionIssue {
+                    message: format!(
+                        "Node '{}' loops back to itself; definitions can't express loops.",
+                        task_id
+                    ),
+                    position: None,
+                });
+            }
+            return Some(task_id.clone());
+        }
+
+        let mut sealed = false;
+        let mut inner = executable;
+        while let Executable::Sealed(node) = inner {
+            sealed = true;
+            inner = &node.inner;
+        }
+        let (type_id, type_name, data, signature, instruction, model) = match inner {
+            Executable::Sync(node) => {
+                let behaviour: &dyn NodeLogic = &*node.behaviour;
+                let promptable = behaviour.as_promptable();
+                (
+                    behaviour.as_any().type_id(),
+                    behaviour.type_name(),
+                    &node.data,
+                    behaviour.as_sealable().map(|s| s.signature()),
+                    promptable.and_then(|p| p.instruction().map(str::to_string)),
+                    promptable.and_then(|p| p.model().map(str::to_string)),
+                )
+            }
+            Executable::Async(node) => {
+                let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+                let promptable = behaviour.as_promptable();
+                (
+                    behaviour.as_any().type_id(),
+                    behaviour.type_name(),
+                    &node.data,
+                    behaviour.as_sealable().map(|s| s.signature()),
+                    promptable.and_then(|p| p.instruction().map(str::to_string)),
+                    promptable.and_then(|p| p.model().map(str::to_string)),
+                )
+            }
+            Executable::Sealed(_) => unreachable!("sealed nodes were unwrapped above"),
+        };
+
+        // The type the node was built under, or the only name its logic is registered under.
+        let names = self.registry.type_names.get(&type_id).map(Vec::as_slice).unwrap_or_default();
+        let built_type = task_id
+            .as_ref()
+            .and_then(|task_id| self.registry.built_types.lock().unwrap().get(task_id).cloned())
+            .filter(|name| self.registry.factories.contains_key(name));
+        let node_type = built_type.as_ref().or(match names {
+            [name] => Some(name),
+            _ => None,
+        });
+        let id = match node_type {
+            Some(node_type) => {
+                let id = task_id.unwrap_or_else(|| {
+                    (1..)
+                        .map(|n| format!("{}_{}", node_type, n))
+                        .find(|id| !self.ids.contains(id))
+                        .expect("some id is free")
+                });
+                self.ids.insert(id.clone());
+                self.nodes.push(NodeDefinition {
+                    id: id.clone(),
+                    node_type: node_type.clone(),
+                    params: data.params.clone(),
+                    signature: signature
+                        .as_ref()
+                        .filter(|s| !s.inputs.is_empty() || !s.outputs.is_empty())
+                        .map(SignatureDefinition::from_signature),
+                    instruction,
+                    model,
+                    seal: sealed && !self.registry.semantic_types.contains(&type_id),
+                });
+                Some(id)
+            }
+            None if names.len() > 1 => {
+                self.issues.push(DefinitionIssue {
+                    message: format!(
+                        "Node '{}' of type '{}' is registered under several names ({}); build it from a definition to serialize it.",
+                        task_id.as_deref().unwrap_or("<unnamed>"),
+                        short_type_name(type_name),
+                        names.join(", ")
+                    ),
+                    position: None,
+                });
+                None
+            }
+            None => {
+                self.issues.push(DefinitionIssue {
+                    message: format!(
+                        "Node '{}' of type '{}' was not created from a registered type and can't be serialized.",
+                        task_id.as_deref().unwrap_or("<unnamed>"),
+                        short_type_name(type_name)
+                    ),
+                    position: None,
+                });
+                None
+            }
+        };
+
+        let mut successors: Vec<_> = executable.successors().iter().collect();
+        successors.sort_by(|a, b| a.0.cmp(b.0));
+        self.path.extend(id.clone());
+        for (action, successor) in successors {
+            let to = self.export(successor);
+            if let (Some(from), Some(to)) = (&id, to) {
+                self.edges.push(EdgeDefinition {
+                    from: from.clone(),
+                    action: action.clone(),
+                    to,
+                });
+            }
+        }
+        if id.is_some() {
+            self.path.pop();
+        }
+        id
+    }
+}
+
+/// Adds `successor` under `action`, reaching into freshly sealed nodes if needed.
+fn attach(executable: Executable, action: &str, successor: Executable) -> Result<Executable, String> {
+    match executable {
+        Executable::Sync(node) => Ok(Executable::Sync(node.next_on(action, successor))),
+        Executable::Async(node) => Ok(Executable::Async(node.next_on(action, successor))),
+        Executable::Sealed(sealed) => match Arc::try_unwrap(sealed) {
+            // Successors are not part of a sealed node's identity, so this is safe.
+            Ok(mut sealed) => {
+                sealed.inner = attach(sealed.inner, action, successor)?;
+                Ok(Executable::Sealed(Arc::new(sealed)))
+            }
+            Err(_) => Err("sealed node is shared and can't be given successors".to_string()),
+        },
+    }
+}
+
+/// Extracts `x` from messages like "Node 'x' requires ...".
+fn quoted_node(message: &str) -> Option<&str> {
+    let start = message.find('\'')? + 1;
+    let end = start + message[start..].find('\'')?;
+    Some(&message[start..end])
+}
+
+/// Best-effort lookup of the first `key: value` / `"key": "value"` pair in the source.
+fn locate(source: &str, key: &str, value: &str) -> Option<Position> {
+    let quoted_key = format!("\"{}\"", key);
+    let quoted_value = format!("\"{}\"", value);
+    for (i, line) in source.lines().enumerate() {
+        let mut offset = 0;
+        while let Some(found) = line[offset..]
+            .find(&quoted_key)
+            .or_else(|| line[offset..].find(&format!("{}:", key)))
+        {
+            let key_start = offset + found;
+            let rest = line[key_start..]
+                .split_once(':')
+                .map(|(_, rest)| rest.trim_start())
+                .unwrap_or_default();
+            let matches = rest.starts_with(&quoted_value)
+                || rest
+                    .strip_prefix(value)
+                    .is_some_and(|tail| tail.is_empty() || tail.starts_with([',', ' ', '}']));
+            if matches {
+                return Some(Position {
+                    line: i + 1,
+                    column: key_start + 1,
+                });
+            }
+            offset = key_start + 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::semantic::Sealable;
+    use serde_json::json;
+
+    #[derive(Clone)]
+    struct SetKey {
+        key: String,
+        signature: Signature,
+        task_id: String,
+    }
+
+    impl NodeLogic for SetKey {
+        fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            _prep_res: NodeValue,
+            _exec_res: NodeValue,
+        ) -> Option<String> {
+            shared.insert(self.key.clone(), json!(true));
+            None
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+
+        fn as_sealable(&self) -> Option<&dyn Sealable> {
+            Some(self)
+        }
+    }
+
+    impl Sealable for SetKey {
+        fn signature(&self) -> Signature {
+            self.signature.clone()
+        }
+
+        fn task_id(&self) -> String {
+            self.task_id.clone()
+        }
+    }
+
+    fn registry() -> NodeRegistry {
+        let mut registry = NodeRegistry::new();
+        registry.register_sync("set", |definition| {
+            Ok(SetKey {
+                key: definition
+                    .params
+                    .get("key")
+                    .and_then(|v| v.as_str())
+                    .ok_or("missing 'key' param")?
+                    .to_string(),
+                signature: match &definition.signature {
+                    Some(signature) => signature.to_signature()?,
+                    None => Signature::new(),
+                },
+                task_id: definition.id.clone(),
+            })
+        });
+        registry
+    }
+
+    #[tokio::test]
+    async fn test_load_and_run_json() {
+        let source = r#"{
+  "start": "first",
+  "inputs": ["seed"],
+  "nodes": [
+    { "id": "first", "type": "set", "params": { "key": "a" }, "signature": "seed -> a" },
+    { "id": "second", "type": "set", "params": { "key": "b" }, "signature": "a -> b", "seal": true }
+  ],
+  "edges": [ { "from": "first", "to": "second" } ]
+}"#;
+        let flow = registry().load_json(source).unwrap();
+        let mut shared = HashMap::new();
+        flow.run(&mut shared).await;
+        assert_eq!(shared.get("a"), Some(&json!(true)));
+        assert_eq!(shared.get("b"), Some(&json!(true)));
+    }
+
+    #[test]
+    fn test_errors_carry_positions() {
+        let source = r#"{
+  "start": "first",
+  "nodes": [
+    { "id": "first", "type": "set", "params": { "key": "a" }, "signature": "seed -> a" },
+    { "id": "second", "type": "nope" }
+  ]
+}"#;
+        let Err(DefinitionError::Invalid(issues)) = registry().load_json(source) else {
+            panic!("expected an invalid definition");
+        };
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("unknown type 'nope'"));
+        assert_eq!(issues[0].position, Some(Position { line: 5, column: 7 }));
+
+        // Validation errors are located on the node requiring the missing key.
+        let source = source.replace(r#""type": "nope""#, r#""type": "set", "params": { "key": "b" }"#);
+        let Err(DefinitionError::Invalid(issues)) = registry().load_json(&source) else {
+            panic!("expected an invalid definition");
+        };
+        assert!(issues[0].message.contains("requires input 'seed'"));
+        assert_eq!(issues[0].position.map(|p| p.line), Some(4));
+
+        let Err(DefinitionError::Parse(issue)) = registry().load_json("{ \"start\": ") else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(issue.position.map(|p| p.line), Some(1));
+    }
+
+    fn set_node(id: &str, params: HashMap<String, NodeValue>) -> NodeDefinition {
+        NodeDefinition {
+            id: id.to_string(),
+            node_type: "set".to_string(),
+            params,
+            signature: None,
+            instruction: None,
+            model: None,
+            seal: false,
+        }
+    }
+
+    fn edge(from: &str, action: &str, to: &str) -> EdgeDefinition {
+        EdgeDefinition { from: from.into(), action: action.into(), to: to.into() }
+    }
+
+    #[test]
+    fn test_cycles_are_rejected_on_the_closing_edge() {
+        let source = r#"{
+  "start": "a",
+  "nodes": [
+    { "id": "a", "type": "set", "params": { "key": "a" } },
+    { "id": "b", "type": "set", "params": { "key": "b" } },
+    { "id": "c", "type": "set", "params": { "key": "c" } }
+  ],
+  "edges": [
+    { "from": "a", "to": "b" },
+    { "from": "a", "action": "skip", "to": "c" },
+    { "from": "c", "to": "b" },
+    { "from": "b", "action": "retry", "to": "a" }
+  ]
+}"#;
+        let registry = registry();
+        let Err(DefinitionError::Invalid(issues)) = registry.load_json(source) else {
+            panic!("expected the cycle to be rejected");
+        };
+        assert_eq!(
+            issues,
+            vec![DefinitionIssue {
+                message: "Edge 'b' --retry--> 'a' closes a cycle; flows can't return to an earlier node."
+                    .to_string(),
+                position: Some(Position { line: 12, column: 39 }),
+            }]
+        );
+
+        // Two edges into "b" are fine without the retry.
+        let source = source.replace(",\n    { \"from\": \"b\", \"action\": \"retry\", \"to\": \"a\" }", "");
+        let flow = registry.load_json(&source).unwrap();
+        assert!(flow.graph().cycles().is_empty());
+
+        // A loop built in code can't be written out either.
+        let node = |id: &str| {
+            Node::new(SetKey { key: id.to_string(), signature: Signature::new(), task_id: id.to_string() })
+        };
+        let flow = AsyncFlow::new(Executable::Sync(
+            node("a").next(Executable::Sync(node("b").next_on("retry", Executable::Sync(node("a"))))),
+        ));
+        let Err(DefinitionError::Invalid(issues)) = registry.to_definition(&flow) else {
+            panic!("expected the loop to be reported");
+        };
+        assert!(issues[0].message.contains("Node 'a' loops back"));
+    }
+
+    #[test]
+    fn test_failed_nodes_are_reported_once() {
+        // "b" has no key, so its factory fails; "a" and "c" can't be built without it.
+        let definition = FlowDefinition {
+            version: DEFINITION_VERSION,
+            start: "a".to_string(),
+            inputs: vec![],
+            nodes: vec![
+                set_node("a", HashMap::from([("key".to_string(), json!("a"))])),
+                set_node("b", HashMap::new()),
+                set_node("c", HashMap::from([("key".to_string(), json!("c"))])),
+            ],
+            edges: vec![edge("a", "default", "b"), edge("b", "retry", "a"), edge("c", "default", "b")],
+        };
+        let Err(DefinitionError::Invalid(issues)) = registry().build(&definition) else {
+            panic!("expected the factory error to be reported");
+        };
+        assert_eq!(
+            issues,
+            vec![DefinitionIssue {
+                message: "Node 'b' could not be built: missing 'key' param".to_string(),
+                position: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_definition_round_trip() {
+        let source = r#"{
+  "start": "first",
+  "inputs": ["seed"],
+  "nodes": [
+    { "id": "first", "type": "set", "params": { "key": "a" }, "signature": "seed -> a" },
+    { "id": "second", "type": "set", "params": { "key": "b" }, "signature": "a -> b", "seal": true },
+    { "id": "third", "type": "set", "params": { "key": "c" }, "signature": "a -> c" }
+  ],
+  "edges": [
+    { "from": "first", "action": "skip", "to": "third" },
+    { "from": "first", "to": "second" }
+  ]
+}"#;
+        let registry = registry();
+        let flow = registry.load_json(source).unwrap();
+        let mut expected: FlowDefinition = serde_json::from_str(source).unwrap();
+        // Successors come out in action order.
+        expected.edges.reverse();
+
+        let snapshot = registry.to_definition(&flow).unwrap();
+        assert_eq!(snapshot, expected);
+
+        let json = snapshot.to_json();
+        let reloaded = registry.load_json(&json).unwrap();
+        assert_eq!(registry.save_json(&reloaded).unwrap(), json);
+    }
+
+    #[test]
+    fn test_aliased_types_round_trip_exactly() {
+        let mut registry = registry();
+        registry.register_sync("flag", |definition| {
+            Ok(SetKey {
+                key: "flag".to_string(),
+                signature: Signature::new(),
+                task_id: definition.id.clone(),
+            })
+        });
+        // "extra" is declared but no node reads it, so it would not be inferred.
+        let source = r#"{
+  "start": "first",
+  "inputs": ["seed", "extra"],
+  "nodes": [
+    { "id": "first", "type": "set", "params": { "key": "a" }, "signature": "seed -> a" },
+    { "id": "second", "type": "flag" }
+  ],
+  "edges": [ { "from": "first", "to": "second" } ]
+}"#;
+        let definition: FlowDefinition = serde_json::from_str(source).unwrap();
+        let flow = registry.build(&definition).unwrap();
+        assert_eq!(registry.to_definition(&flow).unwrap(), definition);
+
+        // Built in code, a node of a type with two names can't be told apart.
+        let flow = AsyncFlow::new(Executable::Sync(Node::new(SetKey {
+            key: "a".to_string(),
+            signature: Signature::new(),
+            task_id: "coded".to_string(),
+        })));
+        let Err(DefinitionError::Invalid(issues)) = registry.to_definition(&flow) else {
+            panic!("expected the ambiguous node to be reported");
+        };
+        assert!(issues[0].message.contains("registered under several names (set, flag)"));
+    }
+
+    #[test]
+    fn test_unregistered_nodes_are_reported() {
+        #[derive(Clone)]
+        struct Opaque;
+
+        impl NodeLogic for Opaque {
+            fn clone_box(&self) -> Box<dyn NodeLogic> {
+                Box::new(self.clone())
+            }
+        }
+
+        let flow = AsyncFlow::new(Executable::Sync(Node::new(Opaque)));
+        let Err(DefinitionError::Invalid(issues)) = registry().to_definition(&flow) else {
+            panic!("expected the opaque node to be reported");
+        };
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("type 'Opaque'"));
+    }
+
+    #[cfg(feature = "llm")]
+    #[test]
+    fn test_semantic_nodes_round_trip() {
+        let mut registry = registry();
+        registry.register_semantic("llm", crate::llm::Client::new());
+        let source = r#"{
+  "start": "summarize",
+  "inputs": ["document"],
+  "nodes": [
+    { "id": "summarize", "type": "llm", "signature": "document -> summary",
+      "instruction": "Summarize the document.", "model": "phi4" }
+  ]
+}"#;
+        let flow = registry.load_json(source).unwrap();
+        let snapshot = registry.to_definition(&flow).unwrap();
+        assert_eq!(snapshot, serde_json::from_str(source).unwrap());
+        assert!(!snapshot.nodes[0].seal);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[tokio::test]
+    async fn test_load_yaml() {
+        let source = "
+start: first
+nodes:
+  - id: first
+    type: set
+    params:
+      key: a
+";
+        let flow = registry().load_yaml(source).unwrap();
+        let mut shared = HashMap::new();
+        flow.run(&mut shared).await;
+        assert_eq!(shared.get("a"), Some(&json!(true)));
+    }
+}
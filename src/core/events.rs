@@ -0,0 +1,197 @@
+//! Progress events emitted while an `AsyncFlow` runs.
+//!
+//! Listeners are attached to a run through [`FlowListener`], or consumed as a stream with
+//! `AsyncFlow::run_streaming`. The context of the current run travels with the task, so nested
+//! flows and semantic nodes report to the same listener without any extra wiring.
+//!
+//! Nodes are reported under their [`FlowGraph`] ids, so events can be matched with the nodes of
+//! the flow's diagrams.
+
+use crate::core::async_impl::async_node::AsyncNodeLogic;
+use crate::core::async_impl::async_flow::AsyncFlowLogic;
+use crate::core::graph::{display_name, FlowGraph};
+use crate::core::sync_impl::node::NodeLogic;
+use crate::core::sync_impl::NodeValue;
+use crate::core::Executable;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// A single progress event of a flow run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlowEvent {
+    /// Identifies the top-level run the event belongs to.
+    pub run_id: String,
+    /// The id of the node in the flow's [`FlowGraph`]. `None` for flow-level events.
+    pub node_id: Option<String>,
+    #[serde(flatten)]
+    pub kind: FlowEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FlowEventKind {
+    FlowStarted,
+    NodeStarted,
+    /// The node finished and returned `action`.
+    NodeFinished { action: String },
+    /// Shared keys written or removed by the node that just ran. Nested async flows don't report
+    /// the changes their members already reported.
+    SharedChanged {
+        updated: HashMap<String, NodeValue>,
+        removed: Vec<String>,
+    },
+    /// Text produced by a language model. Providers are not streamed yet, so a whole
+    /// completion currently arrives as a single chunk.
+    LlmChunk { text: String },
+    Error { message: String },
+    /// The flow finished and returned `action`.
+    FlowFinished { action: Option<String> },
+}
+
+/// Receives the events of a flow run. Implemented for any `Fn(&FlowEvent)`.
+pub trait FlowListener: Send + Sync {
+    fn on_event(&self, event: &FlowEvent);
+}
+
+impl<F> FlowListener for F
+where
+    F: Fn(&FlowEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &FlowEvent) {
+        self(event)
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: EventContext;
+}
+
+/// The listener of the current run, and the node being executed.
+#[derive(Clone)]
+pub(crate) struct EventContext {
+    listener: Arc<dyn FlowListener>,
+    run_id: String,
+    /// The graph of the flow being run, which node ids are taken from.
+    graph: Arc<FlowGraph>,
+    node_id: Option<String>,
+}
+
+impl EventContext {
+    pub(crate) fn new(listener: Arc<dyn FlowListener>, graph: FlowGraph) -> Self {
+        Self {
+            listener,
+            run_id: uuid::Uuid::new_v4().simple().to_string(),
+            graph: Arc::new(graph),
+            node_id: None,
+        }
+    }
+
+    /// The graph id of the first node run by the flow this context was entered for: the root of
+    /// the graph at the top level, the start of the nested flow being run otherwise.
+    pub(crate) fn start_id(&self) -> Option<String> {
+        match &self.node_id {
+            None => Some(self.graph.root().id.clone()),
+            Some(flow_id) => self.graph.node(flow_id)?.nested_start.clone(),
+        }
+    }
+
+    /// The graph id of the node `node_id` hands over to on `action`.
+    pub(crate) fn next_id(&self, node_id: &str, action: &str) -> Option<String> {
+        self.graph
+            .successors(node_id)
+            .find(|edge| edge.action == action)
+            .map(|edge| edge.to.clone())
+    }
+
+    /// The context of the current task, if a listener is attached to it.
+    pub(crate) fn current() -> Option<Self> {
+        CONTEXT.try_with(Clone::clone).ok()
+    }
+
+    pub(crate) fn for_node(&self, node_id: String) -> Self {
+        Self {
+            node_id: Some(node_id),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn emit(&self, kind: FlowEventKind) {
+        self.listener.on_event(&FlowEvent {
+            run_id: self.run_id.clone(),
+            node_id: self.node_id.clone(),
+            kind,
+        });
+    }
+
+    /// Runs `future` with this context attached to the task.
+    pub(crate) async fn scope<F: Future>(self, future: F) -> F::Output {
+        CONTEXT.scope(self, future).await
+    }
+
+    /// Runs `f` with this context attached, for code that leaves the task, such as the
+    /// closures given to `spawn_blocking`.
+    pub(crate) fn sync_scope<R>(self, f: impl FnOnce() -> R) -> R {
+        CONTEXT.sync_scope(self, f)
+    }
+
+    /// Reports the keys `after` differs from `before` in, if any.
+    pub(crate) fn emit_changes(
+        &self,
+        before: &HashMap<String, NodeValue>,
+        after: &HashMap<String, NodeValue>,
+    ) {
+        let updated: HashMap<String, NodeValue> = after
+            .iter()
+            .filter(|(key, value)| before.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let mut removed: Vec<String> = before
+            .keys()
+            .filter(|key| !after.contains_key(*key))
+            .cloned()
+            .collect();
+        removed.sort();
+        if !updated.is_empty() || !removed.is_empty() {
+            self.emit(FlowEventKind::SharedChanged { updated, removed });
+        }
+    }
+}
+
+/// The id of the run being listened to, which its telemetry spans reuse.
+pub(crate) fn run_id() -> Option<String> {
+    CONTEXT.try_with(|context| context.run_id.clone()).ok()
+}
+
+/// Emits `kind` on behalf of the node currently running, if anyone is listening.
+pub(crate) fn emit(kind: FlowEventKind) {
+    let _ = CONTEXT.try_with(|context| context.emit(kind));
+}
+
+/// Whether `executable` is a nested async flow, whose members report their own events.
+pub(crate) fn is_async_flow(executable: &Executable) -> bool {
+    match executable {
+        Executable::Sync(_) => false,
+        Executable::Async(node) => {
+            let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+            behaviour.as_any().downcast_ref::<AsyncFlowLogic>().is_some()
+        }
+        Executable::Sealed(sealed) => is_async_flow(sealed.inner()),
+    }
+}
+
+/// The id events of `executable` are reported under when it cannot be found in the graph.
+pub(crate) fn node_id(executable: &Executable) -> String {
+    executable.task_id().unwrap_or_else(|| match executable {
+        Executable::Sync(node) => {
+            let behaviour: &dyn NodeLogic = &*node.behaviour;
+            display_name(behaviour.type_name())
+        }
+        Executable::Async(node) => {
+            let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+            display_name(behaviour.type_name())
+        }
+        Executable::Sealed(sealed) => sealed.task_id().to_string(),
+    })
+}
@@ -0,0 +1,259 @@
+//! Diagram export of flow graphs, in Graphviz DOT and Mermaid flavours.
+//!
+//! Rendering is done from a [`FlowGraph`], so a successor reusing a task id is drawn as an edge
+//! back to the existing node (that's how loops are expressed). Nested flows are drawn as subgraphs.
+
+use crate::core::async_impl::async_flow::AsyncFlowLogic;
+use crate::core::async_impl::async_node::AsyncNodeLogic;
+use crate::core::graph::{FlowGraph, GraphNode, NodeKind};
+use crate::core::sync_impl::flow::FlowLogic;
+use crate::core::sync_impl::node::NodeLogic;
+use crate::core::Executable;
+use std::collections::HashMap;
+
+impl Executable {
+    /// Renders the graph reachable from this executable as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        FlowGraph::from_executable(self).to_dot()
+    }
+
+    /// Renders the graph reachable from this executable as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        FlowGraph::from_executable(self).to_mermaid()
+    }
+
+    /// Returns the start of the flow wrapped by this executable, if it is one.
+    pub(crate) fn nested_start(&self) -> Option<Executable> {
+        match self {
+            Executable::Sync(node) => {
+                let behaviour: &dyn NodeLogic = &*node.behaviour;
+                behaviour
+                    .as_any()
+                    .downcast_ref::<FlowLogic>()
+                    .map(|logic| Executable::Sync(logic.start.clone()))
+            }
+            Executable::Async(node) => {
+                let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+                behaviour
+                    .as_any()
+                    .downcast_ref::<AsyncFlowLogic>()
+                    .map(|logic| logic.start.clone())
+            }
+            Executable::Sealed(sealed) => sealed.inner().nested_start(),
+        }
+    }
+}
+
+/// Strips module paths and generic parameters from a `std::any::type_name`.
+pub(crate) fn short_type_name(full: &str) -> &str {
+    let base = full.split('<').next().unwrap_or(full);
+    base.rsplit("::").next().unwrap_or(base)
+}
+
+impl FlowGraph {
+    /// Renders the graph as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        let ids = self.diagram_ids();
+        let mut out = String::from("digraph flow {\n    compound=true;\n    node [shape=box];\n");
+        for node in self.children(None) {
+            self.write_dot_node(&mut out, node, &ids, 1);
+        }
+        for edge in self.edges() {
+            let mut attrs = vec![format!("label=\"{}\"", dot_escape(&edge.action))];
+            // DOT can't point edges at clusters, so nested flows are anchored on their start.
+            let from = match self.node(&edge.from).and_then(|n| n.nested_start.as_ref()) {
+                Some(start) => {
+                    attrs.push(format!("ltail=cluster_{}", ids[&edge.from]));
+                    &ids[start]
+                }
+                None => &ids[&edge.from],
+            };
+            let to = match self.node(&edge.to).and_then(|n| n.nested_start.as_ref()) {
+                Some(start) => {
+                    attrs.push(format!("lhead=cluster_{}", ids[&edge.to]));
+                    &ids[start]
+                }
+                None => &ids[&edge.to],
+            };
+            out.push_str(&format!("    {} -> {} [{}];\n", from, to, attrs.join(", ")));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        let ids = self.diagram_ids();
+        let mut out = String::from("flowchart TD\n");
+        for node in self.children(None) {
+            self.write_mermaid_node(&mut out, node, &ids, 1);
+        }
+        for edge in self.edges() {
+            out.push_str(&format!(
+                "    {} -->|{}| {}\n",
+                ids[&edge.from],
+                mermaid_escape(&edge.action),
+                ids[&edge.to]
+            ));
+        }
+        out
+    }
+
+    /// Task ids are free-form, diagrams get positional identifiers instead.
+    fn diagram_ids(&self) -> HashMap<String, String> {
+        self.nodes()
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.clone(), format!("n{}", i)))
+            .collect()
+    }
+
+    fn write_dot_node(
+        &self,
+        out: &mut String,
+        node: &GraphNode,
+        ids: &HashMap<String, String>,
+        depth: usize,
+    ) {
+        let indent = "    ".repeat(depth);
+        let label = node
+            .label_lines()
+            .iter()
+            .map(|line| dot_escape(line))
+            .collect::<Vec<_>>()
+            .join("\\n");
+        let id = &ids[&node.id];
+        if node.is_flow() {
+            out.push_str(&format!("{}subgraph cluster_{} {{\n", indent, id));
+            out.push_str(&format!("{}    label=\"{}\";\n", indent, label));
+            for child in self.children(Some(&node.id)) {
+                self.write_dot_node(out, child, ids, depth + 1);
+            }
+            out.push_str(&format!("{}}}\n", indent));
+        } else {
+            let style = if node.kind == NodeKind::Sealed { ", style=rounded" } else { "" };
+            out.push_str(&format!("{}{} [label=\"{}\"{}];\n", indent, id, label, style));
+        }
+    }
+
+    fn write_mermaid_node(
+        &self,
+        out: &mut String,
+        node: &GraphNode,
+        ids: &HashMap<String, String>,
+        depth: usize,
+    ) {
+        let indent = "    ".repeat(depth);
+        let label = node
+            .label_lines()
+            .iter()
+            .map(|line| mermaid_escape(line))
+            .collect::<Vec<_>>()
+            .join("<br/>");
+        let id = &ids[&node.id];
+        if node.is_flow() {
+            out.push_str(&format!("{}subgraph {} [\"{}\"]\n", indent, id, label));
+            for child in self.children(Some(&node.id)) {
+                self.write_mermaid_node(out, child, ids, depth + 1);
+            }
+            out.push_str(&format!("{}end\n", indent));
+        } else if node.kind == NodeKind::Sealed {
+            out.push_str(&format!("{}{}(\"{}\")\n", indent, id, label));
+        } else {
+            out.push_str(&format!("{}{}[\"{}\"]\n", indent, id, label));
+        }
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('|', "#124;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::semantic::signature::Signature;
+    use crate::core::semantic::Sealable;
+    use crate::core::sync_impl::node::{Node, NodeLogic};
+    use crate::core::Executable;
+
+    #[derive(Clone)]
+    struct Step {
+        task_id: String,
+        signature: Signature,
+    }
+
+    impl NodeLogic for Step {
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+
+        fn as_sealable(&self) -> Option<&dyn Sealable> {
+            Some(self)
+        }
+    }
+
+    impl Sealable for Step {
+        fn signature(&self) -> Signature {
+            self.signature.clone()
+        }
+
+        fn task_id(&self) -> String {
+            self.task_id.clone()
+        }
+    }
+
+    fn step(task_id: &str, signature: &str) -> Node {
+        Node::new(Step {
+            task_id: task_id.to_string(),
+            signature: signature.parse().unwrap(),
+        })
+    }
+
+    #[derive(Clone)]
+    struct Plain;
+
+    impl NodeLogic for Plain {
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_cycle_is_drawn_as_back_edge() {
+        // draft -> review, review --retry--> draft (same task id)
+        let review = step("review", "draft -> verdict")
+            .next_on("retry", Executable::Sync(step("draft", "topic -> draft")));
+        let draft = step("draft", "topic -> draft").next(Executable::Sync(review));
+        let start = Executable::Sync(draft);
+
+        let dot = start.to_dot();
+        assert!(dot.contains("n0 [label=\"draft\\ntopic -> draft\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"default\"];"));
+        assert!(dot.contains("n1 -> n0 [label=\"retry\"];"));
+
+        let mermaid = start.to_mermaid();
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains("n1 -->|retry| n0"));
+    }
+
+    #[test]
+    fn test_nested_flow_is_a_subgraph() {
+        let inner = AsyncFlow::new(Executable::Sync(step("inner", "a -> b")));
+        let start = Executable::Sync(Node::new(Plain).next(inner.into()));
+
+        let dot = start.to_dot();
+        assert!(dot.contains("n0 [label=\"Plain\"];"));
+        assert!(dot.contains("subgraph cluster_n1 {"));
+        assert!(dot.contains("label=\"AsyncFlow\";"));
+        assert!(dot.contains("lhead=cluster_n1"));
+
+        let mermaid = start.to_mermaid();
+        assert!(mermaid.contains("subgraph n1 [\"AsyncFlow\"]"));
+        assert!(mermaid.contains("n0 -->|default| n1"));
+    }
+}
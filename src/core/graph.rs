@@ -0,0 +1,532 @@
+//! A static model of a flow graph, shared by the exporters and other tooling.
+//!
+//! Flows are trees of owned `Executable`s, so the only way to express a loop (or to share a
+//! node between several actions) is to reuse a `task_id`. The graph therefore identifies the
+//! nodes of each flow by their task id when they have one, which also guarantees that building it
+//! terminates. Nested flows are separate scopes: the same task id inside two nested flows, or
+//! inside one and at the top level, makes distinct nodes.
+
+use crate::core::async_impl::async_flow::AsyncFlowLogic;
+use crate::core::async_impl::async_node::AsyncNodeLogic;
+use crate::core::export::short_type_name;
+use crate::core::scope::FlowScope;
+use crate::core::semantic::signature::Signature;
+use crate::core::sync_impl::node::NodeLogic;
+use crate::core::Executable;
+use std::collections::{HashMap, HashSet};
+
+/// Which kind of `Executable` a graph node was built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Sync,
+    Async,
+    Sealed,
+}
+
+/// A node of a [`FlowGraph`].
+#[derive(Debug, Clone)]
+pub struct GraphNode {
+    /// The task id for sealable nodes, a generated `Name#index` otherwise. Members of a nested
+    /// flow are prefixed with the flow's id and a `/`. Ids that would collide with another node's
+    /// get a `~n` suffix; a top-level task id is always its node's id.
+    pub id: String,
+    pub kind: NodeKind,
+    /// Short type name of the logic, or `Flow`/`AsyncFlow` for nested flows.
+    pub name: String,
+    pub task_id: Option<String>,
+    pub signature: Option<Signature>,
+    /// Only known for sealed nodes.
+    pub model_name: Option<String>,
+    /// The nested flow this node belongs to, `None` at the top level.
+    pub parent: Option<String>,
+    /// For nested flows, the id of their start node.
+    pub nested_start: Option<String>,
+    /// For scoped nested flows, their key mapping.
+    pub scope: Option<FlowScope>,
+}
+
+impl GraphNode {
+    pub fn is_flow(&self) -> bool {
+        self.nested_start.is_some()
+    }
+
+    /// Human readable label, one entry per line.
+    pub fn label_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        match &self.task_id {
+            Some(task_id) => lines.push(task_id.clone()),
+            None if self.scope.is_some() => lines.push(format!("{} (scoped)", self.name)),
+            None => lines.push(self.name.clone()),
+        }
+        if let Some(signature) = &self.signature {
+            lines.push(signature.to_string());
+        }
+        if let Some(model) = &self.model_name {
+            lines.push(format!("model: {}", model));
+        }
+        lines
+    }
+}
+
+/// A transition between two nodes, taken when `from` returns `action`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphEdge {
+    pub from: String,
+    pub action: String,
+    pub to: String,
+}
+
+/// The graph reachable from a start `Executable`, nested flows included.
+#[derive(Debug, Clone, Default)]
+pub struct FlowGraph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+    roots: Vec<String>,
+    index: HashMap<String, usize>,
+    /// Node ids by the nested flow they belong to and their task id.
+    tasks: HashMap<(Option<String>, String), String>,
+    /// Top-level task ids, set aside before walking so generated ids never take them.
+    reserved: HashSet<String>,
+}
+
+impl FlowGraph {
+    /// Builds the graph reachable from `start`.
+    pub fn from_executable(start: &Executable) -> Self {
+        Self::from_roots(start, [])
+    }
+
+    /// Builds the graph reachable from `start`, plus the graphs of `others`.
+    ///
+    /// Nodes only reachable from `others` are reported by [`FlowGraph::unreachable_sealed`].
+    pub fn from_roots<'a>(
+        start: &Executable,
+        others: impl IntoIterator<Item = &'a Executable>,
+    ) -> Self {
+        let others: Vec<&Executable> = others.into_iter().collect();
+        let mut graph = FlowGraph::default();
+        for executable in std::iter::once(start).chain(others.iter().copied()) {
+            graph.reserve(executable);
+        }
+        let root = graph.walk(start, None);
+        graph.roots.push(root);
+        for other in others {
+            let root = graph.walk(other, None);
+            if !graph.roots.contains(&root) {
+                graph.roots.push(root);
+            }
+        }
+        graph
+    }
+
+    /// Sets aside the task ids of the top-level nodes reachable from `start`.
+    fn reserve(&mut self, start: &Executable) {
+        let mut stack = vec![start];
+        while let Some(current) = stack.pop() {
+            if let Some(task_id) = current.task_id() {
+                self.reserved.insert(task_id);
+            }
+            stack.extend(current.successors().values());
+        }
+    }
+
+    /// `candidate`, or `candidate~n` with the first `n` no other node can have.
+    fn unique_id(&self, candidate: String) -> String {
+        let taken = |id: &str| self.index.contains_key(id) || self.reserved.contains(id);
+        if !taken(&candidate) {
+            return candidate;
+        }
+        (2..)
+            .map(|n| format!("{}~{}", candidate, n))
+            .find(|id| !taken(id))
+            .expect("some suffix is free")
+    }
+
+    fn walk(&mut self, current: &Executable, parent: Option<&str>) -> String {
+        let task_id = current.task_id();
+        let task_key = task_id.clone().map(|t| (parent.map(str::to_string), t));
+        if let Some(existing) = task_key.as_ref().and_then(|key| self.tasks.get(key)) {
+            return existing.clone();
+        }
+
+        let (kind, name, signature, model_name, scope) = describe(current);
+        let prefix = parent.map(|p| format!("{}/", p)).unwrap_or_default();
+        let id = match (&task_id, parent) {
+            (Some(task_id), None) => task_id.clone(),
+            (Some(task_id), Some(_)) => self.unique_id(format!("{}{}", prefix, task_id)),
+            (None, _) => self.unique_id(format!("{}{}#{}", prefix, name, self.nodes.len())),
+        };
+
+        // Registered before recursing so that loops back to this node terminate.
+        if let Some(key) = task_key {
+            self.tasks.insert(key, id.clone());
+        }
+        self.index.insert(id.clone(), self.nodes.len());
+        self.nodes.push(GraphNode {
+            id: id.clone(),
+            kind,
+            name,
+            task_id,
+            signature,
+            model_name,
+            parent: parent.map(str::to_string),
+            nested_start: None,
+            scope,
+        });
+
+        if let Some(start) = current.nested_start() {
+            let nested_start = self.walk(&start, Some(&id));
+            let position = self.index[&id];
+            self.nodes[position].nested_start = Some(nested_start);
+        }
+
+        let mut successors: Vec<_> = current.successors().iter().collect();
+        successors.sort_by(|a, b| a.0.cmp(b.0));
+        for (action, successor) in successors {
+            let to = self.walk(successor, parent);
+            self.edges.push(GraphEdge {
+                from: id.clone(),
+                action: action.clone(),
+                to,
+            });
+        }
+
+        id
+    }
+
+    /// All nodes, in depth-first discovery order.
+    pub fn nodes(&self) -> &[GraphNode] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[GraphEdge] {
+        &self.edges
+    }
+
+    pub fn node(&self, id: &str) -> Option<&GraphNode> {
+        self.index.get(id).map(|&i| &self.nodes[i])
+    }
+
+    /// The node the graph was built from.
+    pub fn root(&self) -> &GraphNode {
+        &self.nodes[self.index[&self.roots[0]]]
+    }
+
+    /// Outgoing edges of `id`.
+    pub fn successors<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a GraphEdge> + 'a {
+        self.edges.iter().filter(move |edge| edge.from == id)
+    }
+
+    /// Incoming edges of `id`.
+    pub fn predecessors<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a GraphEdge> + 'a {
+        self.edges.iter().filter(move |edge| edge.to == id)
+    }
+
+    /// Direct members of the nested flow `flow_id`, or of the top level for `None`.
+    pub fn children<'a>(
+        &'a self,
+        flow_id: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a GraphNode> + 'a {
+        self.nodes
+            .iter()
+            .filter(move |node| node.parent.as_deref() == flow_id)
+    }
+
+    /// Ids of every node reachable from `id`, itself and nested flow members included.
+    pub fn reachable_from(&self, id: &str) -> HashSet<&str> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            let Some(node) = self.node(current) else {
+                continue;
+            };
+            if !seen.insert(node.id.as_str()) {
+                continue;
+            }
+            if let Some(start) = &node.nested_start {
+                stack.push(start);
+            }
+            stack.extend(self.successors(current).map(|edge| edge.to.as_str()));
+        }
+        seen
+    }
+
+    /// Top-level nodes a run can start from: the roots, and nodes nothing points to.
+    pub fn entry_nodes(&self) -> Vec<&GraphNode> {
+        self.children(None)
+            .filter(|node| {
+                self.roots.contains(&node.id) || self.predecessors(&node.id).next().is_none()
+            })
+            .collect()
+    }
+
+    /// Top-level nodes without successors, where a run necessarily ends.
+    pub fn exit_nodes(&self) -> Vec<&GraphNode> {
+        self.children(None)
+            .filter(|node| self.successors(&node.id).next().is_none())
+            .collect()
+    }
+
+    /// Groups of node ids forming loops (strongly connected components with a cycle).
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            counter: 0,
+            indices: HashMap::new(),
+            lowlinks: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+        for node in &self.nodes {
+            if !tarjan.indices.contains_key(node.id.as_str()) {
+                tarjan.visit(&node.id);
+            }
+        }
+
+        tarjan
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || self
+                        .successors(&component[0])
+                        .any(|edge| edge.to == component[0])
+            })
+            .collect()
+    }
+
+    pub fn has_cycles(&self) -> bool {
+        !self.cycles().is_empty()
+    }
+
+    /// Nodes with a task id (sealed or sealable) that can't be reached from the root.
+    pub fn unreachable_sealed(&self) -> Vec<&GraphNode> {
+        let reachable = self.reachable_from(&self.roots[0]);
+        self.nodes
+            .iter()
+            .filter(|node| node.task_id.is_some() && !reachable.contains(node.id.as_str()))
+            .collect()
+    }
+}
+
+type Description = (
+    NodeKind,
+    String,
+    Option<Signature>,
+    Option<String>,
+    Option<FlowScope>,
+);
+
+fn describe(current: &Executable) -> Description {
+    match current {
+        Executable::Sealed(sealed) => {
+            let (_, name, _, _, scope) = describe(sealed.inner());
+            (
+                NodeKind::Sealed,
+                name,
+                Some(sealed.signature.clone()),
+                Some(sealed.model_name().to_string()),
+                scope,
+            )
+        }
+        Executable::Sync(node) => {
+            let behaviour: &dyn NodeLogic = &*node.behaviour;
+            (
+                NodeKind::Sync,
+                display_name(behaviour.type_name()),
+                behaviour.as_sealable().map(|s| s.signature()),
+                None,
+                None,
+            )
+        }
+        Executable::Async(node) => {
+            let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+            let scope = behaviour
+                .as_any()
+                .downcast_ref::<AsyncFlowLogic>()
+                .and_then(|logic| logic.scope.clone());
+            (
+                NodeKind::Async,
+                display_name(behaviour.type_name()),
+                behaviour.as_sealable().map(|s| s.signature()),
+                None,
+                scope,
+            )
+        }
+    }
+}
+
+pub(crate) fn display_name(type_name: &str) -> String {
+    match short_type_name(type_name) {
+        "FlowLogic" => "Flow".to_string(),
+        "AsyncFlowLogic" => "AsyncFlow".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Tarjan's strongly connected components over the transition edges.
+struct Tarjan<'a> {
+    graph: &'a FlowGraph,
+    counter: usize,
+    indices: HashMap<&'a str, usize>,
+    lowlinks: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: HashSet<&'a str>,
+    components: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, id: &'a str) {
+        self.indices.insert(id, self.counter);
+        self.lowlinks.insert(id, self.counter);
+        self.counter += 1;
+        self.stack.push(id);
+        self.on_stack.insert(id);
+
+        let graph = self.graph;
+        for edge in graph.successors(id) {
+            let to = edge.to.as_str();
+            if !self.indices.contains_key(to) {
+                self.visit(to);
+                let low = self.lowlinks[id].min(self.lowlinks[to]);
+                self.lowlinks.insert(id, low);
+            } else if self.on_stack.contains(to) {
+                let low = self.lowlinks[id].min(self.indices[to]);
+                self.lowlinks.insert(id, low);
+            }
+        }
+
+        if self.lowlinks[id] == self.indices[id] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == id {
+                    break;
+                }
+            }
+            component.reverse();
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::semantic::Sealable;
+    use crate::core::sync_impl::node::Node;
+
+    #[derive(Clone)]
+    struct Step(String);
+
+    impl NodeLogic for Step {
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+
+        fn as_sealable(&self) -> Option<&dyn Sealable> {
+            Some(self)
+        }
+    }
+
+    impl Sealable for Step {
+        fn signature(&self) -> Signature {
+            "a -> b".parse().unwrap()
+        }
+
+        fn task_id(&self) -> String {
+            self.0.clone()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Plain;
+
+    impl NodeLogic for Plain {
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn step(task_id: &str) -> Node {
+        Node::new(Step(task_id.to_string()))
+    }
+
+    #[test]
+    fn test_shared_node_and_cycle() {
+        // draft -> review; review --retry--> draft; review --accept--> publish;
+        // publish is also reachable straight from draft via "skip".
+        let review = step("review")
+            .next_on("retry", Executable::Sync(step("draft")))
+            .next_on("accept", Executable::Sync(step("publish")));
+        let draft = step("draft")
+            .next(Executable::Sync(review))
+            .next_on("skip", Executable::Sync(step("publish")));
+        let graph = FlowGraph::from_executable(&Executable::Sync(draft));
+
+        assert_eq!(graph.nodes().len(), 3);
+        assert_eq!(graph.edges().len(), 4);
+        assert_eq!(graph.root().id, "draft");
+        assert_eq!(graph.predecessors("publish").count(), 2);
+        assert_eq!(graph.cycles(), vec![vec!["draft".to_string(), "review".to_string()]]);
+
+        let exits: Vec<_> = graph.exit_nodes().iter().map(|n| n.id.clone()).collect();
+        assert_eq!(exits, vec!["publish".to_string()]);
+        let entries: Vec<_> = graph.entry_nodes().iter().map(|n| n.id.clone()).collect();
+        assert_eq!(entries, vec!["draft".to_string()]);
+        assert!(graph.unreachable_sealed().is_empty());
+    }
+
+    #[test]
+    fn test_nested_flow_and_unreachable_nodes() {
+        let inner = AsyncFlow::new(Executable::Sync(step("inner")));
+        let start = Executable::Sync(Node::new(Plain).next(inner.into()));
+        let orphan = Executable::Sync(step("orphan"));
+        let graph = FlowGraph::from_roots(&start, [&orphan]);
+
+        assert!(!graph.has_cycles());
+        let flow = graph.node("AsyncFlow#1").unwrap();
+        assert!(flow.is_flow());
+        assert_eq!(flow.nested_start.as_deref(), Some("AsyncFlow#1/inner"));
+        let inner = graph.node("AsyncFlow#1/inner").unwrap();
+        assert_eq!(inner.parent.as_deref(), Some("AsyncFlow#1"));
+        assert_eq!(inner.task_id.as_deref(), Some("inner"));
+        assert!(graph.reachable_from("Plain#0").contains("AsyncFlow#1/inner"));
+
+        let unreachable: Vec<_> = graph.unreachable_sealed().iter().map(|n| n.id.clone()).collect();
+        assert_eq!(unreachable, vec!["orphan".to_string()]);
+    }
+
+    #[test]
+    fn test_ids_are_scoped_to_their_flow_and_never_collide() {
+        // The same task id in two nested flows and at the top level, and a task id that looks
+        // like a generated one.
+        let summarize = || AsyncFlow::new(Executable::Sync(step("summarize")));
+        let first = match Executable::from(summarize()) {
+            Executable::Async(flow) => flow
+                .next(summarize().into())
+                .next_on("done", Executable::Sync(step("summarize"))),
+            _ => unreachable!(),
+        };
+        let start = Executable::Sync(step("AsyncFlow#1").next(Executable::Async(first)));
+        let graph = FlowGraph::from_executable(&start);
+
+        let ids: Vec<&str> = graph.nodes().iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "AsyncFlow#1",
+                "AsyncFlow#1~2",
+                "AsyncFlow#1~2/summarize",
+                "AsyncFlow#3",
+                "AsyncFlow#3/summarize",
+                "summarize",
+            ]
+        );
+        let unique: HashSet<&str> = ids.iter().copied().collect();
+        assert_eq!(unique.len(), ids.len());
+        assert_eq!(graph.children(Some("AsyncFlow#3")).count(), 1);
+    }
+}
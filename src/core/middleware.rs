@@ -0,0 +1,325 @@
+//! Cross-cutting hooks around the `prep`/`exec`/`post` phases of every node of a flow.
+//!
+//! Middleware is registered on a flow with `with_middleware` and applies to every node the flow
+//! runs, including the nodes of nested flows. The first registered middleware is the outermost
+//! one: its `before_*` hooks run first and its `after_*` hooks run last.
+
+use crate::core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
+use crate::core::graph::display_name;
+use crate::core::sync_impl::node::{Node, NodeLogic};
+use crate::core::sync_impl::NodeValue;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Identity of the node a hook is called for.
+#[derive(Debug, Clone)]
+pub struct NodeInfo<'a> {
+    /// The task id of the node, or its type name if it has none.
+    pub id: String,
+    pub task_id: Option<String>,
+    pub params: &'a HashMap<String, NodeValue>,
+}
+
+impl<'a> NodeInfo<'a> {
+    fn new(task_id: Option<String>, type_name: &str, params: &'a HashMap<String, NodeValue>) -> Self {
+        Self {
+            id: task_id.clone().unwrap_or_else(|| display_name(type_name)),
+            task_id,
+            params,
+        }
+    }
+}
+
+/// Hooks wrapping each phase of a node. Every hook does nothing by default.
+pub trait NodeMiddleware: Send + Sync {
+    fn before_prep(&self, _node: &NodeInfo, _shared: &mut HashMap<String, NodeValue>) {}
+
+    /// Can rewrite the prep result, which is what `exec` and telemetry see.
+    fn after_prep(
+        &self,
+        _node: &NodeInfo,
+        _shared: &HashMap<String, NodeValue>,
+        _prep_res: &mut NodeValue,
+    ) {
+    }
+
+    /// Returning a value skips `exec` and uses it as the exec result (caching, authorization).
+    fn before_exec(&self, _node: &NodeInfo, _prep_res: &NodeValue) -> Option<NodeValue> {
+        None
+    }
+
+    /// Can rewrite the exec result, which is what `post` and telemetry see.
+    fn after_exec(&self, _node: &NodeInfo, _prep_res: &NodeValue, _exec_res: &mut NodeValue) {}
+
+    fn before_post(
+        &self,
+        _node: &NodeInfo,
+        _shared: &mut HashMap<String, NodeValue>,
+        _exec_res: &NodeValue,
+    ) {
+    }
+
+    /// Can inspect the shared state left by the node and override the action it returned.
+    fn after_post(
+        &self,
+        _node: &NodeInfo,
+        _shared: &mut HashMap<String, NodeValue>,
+        _action: &mut Option<String>,
+    ) {
+    }
+}
+
+/// The middleware registered on a flow, outermost first.
+pub(crate) type MiddlewareChain = Vec<Arc<dyn NodeMiddleware>>;
+
+/// Runs `prep`, `exec` and `post` with the hooks of `chain`, returning what each phase produced.
+pub(crate) fn run_sync_phases(
+    node: &Node,
+    shared: &mut HashMap<String, NodeValue>,
+    chain: &[Arc<dyn NodeMiddleware>],
+) -> (NodeValue, NodeValue, Option<String>) {
+    let behaviour: &dyn NodeLogic = &*node.behaviour;
+    let info = NodeInfo::new(
+        behaviour.as_sealable().map(|s| s.task_id()),
+        behaviour.type_name(),
+        &node.data.params,
+    );
+
+    for middleware in chain {
+        middleware.before_prep(&info, shared);
+    }
+    let mut p = behaviour.prep(&node.data.params, shared);
+    for middleware in chain.iter().rev() {
+        middleware.after_prep(&info, shared, &mut p);
+    }
+
+    let mut e = match chain.iter().find_map(|m| m.before_exec(&info, &p)) {
+        Some(cached) => cached,
+        None => behaviour.exec(p.clone()),
+    };
+    for middleware in chain.iter().rev() {
+        middleware.after_exec(&info, &p, &mut e);
+    }
+
+    for middleware in chain {
+        middleware.before_post(&info, shared, &e);
+    }
+    let mut action = behaviour.post(shared, p.clone(), e.clone());
+    for middleware in chain.iter().rev() {
+        middleware.after_post(&info, shared, &mut action);
+    }
+    (p, e, action)
+}
+
+/// Async counterpart of [`run_sync_phases`].
+pub(crate) async fn run_async_phases(
+    node: &AsyncNode,
+    shared: &mut HashMap<String, NodeValue>,
+    chain: &[Arc<dyn NodeMiddleware>],
+) -> (NodeValue, NodeValue, Option<String>) {
+    let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+    let info = NodeInfo::new(
+        behaviour.as_sealable().map(|s| s.task_id()),
+        behaviour.type_name(),
+        &node.data.params,
+    );
+
+    for middleware in chain {
+        middleware.before_prep(&info, shared);
+    }
+    let mut p = behaviour.prep(&node.data.params, shared).await;
+    for middleware in chain.iter().rev() {
+        middleware.after_prep(&info, shared, &mut p);
+    }
+
+    let mut e = match chain.iter().find_map(|m| m.before_exec(&info, &p)) {
+        Some(cached) => cached,
+        None => behaviour.exec(p.clone()).await,
+    };
+    for middleware in chain.iter().rev() {
+        middleware.after_exec(&info, &p, &mut e);
+    }
+
+    for middleware in chain {
+        middleware.before_post(&info, shared, &e);
+    }
+    let mut action = behaviour.post(shared, p.clone(), e.clone()).await;
+    for middleware in chain.iter().rev() {
+        middleware.after_post(&info, shared, &mut action);
+    }
+    (p, e, action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::sync_impl::flow::Flow;
+    use crate::core::Executable;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Copies `input` to `output`, uppercased.
+    #[derive(Clone)]
+    struct Upper {
+        input: &'static str,
+        output: &'static str,
+    }
+
+    impl NodeLogic for Upper {
+        fn prep(
+            &self,
+            _params: &HashMap<String, NodeValue>,
+            shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            shared.get(self.input).cloned().unwrap_or(NodeValue::Null)
+        }
+
+        fn exec(&self, input: NodeValue) -> NodeValue {
+            json!(input.as_str().unwrap_or_default().to_uppercase())
+        }
+
+        fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            _prep_res: NodeValue,
+            exec_res: NodeValue,
+        ) -> Option<String> {
+            shared.insert(self.output.to_string(), exec_res);
+            None
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[async_trait]
+    impl AsyncNodeLogic for Upper {
+        async fn prep(
+            &self,
+            params: &HashMap<String, NodeValue>,
+            shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            NodeLogic::prep(self, params, shared)
+        }
+
+        async fn exec(&self, input: NodeValue) -> NodeValue {
+            NodeLogic::exec(self, input)
+        }
+
+        async fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            prep_res: NodeValue,
+            exec_res: NodeValue,
+        ) -> Option<String> {
+            NodeLogic::post(self, shared, prep_res, exec_res)
+        }
+
+        fn clone_box(&self) -> Box<dyn AsyncNodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    /// Records every hook call and answers `exec` from a cache for the inputs it knows.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        calls: Arc<Mutex<Vec<String>>>,
+        cache: HashMap<String, NodeValue>,
+    }
+
+    impl NodeMiddleware for Recorder {
+        fn before_prep(&self, node: &NodeInfo, _shared: &mut HashMap<String, NodeValue>) {
+            self.calls.lock().unwrap().push(format!("before_prep {}", node.id));
+        }
+
+        fn before_exec(&self, node: &NodeInfo, prep_res: &NodeValue) -> Option<NodeValue> {
+            let cached = self.cache.get(prep_res.as_str()?).cloned();
+            if cached.is_some() {
+                self.calls.lock().unwrap().push(format!("cached {}", node.id));
+            }
+            cached
+        }
+
+        fn after_post(
+            &self,
+            node: &NodeInfo,
+            _shared: &mut HashMap<String, NodeValue>,
+            action: &mut Option<String>,
+        ) {
+            self.calls.lock().unwrap().push(format!("after_post {} {:?}", node.id, action));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_flow_middleware_reaches_nested_flows() {
+        let recorder = Recorder {
+            cache: HashMap::from([("b".to_string(), json!("cached"))]),
+            ..Default::default()
+        };
+        let nested = AsyncFlow::new(Executable::Async(AsyncNode::new(Upper {
+            input: "b",
+            output: "B",
+        })));
+        let start = Node::new(Upper { input: "a", output: "A" }).next(nested.into());
+        let flow = AsyncFlow::new(Executable::Sync(start)).with_middleware(recorder.clone());
+
+        let mut shared = HashMap::from([
+            ("a".to_string(), json!("a")),
+            ("b".to_string(), json!("b")),
+        ]);
+        flow.run(&mut shared).await;
+
+        assert_eq!(shared.get("A"), Some(&json!("A")));
+        assert_eq!(shared.get("B"), Some(&json!("cached")));
+        assert_eq!(
+            *recorder.calls.lock().unwrap(),
+            vec![
+                "before_prep Upper",
+                "after_post Upper None",
+                "before_prep Upper",
+                "cached Upper",
+                "after_post Upper None",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flow_middleware_order() {
+        struct Tag(&'static str, Arc<Mutex<Vec<String>>>);
+
+        impl NodeMiddleware for Tag {
+            fn before_prep(&self, _node: &NodeInfo, _shared: &mut HashMap<String, NodeValue>) {
+                self.1.lock().unwrap().push(format!("{} before", self.0));
+            }
+
+            fn after_post(
+                &self,
+                _node: &NodeInfo,
+                _shared: &mut HashMap<String, NodeValue>,
+                action: &mut Option<String>,
+            ) {
+                self.1.lock().unwrap().push(format!("{} after", self.0));
+                *action = Some("stop".to_string());
+            }
+        }
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let nested = Flow::new(Node::new(Upper { input: "a", output: "A" }));
+        let flow = Flow::new((*nested).clone())
+            .with_middleware(Tag("outer", calls.clone()))
+            .with_middleware(Tag("inner", calls.clone()));
+
+        let mut shared = HashMap::from([("a".to_string(), json!("a"))]);
+        let action = flow.run(&mut shared);
+
+        assert_eq!(shared.get("A"), Some(&json!("A")));
+        assert_eq!(action, Some("stop".to_string()));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec!["outer before", "inner before", "inner after", "outer after"]
+        );
+    }
+}
@@ -1,6 +1,15 @@
 pub mod async_impl;
+pub mod definition;
+pub mod events;
+pub mod export;
+pub mod graph;
+pub mod optimize;
+pub mod middleware;
+pub mod runner;
+pub mod scope;
 pub mod sealed;
 pub mod semantic;
+pub mod state;
 pub mod sync_impl;
 pub mod telemetry;
 pub mod validation;
@@ -30,6 +39,15 @@ impl Executable {
         }
     }
 
+    /// Returns the task id of this executable, if it is sealable.
+    pub fn task_id(&self) -> Option<String> {
+        match self {
+            Executable::Sync(node) => node.behaviour.as_sealable().map(|s| s.task_id()),
+            Executable::Async(node) => node.behaviour.as_sealable().map(|s| s.task_id()),
+            Executable::Sealed(sealed) => Some(sealed.task_id().to_string()),
+        }
+    }
+
     pub async fn run_with_telemetry(
         &self,
         shared: &mut HashMap<String, NodeValue>,
@@ -0,0 +1,284 @@
+//! Few-shot demonstrations bootstrapped from a flow's own successful runs.
+
+use super::{hash_json, mean, Example, Metric};
+use crate::core::async_impl::async_flow::AsyncFlow;
+use crate::core::graph::NodeKind;
+use crate::core::semantic::demo::Demo;
+use crate::core::semantic::extract::extract_object;
+use crate::core::semantic::registry::{OptimizationRecord, RegistryError, RegistryStore};
+use crate::core::semantic::signature::Signature;
+use crate::core::sync_impl::NodeValue;
+use crate::core::telemetry::query::is_error;
+use crate::core::telemetry::{MemoryTelemetry, SpanKind, TraceEntry};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Runs a flow over a training set and keeps what its semantic nodes did on the examples that
+/// scored well as their demonstrations.
+pub struct BootstrapFewShot {
+    metric: Arc<dyn Metric>,
+    threshold: f64,
+    max_demos: usize,
+}
+
+/// What [`BootstrapFewShot::compile`] did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapReport {
+    /// The score of each training example, in order.
+    pub scores: Vec<f64>,
+    /// The registered records, one per semantic node that got demos, sorted by task id.
+    pub records: Vec<OptimizationRecord>,
+}
+
+impl BootstrapReport {
+    /// The mean score over the training set, also registered as each record's fitness.
+    pub fn fitness(&self) -> f64 {
+        mean(&self.scores)
+    }
+}
+
+/// The search configuration, hashed into `optimization_config_hash`.
+#[derive(Serialize)]
+struct Config {
+    optimizer: &'static str,
+    threshold: f64,
+    max_demos: usize,
+}
+
+struct Candidate {
+    signature_hash: String,
+    instruction_hash: String,
+    demos: Vec<Demo>,
+}
+
+impl BootstrapFewShot {
+    /// Keeps the runs `metric` scores 1.0, up to 4 demos per node.
+    pub fn new(metric: impl Metric + 'static) -> Self {
+        Self {
+            metric: Arc::new(metric),
+            threshold: 1.0,
+            max_demos: 4,
+        }
+    }
+
+    /// Keeps the runs scoring at least `threshold`.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_max_demos(mut self, max_demos: usize) -> Self {
+        self.max_demos = max_demos;
+        self
+    }
+
+    /// Runs `flow` over `trainset`, one example at a time, and registers the demos found for
+    /// each sealed node that called a model.
+    ///
+    /// Records keep the nodes' signature and instruction hashes, so sealing the nodes with the
+    /// registry applies them once promoted. Their `instruction` is left unset: the node's own
+    /// instruction is kept. Traces with redacted fields are never used as demos.
+    pub async fn compile(
+        &self,
+        flow: &AsyncFlow,
+        trainset: &[Example],
+        registry: &dyn RegistryStore,
+    ) -> Result<BootstrapReport, RegistryError> {
+        let signatures: HashMap<String, Signature> = flow
+            .graph()
+            .nodes()
+            .iter()
+            .filter(|node| node.kind == NodeKind::Sealed)
+            .filter_map(|node| Some((node.task_id.clone()?, node.signature.clone()?)))
+            .collect();
+
+        let mut candidates: BTreeMap<String, Candidate> = BTreeMap::new();
+        let mut scores = Vec::with_capacity(trainset.len());
+        for example in trainset {
+            let telemetry = Arc::new(MemoryTelemetry::new());
+            let mut shared = example.inputs.clone();
+            flow.run_with_telemetry(&mut shared, Some(telemetry.clone())).await;
+            let score = self.metric.score(example, &shared).await;
+            scores.push(score);
+            if score < self.threshold {
+                continue;
+            }
+
+            for entry in telemetry.get_traces() {
+                if entry.kind != SpanKind::Sealed || entry.llm_latency_us.is_none() {
+                    continue;
+                }
+                let Some(demo) = signatures.get(&entry.task_id).and_then(|s| demo_from(&entry, s))
+                else {
+                    continue;
+                };
+                let candidate = candidates.entry(entry.task_id.clone()).or_insert_with(|| Candidate {
+                    signature_hash: entry.signature_hash.clone(),
+                    instruction_hash: entry.instruction_hash.clone(),
+                    demos: Vec::new(),
+                });
+                if candidate.demos.len() < self.max_demos && !candidate.demos.contains(&demo) {
+                    candidate.demos.push(demo);
+                }
+            }
+        }
+
+        let training_hash = hash_json(&trainset);
+        let config_hash = hash_json(&Config {
+            optimizer: "bootstrap_few_shot",
+            threshold: self.threshold,
+            max_demos: self.max_demos,
+        });
+        let fitness = mean(&scores);
+
+        let mut records = Vec::new();
+        for (task_id, candidate) in candidates {
+            let mut record = OptimizationRecord {
+                task_id,
+                version: 0,
+                active: false,
+                signature_hash: candidate.signature_hash,
+                instruction_hash: candidate.instruction_hash,
+                optimized_instruction_hash: None,
+                instruction: None,
+                demos: candidate.demos,
+                training_hash: Some(training_hash.clone()),
+                optimization_config_hash: Some(config_hash.clone()),
+                fitness_score: Some(fitness),
+                weights_path: None,
+                created_at: 0,
+                updated_at: 0,
+            };
+            record.version = registry.register(record.clone())?;
+            records.push(record);
+        }
+        Ok(BootstrapReport { scores, records })
+    }
+}
+
+/// The demo a sealed node's trace makes, if it answered every output of `signature`.
+fn demo_from(entry: &TraceEntry, signature: &Signature) -> Option<Demo> {
+    if is_error(entry) || entry.metadata.keys().any(|k| k.starts_with("redaction.")) {
+        return None;
+    }
+    // Semantic nodes trace the model's raw answer.
+    let outputs = match &entry.outputs {
+        NodeValue::String(text) => Value::Object(extract_object(text).ok()?),
+        other => other.clone(),
+    };
+    let outputs: Map<String, Value> = signature
+        .outputs
+        .iter()
+        .map(|field| Some((field.name.clone(), outputs.get(&field.name)?.clone())))
+        .collect::<Option<_>>()?;
+    let demo = Demo::new(entry.inputs.clone(), Value::Object(outputs));
+    demo.check(signature).ok()?;
+    Some(demo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::semantic::redaction::{Redaction, Scrubber};
+    use crate::core::semantic::registry::OptimizationRegistry;
+    use crate::core::Executable;
+    use crate::llm::{mock, Client};
+    use serde_json::json;
+
+    /// Answers arithmetic questions, getting `5+5?` wrong.
+    fn calculator() -> Client<impl Clone + Send + Sync + 'static> {
+        let host = mock::ollama(|messages| {
+            let prompt = mock::last_content(messages);
+            let input = prompt.rsplit("Input Data:\n").next().unwrap().lines().next().unwrap();
+            let question: Value = serde_json::from_str(input).unwrap();
+            let answer = match question["question"].as_str().unwrap() {
+                "2+2?" => "4",
+                "3+3?" => "6",
+                _ => "11",
+            };
+            json!({ "answer": answer }).to_string()
+        });
+        Client::new().with_ollama_at(host)
+    }
+
+    fn example(question: &str, answer: &str) -> Example {
+        Example::new(
+            HashMap::from([("question".to_string(), json!(question))]),
+            HashMap::from([("answer".to_string(), json!(answer))]),
+        )
+    }
+
+    fn exact_match(example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        let right = example.expected.iter().all(|(key, value)| shared.get(key) == Some(value));
+        if right { 1.0 } else { 0.0 }
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_registers_successful_traces_as_demos() {
+        let client = calculator();
+        let node = || {
+            client
+                .semantic_node()
+                .signature(crate::signature!("question -> answer"))
+                .instruction("Answer the question.")
+                .task_id("answer")
+        };
+        let flow = AsyncFlow::new(node().seal());
+        let trainset = [example("2+2?", "4"), example("5+5?", "10"), example("3+3?", "6")];
+        let registry = Arc::new(OptimizationRegistry::new());
+
+        let report = BootstrapFewShot::new(exact_match)
+            .compile(&flow, &trainset, registry.as_ref())
+            .await
+            .unwrap();
+
+        assert_eq!(report.scores, vec![1.0, 0.0, 1.0]);
+        assert_eq!(report.records.len(), 1);
+        let record = &report.records[0];
+        assert_eq!(
+            record.demos,
+            vec![
+                Demo::new(json!({"question": "2+2?"}), json!({"answer": "4"})),
+                Demo::new(json!({"question": "3+3?"}), json!({"answer": "6"})),
+            ]
+        );
+        assert_eq!(record.fitness_score, Some(2.0 / 3.0));
+        assert_eq!(record.training_hash.as_ref().unwrap().len(), 64);
+        assert!(record.optimization_config_hash.is_some());
+
+        // Registered for review, not promoted.
+        assert_eq!(registry.get_by_task_id("answer").unwrap(), None);
+        registry.promote("answer", record.version).unwrap();
+        let Executable::Sealed(sealed) = node().registry(registry.clone()).seal() else {
+            unreachable!()
+        };
+        let Executable::Async(optimized) = sealed.inner() else { unreachable!() };
+        assert_eq!(optimized.behaviour.as_promptable().unwrap().demos(), record.demos.as_slice());
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_keeps_redacted_traces_out_of_demos() {
+        let signature = crate::signature!("question -> answer")
+            .redact("answer", Redaction::Scrub(vec![Scrubber::Emails]));
+        let flow = AsyncFlow::new(
+            calculator()
+                .semantic_node()
+                .signature(signature)
+                .instruction("Answer the question.")
+                .task_id("answer")
+                .seal(),
+        );
+        let registry = OptimizationRegistry::new();
+
+        let report = BootstrapFewShot::new(exact_match)
+            .compile(&flow, &[example("2+2?", "4")], &registry)
+            .await
+            .unwrap();
+
+        assert_eq!(report.scores, vec![1.0]);
+        assert!(report.records.is_empty());
+        assert!(registry.task_ids().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Loading examples from JSON Lines and CSV files.
+
+use super::Example;
+use crate::core::sync_impl::NodeValue;
+use std::io;
+use std::path::Path;
+
+/// Prefix of the CSV columns holding expected values.
+pub const EXPECTED_PREFIX: &str = "expected.";
+
+impl Example {
+    /// Reads one example per line, each an object with `inputs` and (optionally) `expected`.
+    pub fn load_jsonl(path: impl AsRef<Path>) -> io::Result<Vec<Example>> {
+        let contents = std::fs::read_to_string(path)?;
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| invalid(format!("line {}: {}", i + 1, e)))
+            })
+            .collect()
+    }
+
+    /// Reads one example per row. Columns named `expected.<key>` hold the expected value of
+    /// `<key>`; the others are inputs. Values are kept as strings.
+    pub fn load_csv(path: impl AsRef<Path>) -> io::Result<Vec<Example>> {
+        let rows = parse_csv(&std::fs::read_to_string(path)?)?;
+        let mut rows = rows.into_iter();
+        let Some(header) = rows.next() else {
+            return Ok(Vec::new());
+        };
+        rows.enumerate()
+            .map(|(i, row)| {
+                if row.len() != header.len() {
+                    return Err(invalid(format!(
+                        "row {} has {} fields, the header has {}",
+                        i + 2,
+                        row.len(),
+                        header.len()
+                    )));
+                }
+                let mut example = Example::default();
+                for (column, value) in header.iter().zip(row) {
+                    let value = NodeValue::String(value);
+                    match column.strip_prefix(EXPECTED_PREFIX) {
+                        Some(key) => example.expected.insert(key.to_string(), value),
+                        None => example.inputs.insert(column.clone(), value),
+                    };
+                }
+                Ok(example)
+            })
+            .collect()
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Splits RFC 4180 CSV into rows of fields: quoted fields may contain commas, newlines and
+/// doubled quotes. Empty lines are skipped.
+fn parse_csv(text: &str) -> io::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid("unterminated quoted field".to_string()));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_file(extension: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("orichalcum-{}.{}", uuid::Uuid::new_v4(), extension));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_csv_and_jsonl() {
+        let path = temp_file(
+            "csv",
+            "question,context,expected.answer\r\n\"Who, exactly?\",\"said \"\"hi\"\"\nthen left\",Ada\r\n\n2+2?,,4\n",
+        );
+        let examples = Example::load_csv(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].inputs["question"], json!("Who, exactly?"));
+        assert_eq!(examples[0].inputs["context"], json!("said \"hi\"\nthen left"));
+        assert_eq!(examples[0].expected["answer"], json!("Ada"));
+        assert_eq!(examples[1].inputs["context"], json!(""));
+
+        let path = temp_file("jsonl", "{\"inputs\": {\"x\": 2}, \"expected\": {\"y\": 4}}\n\n{\"inputs\": {}}\n");
+        let examples = Example::load_jsonl(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(examples[0].expected["y"], json!(4));
+        assert!(examples[1].expected.is_empty());
+
+        let path = temp_file("csv", "a,b\n1\n");
+        let error = Example::load_csv(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(error.to_string(), "row 2 has 1 fields, the header has 2");
+    }
+}
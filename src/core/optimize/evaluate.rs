@@ -0,0 +1,242 @@
+//! Scoring a flow over a dataset.
+
+use super::{mean, Example, Metric};
+use crate::core::async_impl::async_flow::AsyncFlow;
+use crate::core::sync_impl::NodeValue;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Runs a flow over examples, a few at a time, and scores each run with every metric.
+pub struct Evaluator {
+    metrics: Vec<(String, Arc<dyn Metric>)>,
+    concurrency: usize,
+    threshold: f64,
+}
+
+/// How one example went.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExampleResult {
+    /// The example's position in the dataset.
+    pub index: usize,
+    /// The shared state the run ended with.
+    pub shared: HashMap<String, NodeValue>,
+    /// The action the flow ended with.
+    pub action: Option<String>,
+    /// The score of each metric, by name.
+    pub scores: BTreeMap<String, f64>,
+    pub duration_us: u64,
+}
+
+/// Aggregate of one metric over the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MetricSummary {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// What [`Evaluator::evaluate`] found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvaluationReport {
+    /// One result per example, in dataset order.
+    pub results: Vec<ExampleResult>,
+    /// The aggregates of each metric, by name.
+    pub summaries: BTreeMap<String, MetricSummary>,
+    /// The fraction of examples whose every metric scored at least the threshold.
+    pub pass_rate: f64,
+}
+
+impl EvaluationReport {
+    /// The mean score of `metric`, if it was evaluated.
+    pub fn mean(&self, metric: &str) -> Option<f64> {
+        self.summaries.get(metric).map(|summary| summary.mean)
+    }
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Evaluator {
+    /// No metrics, 4 examples at a time, passing at 1.0.
+    pub fn new() -> Self {
+        Self {
+            metrics: Vec::new(),
+            concurrency: 4,
+            threshold: 1.0,
+        }
+    }
+
+    /// Scores each run with `metric`, reported under `name`.
+    pub fn with_metric(self, name: impl Into<String>, metric: impl Metric + 'static) -> Self {
+        self.with_shared_metric(name, Arc::new(metric))
+    }
+
+    pub(crate) fn with_shared_metric(mut self, name: impl Into<String>, metric: Arc<dyn Metric>) -> Self {
+        self.metrics.push((name.into(), metric));
+        self
+    }
+
+    /// How many examples run at the same time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The score every metric must reach for an example to pass.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Runs `flow` once per example, starting from its inputs, and scores what it left in the
+    /// shared state against what the example expects.
+    pub async fn evaluate(&self, flow: &AsyncFlow, dataset: &[Example]) -> EvaluationReport {
+        let mut results: Vec<ExampleResult> = stream::iter(dataset.iter().enumerate())
+            .map(|(index, example)| self.run(flow, index, example))
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        results.sort_by_key(|result| result.index);
+
+        let summaries = self
+            .metrics
+            .iter()
+            .filter(|_| !results.is_empty())
+            .map(|(name, _)| {
+                let scores: Vec<f64> = results.iter().map(|r| r.scores[name]).collect();
+                let summary = MetricSummary {
+                    mean: mean(&scores),
+                    min: scores.iter().copied().fold(f64::INFINITY, f64::min),
+                    max: scores.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                };
+                (name.clone(), summary)
+            })
+            .collect();
+        let passed = results
+            .iter()
+            .filter(|r| r.scores.values().all(|score| *score >= self.threshold))
+            .count();
+        let pass_rate = if results.is_empty() {
+            0.0
+        } else {
+            passed as f64 / results.len() as f64
+        };
+        EvaluationReport {
+            results,
+            summaries,
+            pass_rate,
+        }
+    }
+
+    async fn run(&self, flow: &AsyncFlow, index: usize, example: &Example) -> ExampleResult {
+        let start = Instant::now();
+        let mut shared = example.inputs.clone();
+        let action = flow.run(&mut shared).await;
+        let duration_us = start.elapsed().as_micros() as u64;
+        let mut scores = BTreeMap::new();
+        for (name, metric) in &self.metrics {
+            scores.insert(name.clone(), metric.score(example, &shared).await);
+        }
+        ExampleResult {
+            index,
+            shared,
+            action,
+            scores,
+            duration_us,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
+    use crate::core::optimize::metrics::{ExactMatch, NumericTolerance};
+    use crate::core::Executable;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Halves `x` into `half`, slowly, counting how many runs overlap.
+    #[derive(Clone)]
+    struct Halve {
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AsyncNodeLogic for Halve {
+        async fn prep(
+            &self,
+            _params: &HashMap<String, NodeValue>,
+            shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            shared["x"].clone()
+        }
+
+        async fn exec(&self, input: NodeValue) -> NodeValue {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            json!(input.as_f64().unwrap() / 2.0)
+        }
+
+        async fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            _prep_res: NodeValue,
+            exec_res: NodeValue,
+        ) -> Option<String> {
+            shared.insert("half".to_string(), exec_res);
+            Some("done".to_string())
+        }
+
+        fn clone_box(&self) -> Box<dyn AsyncNodeLogic> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn example(x: f64, half: f64) -> Example {
+        Example::new(
+            HashMap::from([("x".to_string(), json!(x))]),
+            HashMap::from([("half".to_string(), json!(half))]),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_evaluate() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let flow = AsyncFlow::new(Executable::Async(AsyncNode::new(Halve {
+            running: Arc::new(AtomicUsize::new(0)),
+            peak: peak.clone(),
+        })));
+        let dataset = [example(4.0, 2.0), example(3.0, 1.5), example(1.0, 0.4), example(8.0, 4.0)];
+
+        let report = Evaluator::new()
+            .with_metric("exact", ExactMatch)
+            .with_metric("close", NumericTolerance::new(0.2))
+            .with_concurrency(2)
+            .evaluate(&flow, &dataset)
+            .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        let indices: Vec<usize> = report.results.iter().map(|r| r.index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert_eq!(report.results[1].shared["half"], json!(1.5));
+        assert_eq!(report.results[2].action.as_deref(), Some("done"));
+        assert_eq!(report.results[2].scores["exact"], 0.0);
+        assert_eq!(report.results[2].scores["close"], 1.0);
+
+        assert_eq!(report.mean("exact"), Some(0.75));
+        assert_eq!(report.summaries["exact"], MetricSummary { mean: 0.75, min: 0.0, max: 1.0 });
+        assert_eq!(report.mean("close"), Some(1.0));
+        assert_eq!(report.pass_rate, 0.75);
+    }
+}
@@ -0,0 +1,292 @@
+//! Instruction search: an LLM proposes rewrites of a node's instruction, which are scored on a
+//! dev set, the best ones seeding the next generation.
+
+use super::evaluate::Evaluator;
+use super::{hash_json, Example, Metric, OptimizeError};
+use crate::core::async_impl::async_flow::AsyncFlow;
+use crate::core::sealed::SealedNode;
+use crate::core::semantic::extract::extract_object;
+use crate::core::semantic::registry::{OptimizationRecord, RegistryStore};
+use crate::core::Executable;
+use crate::llm::{Client, Turn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Asks a proposer model for rewritten instructions and keeps the one scoring best on a dev set.
+pub struct InstructionOptimizer<P> {
+    proposer: Client<P>,
+    metric: Arc<dyn Metric>,
+    candidates: usize,
+    generations: usize,
+    survivors: usize,
+}
+
+/// An instruction that was tried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionCandidate {
+    pub instruction: String,
+    /// The instruction hash of the node rebuilt with this instruction.
+    pub instruction_hash: String,
+    /// Mean metric score over the dev set.
+    pub fitness: f64,
+    /// 0 for the node's own instruction.
+    pub generation: usize,
+}
+
+/// What [`InstructionOptimizer::optimize`] did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionReport {
+    /// Every candidate, in the order they were tried; the node's own instruction first.
+    pub candidates: Vec<InstructionCandidate>,
+    /// The registered record, if a proposal beat the node's own instruction.
+    pub record: Option<OptimizationRecord>,
+}
+
+impl InstructionReport {
+    /// The best candidate, the node's own instruction on ties.
+    pub fn best(&self) -> &InstructionCandidate {
+        best(&self.candidates)
+    }
+}
+
+/// The search configuration, hashed into `optimization_config_hash`.
+#[derive(Serialize)]
+struct Config {
+    optimizer: &'static str,
+    candidates: usize,
+    generations: usize,
+    survivors: usize,
+}
+
+#[derive(Deserialize)]
+struct Proposal {
+    instructions: Vec<String>,
+}
+
+impl<P> InstructionOptimizer<P>
+where
+    P: Clone + Send + Sync + 'static,
+{
+    /// One generation of 4 proposals, scored with `metric`.
+    pub fn new(proposer: Client<P>, metric: impl Metric + 'static) -> Self {
+        Self {
+            proposer,
+            metric: Arc::new(metric),
+            candidates: 4,
+            generations: 1,
+            survivors: 2,
+        }
+    }
+
+    /// How many instructions to ask for per generation.
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// How many rounds of proposals to run. Each round shows the proposer the best instructions
+    /// so far with their fitness.
+    pub fn with_generations(mut self, generations: usize) -> Self {
+        self.generations = generations;
+        self
+    }
+
+    /// How many of the best instructions seed the next generation.
+    pub fn with_survivors(mut self, survivors: usize) -> Self {
+        self.survivors = survivors.max(1);
+        self
+    }
+
+    /// Searches for a better instruction for `node`, a sealed semantic node, over `devset`.
+    ///
+    /// If a proposal beats the node's own instruction, it is registered (unpromoted) under the
+    /// node's signature and instruction hashes, so sealing the node with the registry applies
+    /// it once promoted. The record keeps the proposal's own instruction hash as
+    /// `optimized_instruction_hash`, so it can be looked up by either hash.
+    pub async fn optimize(
+        &self,
+        node: &Arc<SealedNode>,
+        devset: &[Example],
+        registry: &dyn RegistryStore,
+    ) -> Result<InstructionReport, OptimizeError> {
+        let not_promptable = || OptimizeError::NotPromptable(node.task_id().to_string());
+        let Executable::Async(inner) = node.inner() else {
+            return Err(not_promptable());
+        };
+        let promptable = inner.behaviour.as_promptable().ok_or_else(not_promptable)?;
+        let instruction = promptable.instruction().ok_or_else(not_promptable)?;
+
+        let mut candidates = Vec::new();
+        let base = self.evaluate(node.clone(), devset).await;
+        candidates.push(InstructionCandidate {
+            instruction: instruction.to_string(),
+            instruction_hash: node.instruction_hash().to_string(),
+            fitness: base,
+            generation: 0,
+        });
+
+        for generation in 1..=self.generations {
+            let mut ranked: Vec<&InstructionCandidate> = candidates.iter().collect();
+            ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+            ranked.truncate(self.survivors);
+            let prompt = self.proposal_prompt(node, &ranked);
+
+            let completion = self.proposer.dispatch_complete(&[Turn::User(prompt)], None).await?;
+            let proposal: Proposal = extract_object(&completion.text)
+                .map_err(|e| OptimizeError::InvalidProposal(e.to_string()))
+                .and_then(|object| {
+                    serde_json::from_value(Value::Object(object))
+                        .map_err(|e| OptimizeError::InvalidProposal(e.to_string()))
+                })?;
+
+            for instruction in proposal.instructions.into_iter().take(self.candidates) {
+                if candidates.iter().any(|c| c.instruction == instruction) {
+                    continue;
+                }
+                let variant = promptable.with_instruction(&instruction).ok_or_else(not_promptable)?;
+                let variant = Arc::new(variant);
+                let fitness = self.evaluate(variant.clone(), devset).await;
+                candidates.push(InstructionCandidate {
+                    instruction,
+                    instruction_hash: variant.instruction_hash().to_string(),
+                    fitness,
+                    generation,
+                });
+            }
+        }
+
+        let winner = best(&candidates);
+        let record = if winner.generation > 0 && winner.fitness > base {
+            let mut record = OptimizationRecord {
+                task_id: node.task_id().to_string(),
+                version: 0,
+                active: false,
+                signature_hash: node.signature_hash().to_string(),
+                instruction_hash: node.instruction_hash().to_string(),
+                optimized_instruction_hash: Some(winner.instruction_hash.clone()),
+                instruction: Some(winner.instruction.clone()),
+                demos: Vec::new(),
+                training_hash: Some(hash_json(&devset)),
+                optimization_config_hash: Some(hash_json(&Config {
+                    optimizer: "instruction_search",
+                    candidates: self.candidates,
+                    generations: self.generations,
+                    survivors: self.survivors,
+                })),
+                fitness_score: Some(winner.fitness),
+                weights_path: None,
+                created_at: 0,
+                updated_at: 0,
+            };
+            record.version = registry.register(record.clone())?;
+            Some(record)
+        } else {
+            None
+        };
+        Ok(InstructionReport { candidates, record })
+    }
+
+    /// Mean score of `node` over `devset`.
+    async fn evaluate(&self, node: Arc<SealedNode>, devset: &[Example]) -> f64 {
+        let flow = AsyncFlow::new(Executable::Sealed(node));
+        let report = Evaluator::new()
+            .with_shared_metric("metric", self.metric.clone())
+            .evaluate(&flow, devset)
+            .await;
+        report.mean("metric").unwrap_or(0.0)
+    }
+
+    fn proposal_prompt(&self, node: &SealedNode, ranked: &[&InstructionCandidate]) -> String {
+        let mut prompt = format!(
+            "You are improving the instruction of an LLM task with the signature `{}`.\n\n",
+            node.signature
+        );
+        prompt.push_str("Instructions tried so far, with their score between 0 and 1:\n");
+        for candidate in ranked {
+            prompt.push_str(&format!("- {:.2}: {}\n", candidate.fitness, candidate.instruction));
+        }
+        prompt.push_str(&format!(
+            "\nWrite {} new instructions likely to score higher.\n\
+             Respond ONLY with a valid JSON object: {{\"instructions\": [\"...\"]}}\n",
+            self.candidates
+        ));
+        prompt
+    }
+}
+
+fn best(candidates: &[InstructionCandidate]) -> &InstructionCandidate {
+    candidates
+        .iter()
+        .reduce(|best, c| if c.fitness > best.fitness { c } else { best })
+        .expect("the node's own instruction is always a candidate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::semantic::registry::OptimizationRegistry;
+    use crate::core::sync_impl::NodeValue;
+    use crate::llm::mock;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn exact_match(example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        let right = example.expected.iter().all(|(key, value)| shared.get(key) == Some(value));
+        if right { 1.0 } else { 0.0 }
+    }
+
+    #[tokio::test]
+    async fn test_registers_the_best_proposal() {
+        // Proposes two rewrites; only "Answer with digits." makes the model answer right.
+        let host = mock::ollama(|messages| {
+            let prompt = mock::last_content(messages);
+            if prompt.contains("Instructions tried so far") {
+                let proposal = json!({"instructions": ["Answer in words.", "Answer with digits."]});
+                return format!("Here are my proposals:\n```json\n{}\n```", proposal);
+            }
+            let answer = if prompt.contains("Answer with digits.") { "4" } else { "four" };
+            json!({ "answer": answer }).to_string()
+        });
+        let client = Client::new().with_ollama_at(host);
+        let Executable::Sealed(node) = client
+            .semantic_node()
+            .signature(crate::signature!("question -> answer"))
+            .instruction("Answer the question.")
+            .task_id("answer")
+            .seal()
+        else {
+            unreachable!()
+        };
+        let devset = [Example::new(
+            HashMap::from([("question".to_string(), json!("2+2?"))]),
+            HashMap::from([("answer".to_string(), json!("4"))]),
+        )];
+        let registry = OptimizationRegistry::new();
+
+        let report = InstructionOptimizer::new(client, exact_match)
+            .with_generations(2)
+            .optimize(&node, &devset, &registry)
+            .await
+            .unwrap();
+
+        // The second generation proposes the same instructions again, which are not re-run.
+        assert_eq!(report.candidates.len(), 3);
+        assert_eq!(report.best().instruction, "Answer with digits.");
+        assert_ne!(report.best().instruction_hash, node.instruction_hash());
+
+        let record = report.record.clone().unwrap();
+        assert_eq!(record.instruction.as_deref(), Some("Answer with digits."));
+        assert_eq!(record.instruction_hash, node.instruction_hash());
+        assert_eq!(record.optimized_instruction_hash.as_ref(), Some(&report.best().instruction_hash));
+        assert_eq!(record.fitness_score, Some(1.0));
+        assert!(record.optimization_config_hash.is_some());
+        assert_eq!(registry.versions("answer").unwrap().len(), 1);
+
+        registry.promote("answer", record.version).unwrap();
+        for hash in [node.instruction_hash(), report.best().instruction_hash.as_str()] {
+            let found = registry.find_best_match(node.signature_hash(), hash).unwrap().unwrap();
+            assert_eq!(found.version, record.version);
+        }
+    }
+}
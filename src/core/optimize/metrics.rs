@@ -0,0 +1,204 @@
+//! Ready-made metrics comparing the keys an example expects with the shared state a run ended
+//! with. All of them score the fraction of expected keys that match, 1.0 if none are expected.
+
+use super::{Example, Metric};
+use crate::core::semantic::extract::extract_object;
+use crate::core::sync_impl::NodeValue;
+use crate::llm::{Client, Turn};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// Expected values must be equal to the actual ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExactMatch;
+
+/// Expected numbers (or numeric strings) must be within `tolerance` of the actual ones.
+#[derive(Debug, Clone, Copy)]
+pub struct NumericTolerance {
+    pub tolerance: f64,
+}
+
+/// Expected values must be contained in the actual ones: objects may have extra keys, at any
+/// depth; arrays must have the same length, element by element.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSubset;
+
+/// Asks a model to grade each example against `criteria`, as a score between 0 and 1.
+/// Answers that cannot be read as a score count as 0.
+pub struct LlmJudge<S> {
+    client: Client<S>,
+    criteria: String,
+    model: Option<String>,
+}
+
+impl NumericTolerance {
+    pub fn new(tolerance: f64) -> Self {
+        Self { tolerance }
+    }
+}
+
+impl<S> LlmJudge<S> {
+    pub fn new(client: Client<S>, criteria: impl Into<String>) -> Self {
+        Self {
+            client,
+            criteria: criteria.into(),
+            model: None,
+        }
+    }
+
+    /// Grades with `model` instead of the client's default model.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+}
+
+/// The fraction of expected keys for which `matches(expected, actual)` holds.
+fn per_key(
+    example: &Example,
+    shared: &HashMap<String, NodeValue>,
+    matches: impl Fn(&Value, &Value) -> bool,
+) -> f64 {
+    if example.expected.is_empty() {
+        return 1.0;
+    }
+    let matching = example
+        .expected
+        .iter()
+        .filter(|(key, expected)| shared.get(*key).is_some_and(|actual| matches(expected, actual)))
+        .count();
+    matching as f64 / example.expected.len() as f64
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn is_subset(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).is_some_and(|a| is_subset(value, a))),
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| is_subset(e, a))
+        }
+        _ => expected == actual,
+    }
+}
+
+#[async_trait]
+impl Metric for ExactMatch {
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        per_key(example, shared, |expected, actual| expected == actual)
+    }
+}
+
+#[async_trait]
+impl Metric for NumericTolerance {
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        per_key(example, shared, |expected, actual| match (number(expected), number(actual)) {
+            (Some(expected), Some(actual)) => (expected - actual).abs() <= self.tolerance,
+            _ => false,
+        })
+    }
+}
+
+#[async_trait]
+impl Metric for JsonSubset {
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        per_key(example, shared, is_subset)
+    }
+}
+
+#[async_trait]
+impl<S> Metric for LlmJudge<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        let actual: HashMap<&String, &NodeValue> = example
+            .expected
+            .keys()
+            .map(|key| (key, shared.get(key).unwrap_or(&Value::Null)))
+            .collect();
+        let prompt = format!(
+            "You are grading the output of a task.\n\nCriteria: {}\n\nInputs:\n{}\n\n\
+             Expected:\n{}\n\nActual:\n{}\n\n\
+             Respond ONLY with a valid JSON object: {{\"score\": <number between 0 and 1>}}\n",
+            self.criteria,
+            json!(example.inputs),
+            json!(example.expected),
+            json!(actual)
+        );
+        let completion = match self
+            .client
+            .dispatch_complete(&[Turn::User(prompt)], self.model.clone())
+            .await
+        {
+            Ok(completion) => completion,
+            Err(e) => {
+                log::warn!("LLM judge failed: {}", e);
+                return 0.0;
+            }
+        };
+        match extract_object(&completion.text)
+            .ok()
+            .and_then(|answer| answer.get("score").and_then(number))
+        {
+            Some(score) => score.clamp(0.0, 1.0),
+            None => {
+                log::warn!("LLM judge returned no score: {}", completion.text);
+                0.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock;
+
+    fn example(expected: Value) -> Example {
+        let Value::Object(expected) = expected else { unreachable!() };
+        Example::new(HashMap::new(), expected.into_iter().collect())
+    }
+
+    fn shared(actual: Value) -> HashMap<String, NodeValue> {
+        let Value::Object(actual) = actual else { unreachable!() };
+        actual.into_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let expected = example(json!({"total": 10, "label": "ok"}));
+        let actual = shared(json!({"total": "10.004", "label": "ok"}));
+        assert_eq!(ExactMatch.score(&expected, &actual).await, 0.5);
+        assert_eq!(NumericTolerance::new(0.01).score(&expected, &actual).await, 0.5);
+        assert_eq!(ExactMatch.score(&example(json!({})), &actual).await, 1.0);
+
+        let expected = example(json!({"user": {"name": "ada", "tags": [{"id": 1}]}}));
+        let actual = shared(json!({"user": {"name": "ada", "age": 36, "tags": [{"id": 1, "x": 0}]}}));
+        assert_eq!(JsonSubset.score(&expected, &actual).await, 1.0);
+        let actual = shared(json!({"user": {"name": "ada", "tags": []}}));
+        assert_eq!(JsonSubset.score(&expected, &actual).await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_llm_judge() {
+        let host = mock::ollama(|messages| {
+            let prompt = mock::last_content(messages);
+            let score = if prompt.contains(r#"{"answer":"four"}"#) { 0.8 } else { 0.0 };
+            json!({ "score": score }).to_string()
+        });
+        let judge = LlmJudge::new(Client::new().with_ollama_at(host), "Same meaning counts.");
+        let expected = example(json!({"answer": "4"}));
+        assert_eq!(judge.score(&expected, &shared(json!({"answer": "four"}))).await, 0.8);
+        assert_eq!(judge.score(&expected, &shared(json!({}))).await, 0.0);
+    }
+}
@@ -0,0 +1,87 @@
+//! Optimizers for sealed semantic nodes.
+//!
+//! Optimizers run a flow over a training set, score each run with a [`Metric`], and register what
+//! they learn in a [`RegistryStore`](crate::core::semantic::registry::RegistryStore). Records are
+//! registered without being promoted, so nothing reaches production until someone promotes them.
+
+pub mod bootstrap;
+pub mod dataset;
+pub mod evaluate;
+pub mod instruction;
+pub mod metrics;
+
+use crate::core::semantic::registry::RegistryError;
+use crate::core::sync_impl::NodeValue;
+use crate::LLMError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// A training or evaluation example.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    /// The shared state the flow starts from.
+    pub inputs: HashMap<String, NodeValue>,
+    /// The values the flow is expected to leave in the shared state.
+    #[serde(default)]
+    pub expected: HashMap<String, NodeValue>,
+}
+
+impl Example {
+    pub fn new(inputs: HashMap<String, NodeValue>, expected: HashMap<String, NodeValue>) -> Self {
+        Self { inputs, expected }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum OptimizeError {
+    #[error("Node '{0}' is not a semantic node with an instruction")]
+    NotPromptable(String),
+
+    #[error("LLM error: {0}")]
+    Llm(#[from] LLMError),
+
+    #[error("Invalid proposal from the model: {0}")]
+    InvalidProposal(String),
+
+    #[error("Registry error: {0}")]
+    Registry(#[from] RegistryError),
+}
+
+/// Scores the shared state a run ended with against its example.
+#[async_trait]
+pub trait Metric: Send + Sync {
+    /// A score between 0 (wrong) and 1 (right).
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64;
+}
+
+#[async_trait]
+impl<F> Metric for F
+where
+    F: Fn(&Example, &HashMap<String, NodeValue>) -> f64 + Send + Sync,
+{
+    async fn score(&self, example: &Example, shared: &HashMap<String, NodeValue>) -> f64 {
+        self(example, shared)
+    }
+}
+
+/// Stable SHA-256 of the JSON form of `value`, for `training_hash`/`optimization_config_hash`.
+///
+/// JSON objects are written with sorted keys, so maps hash the same whatever their order.
+pub(crate) fn hash_json(value: &impl Serialize) -> String {
+    let json = serde_json::to_value(value)
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+    let digest = Sha256::digest(json.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn mean(scores: &[f64]) -> f64 {
+    if scores.is_empty() {
+        0.0
+    } else {
+        scores.iter().sum::<f64>() / scores.len() as f64
+    }
+}
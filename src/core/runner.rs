@@ -0,0 +1,260 @@
+//! How flows run each of their nodes.
+//!
+//! Every node run inside a flow gets a telemetry span and the flow's middleware. Nested flows
+//! aren't wrapped by the middleware themselves: the chain, the telemetry and the span are handed
+//! down to them so that their own nodes are wrapped and recorded instead.
+
+use crate::core::async_impl::async_flow::AsyncFlowLogic;
+use crate::core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
+use crate::core::graph::display_name;
+use crate::core::middleware::{self, NodeMiddleware};
+use crate::core::sealed::SealedNode;
+use crate::core::semantic::redaction;
+use crate::core::semantic::Sealable;
+use crate::core::sync_impl::flow::FlowLogic;
+use crate::core::sync_impl::node::{Node, NodeLogic};
+use crate::core::sync_impl::NodeValue;
+use crate::core::telemetry::{Span, SpanContext, SpanKind, Telemetry, TraceEntry};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// What a flow hands to each of its nodes.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct RunEnv<'a> {
+    pub(crate) telemetry: Option<&'a Arc<dyn Telemetry>>,
+    pub(crate) middleware: &'a [Arc<dyn NodeMiddleware>],
+    /// The span of the flow the node runs in, `None` for a new run.
+    pub(crate) parent: Option<&'a SpanContext>,
+}
+
+impl RunEnv<'_> {
+    fn recorder(&self) -> Option<&dyn Telemetry> {
+        self.telemetry.map(|t| t.as_ref())
+    }
+
+    /// Settings of a nested flow once it inherited this environment.
+    fn hand_down(
+        &self,
+        telemetry: &mut Option<Arc<dyn Telemetry>>,
+        middleware: &mut Vec<Arc<dyn NodeMiddleware>>,
+        span: &mut Option<SpanContext>,
+        context: SpanContext,
+    ) {
+        if telemetry.is_none() {
+            *telemetry = self.telemetry.cloned();
+        }
+        *middleware = [self.middleware, middleware.as_slice()].concat();
+        *span = Some(context);
+    }
+}
+
+pub(crate) fn run_node(
+    node: &Node,
+    shared: &mut HashMap<String, NodeValue>,
+    env: RunEnv,
+) -> Option<String> {
+    let span = Span::start(env.parent);
+    let behaviour: &dyn NodeLogic = &*node.behaviour;
+    let sealable = behaviour.as_sealable();
+    let task_id = sealable.map(|s| s.task_id());
+    let name = task_id.unwrap_or_else(|| display_name(behaviour.type_name()));
+
+    if behaviour.as_any().is::<FlowLogic>() {
+        let mut nested = node.clone();
+        if let Some(logic) = nested.behaviour.as_any_mut().downcast_mut::<FlowLogic>() {
+            env.hand_down(
+                &mut logic.telemetry,
+                &mut logic.middleware,
+                &mut logic.span,
+                span.children(),
+            );
+        }
+        let (_, _, action) = middleware::run_sync_phases(&nested, shared, &[]);
+        record_flow(env.recorder(), span, name, &action);
+        return action;
+    }
+
+    let (p, e, action) = middleware::run_sync_phases(node, shared, env.middleware);
+    record_node(env.recorder(), span, sealable, name, p, e, &action);
+    action
+}
+
+pub(crate) async fn run_async_node(
+    node: &AsyncNode,
+    shared: &mut HashMap<String, NodeValue>,
+    env: RunEnv<'_>,
+) -> Option<String> {
+    let span = Span::start(env.parent);
+    let behaviour: &dyn AsyncNodeLogic = &*node.behaviour;
+    let sealable = behaviour.as_sealable();
+    let task_id = sealable.map(|s| s.task_id());
+    let name = task_id.unwrap_or_else(|| display_name(behaviour.type_name()));
+
+    if behaviour.as_any().is::<AsyncFlowLogic>() {
+        let mut nested = node.clone();
+        if let Some(logic) = nested.behaviour.as_any_mut().downcast_mut::<AsyncFlowLogic>() {
+            env.hand_down(
+                &mut logic.telemetry,
+                &mut logic.middleware,
+                &mut logic.span,
+                span.children(),
+            );
+        }
+        let (_, _, action) = middleware::run_async_phases(&nested, shared, &[]).await;
+        record_flow(env.recorder(), span, name, &action);
+        return action;
+    }
+
+    let (p, e, action) = middleware::run_async_phases(node, shared, env.middleware).await;
+    record_node(env.recorder(), span, sealable, name, p, e, &action);
+    action
+}
+
+pub(crate) async fn run_sealed(
+    sealed: &SealedNode,
+    shared: &mut HashMap<String, NodeValue>,
+    env: RunEnv<'_>,
+) -> Option<String> {
+    sealed
+        .run_traced(shared, env.recorder(), env.middleware, env.parent)
+        .await
+}
+
+/// Records the span of a node that isn't sealed, applying the redactions of its signature if it
+/// has one.
+pub(crate) fn record_node(
+    telemetry: Option<&dyn Telemetry>,
+    span: Span,
+    sealable: Option<&dyn Sealable>,
+    name: String,
+    inputs: NodeValue,
+    outputs: NodeValue,
+    action: &Option<String>,
+) {
+    let Some(t) = telemetry else {
+        return;
+    };
+    let mut entry = span.finish(TraceEntry {
+        task_id: name,
+        kind: SpanKind::Node,
+        inputs,
+        outputs,
+        action: action.clone(),
+        ..Default::default()
+    });
+    if let Some(sealable) = sealable {
+        redaction::redact(&sealable.signature(), &mut entry);
+    }
+    t.record(entry);
+}
+
+/// Records the span of a nested flow.
+fn record_flow(telemetry: Option<&dyn Telemetry>, span: Span, name: String, action: &Option<String>) {
+    if let Some(t) = telemetry {
+        t.record(span.finish(TraceEntry {
+            task_id: name,
+            kind: SpanKind::Flow,
+            action: action.clone(),
+            ..Default::default()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::semantic::signature::Signature;
+    use crate::core::semantic::Sealable;
+    use crate::core::telemetry::MemoryTelemetry;
+    use crate::core::Executable;
+    use serde_json::json;
+
+    #[derive(Clone)]
+    struct Step(&'static str);
+
+    impl NodeLogic for Step {
+        fn prep(
+            &self,
+            _params: &HashMap<String, NodeValue>,
+            _shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            json!(self.0)
+        }
+
+        fn post(
+            &self,
+            shared: &mut HashMap<String, NodeValue>,
+            prep_res: NodeValue,
+            _exec_res: NodeValue,
+        ) -> Option<String> {
+            shared.insert(self.0.to_string(), prep_res);
+            Some("next".to_string())
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+
+        fn as_sealable(&self) -> Option<&dyn Sealable> {
+            Some(self)
+        }
+    }
+
+    impl Sealable for Step {
+        fn signature(&self) -> Signature {
+            Signature::new()
+        }
+
+        fn task_id(&self) -> String {
+            self.0.to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spans_form_the_execution_tree() {
+        // outer: a --next--> inner[b] --next--> sealed c
+        let sealed = Node::new(Step("c")).seal().unwrap();
+        let inner = AsyncFlow::new(Executable::Sync(Node::new(Step("b"))));
+        let inner = match Executable::from(inner) {
+            Executable::Async(node) => node.next_on("next", Executable::Sealed(sealed)),
+            _ => unreachable!(),
+        };
+        let outer = AsyncFlow::new(Executable::Sync(
+            Node::new(Step("a")).next_on("next", Executable::Async(inner)),
+        ));
+
+        let telemetry = Arc::new(MemoryTelemetry::new());
+        let mut shared = HashMap::new();
+        outer.run_with_telemetry(&mut shared, Some(telemetry.clone())).await;
+
+        let traces = telemetry.get_traces();
+        let span = |task_id: &str| {
+            traces
+                .iter()
+                .find(|t| t.task_id == task_id && t.kind != SpanKind::Flow)
+                .unwrap_or_else(|| panic!("no span for {}", task_id))
+        };
+        let flows: Vec<&TraceEntry> = traces.iter().filter(|t| t.kind == SpanKind::Flow).collect();
+        assert_eq!(traces.len(), 5);
+        assert_eq!(flows.len(), 2);
+
+        let root = flows.iter().find(|t| t.parent_span_id.is_none()).unwrap();
+        let nested = flows.iter().find(|t| t.parent_span_id.is_some()).unwrap();
+        assert!(traces.iter().all(|t| t.run_id == root.run_id));
+        assert_eq!(nested.parent_span_id.as_ref(), Some(&root.span_id));
+        assert_eq!(span("a").parent_span_id.as_ref(), Some(&root.span_id));
+        assert_eq!(span("b").parent_span_id.as_ref(), Some(&nested.span_id));
+        assert_eq!(span("c").parent_span_id.as_ref(), Some(&root.span_id));
+
+        assert_eq!(span("a").action.as_deref(), Some("next"));
+        assert_eq!(span("c").kind, SpanKind::Sealed);
+        assert_eq!(span("b").inputs, json!("b"));
+        assert_eq!(root.action.as_deref(), Some("next"));
+        for trace in &traces {
+            assert_eq!(trace.end_time_us - trace.start_time_us, trace.duration_us);
+            assert!(root.start_time_us <= trace.start_time_us);
+            assert!(trace.end_time_us <= root.end_time_us);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::core::sync_impl::NodeValue;
+use crate::core::validation::ValidationResult;
+use std::collections::{HashMap, HashSet};
+
+/// Explicit key mapping between a parent flow and a nested flow running in its own namespace.
+///
+/// Think of it as function arguments: the nested flow only sees the `inputs` it was handed
+/// (under their child names), and only the `outputs` are written back to the parent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlowScope {
+    /// (parent key, child key) pairs copied into the nested flow before it runs.
+    pub inputs: Vec<(String, String)>,
+    /// (child key, parent key) pairs copied back into the parent once the nested flow is done.
+    pub outputs: Vec<(String, String)>,
+}
+
+impl FlowScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exposes the parent's `parent_key` to the nested flow as `child_key`.
+    pub fn input(mut self, parent_key: impl Into<String>, child_key: impl Into<String>) -> Self {
+        self.inputs.push((parent_key.into(), child_key.into()));
+        self
+    }
+
+    /// Writes the nested flow's `child_key` back to the parent as `parent_key`.
+    pub fn output(mut self, child_key: impl Into<String>, parent_key: impl Into<String>) -> Self {
+        self.outputs.push((child_key.into(), parent_key.into()));
+        self
+    }
+
+    /// Builds the nested flow's shared state out of the parent's.
+    pub(crate) fn enter(
+        &self,
+        parent: &HashMap<String, NodeValue>,
+    ) -> HashMap<String, NodeValue> {
+        let mut child = HashMap::new();
+        for (parent_key, child_key) in &self.inputs {
+            match parent.get(parent_key) {
+                Some(value) => {
+                    child.insert(child_key.clone(), value.clone());
+                }
+                None => log::warn!(
+                    "Scoped sub-flow input '{}' is missing from the parent shared state.",
+                    parent_key
+                ),
+            }
+        }
+        child
+    }
+
+    /// Copies the mapped outputs of the nested flow back into the parent's shared state.
+    pub(crate) fn exit(
+        &self,
+        child: &HashMap<String, NodeValue>,
+        parent: &mut HashMap<String, NodeValue>,
+    ) {
+        for (child_key, parent_key) in &self.outputs {
+            match child.get(child_key) {
+                Some(value) => {
+                    parent.insert(parent_key.clone(), value.clone());
+                }
+                None => log::warn!(
+                    "Scoped sub-flow did not produce output '{}' (mapped to '{}').",
+                    child_key,
+                    parent_key
+                ),
+            }
+        }
+    }
+
+    /// Reports mappings that would silently clobber each other.
+    pub(crate) fn check(&self, result: &mut ValidationResult) {
+        let mut seen = HashSet::new();
+        for (_, child_key) in &self.inputs {
+            if !seen.insert(child_key) {
+                result.add_error(format!(
+                    "Scoped sub-flow maps several parent keys onto input '{}'.",
+                    child_key
+                ));
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for (_, parent_key) in &self.outputs {
+            if !seen.insert(parent_key) {
+                result.add_error(format!(
+                    "Scoped sub-flow maps several outputs onto parent key '{}'.",
+                    parent_key
+                ));
+            }
+        }
+    }
+}
@@ -1,9 +1,14 @@
 use crate::core::Executable;
 use crate::core::sync_impl::NodeValue;
+use crate::core::sync_impl::node::NodeCore;
 use std::collections::HashMap;
-use crate::core::telemetry::Telemetry;
-use crate::core::semantic::Sealable;
+use crate::core::telemetry::{usage, Span, SpanContext, SpanKind, Telemetry, TraceEntry};
+use crate::core::semantic::{Promptable, Sealable};
+use crate::core::semantic::redaction;
+use crate::core::semantic::registry::{OptimizationRecord, RegistryStore};
 use crate::core::semantic::signature::Signature;
+use crate::core::middleware::{self, NodeMiddleware};
+use std::sync::Arc;
 use futures::future::BoxFuture;
 
 /// An immutable wrapper that encapsulates any executable unit.
@@ -21,6 +26,9 @@ pub struct SealedNode {
     pub optimization_config_hash: Option<String>,
     pub fitness_score: Option<f64>,
     pub weights_path: Option<std::path::PathBuf>,
+    pub(crate) applied: Option<OptimizationRecord>,
+    /// The instruction hash of the prompt actually sent, when an applied record changed it.
+    pub(crate) optimized_instruction_hash: Option<String>,
 }
 
 impl SealedNode {
@@ -44,9 +52,73 @@ impl SealedNode {
             optimization_config_hash: None,
             fitness_score: None,
             weights_path: None,
+            applied: None,
+            optimized_instruction_hash: None,
         }
     }
 
+    /// Applies the best promoted record matching this node's hashes in `registry`, if any.
+    ///
+    /// The record's instruction replaces the node's through [`Promptable::with_instruction`];
+    /// its demos are only applied by builders that consult the registry before sealing, such as
+    /// `SemanticNodeBuilder::registry`. The node keeps its base hashes, which identify it in the
+    /// registry, and its successors and params.
+    pub fn optimized(mut self, registry: &dyn RegistryStore) -> Self {
+        let Some(record) = lookup(registry, &self.signature_hash, &self.instruction_hash) else {
+            return self;
+        };
+        if let Some(instruction) = &record.instruction {
+            match self.promptable().and_then(|p| p.with_instruction(instruction)) {
+                Some(rebuilt) => self.adopt(rebuilt),
+                None => log::warn!(
+                    "'{}' cannot take the instruction of registry record v{}; applying its metadata only",
+                    self.task_id,
+                    record.version
+                ),
+            }
+        }
+        self.apply_record(record);
+        self
+    }
+
+    fn promptable(&self) -> Option<&dyn Promptable> {
+        match &self.inner {
+            Executable::Sync(node) => node.behaviour.as_promptable(),
+            Executable::Async(node) => node.behaviour.as_promptable(),
+            Executable::Sealed(_) => None,
+        }
+    }
+
+    /// Runs the logic of `rebuilt` in place of this node's, keeping the node's core.
+    fn adopt(&mut self, rebuilt: SealedNode) {
+        let mut inner = rebuilt.inner;
+        let (Some(new), Some(old)) = (core_mut(&mut inner), core_mut(&mut self.inner)) else {
+            return;
+        };
+        std::mem::swap(new, old);
+        self.inner = inner;
+        self.set_effective_instruction_hash(rebuilt.instruction_hash);
+    }
+
+    /// Records `hash` as the hash of the prompt sent, if it differs from the base hash.
+    pub(crate) fn set_effective_instruction_hash(&mut self, hash: String) {
+        self.optimized_instruction_hash = (hash != self.instruction_hash).then_some(hash);
+    }
+
+    /// Fills the optimization slots from `record` and remembers it was applied.
+    pub(crate) fn apply_record(&mut self, record: OptimizationRecord) {
+        self.training_hash = record.training_hash.clone();
+        self.optimization_config_hash = record.optimization_config_hash.clone();
+        self.fitness_score = record.fitness_score;
+        self.weights_path = record.weights_path.clone();
+        self.applied = Some(record);
+    }
+
+    /// The registry record applied when the node was sealed.
+    pub fn applied_record(&self) -> Option<&OptimizationRecord> {
+        self.applied.as_ref()
+    }
+
     pub fn task_id(&self) -> &str {
         &self.task_id
     }
@@ -55,10 +127,16 @@ impl SealedNode {
         &self.signature_hash
     }
 
+    /// The hash of the base instruction, under which the node is found in the registry.
     pub fn instruction_hash(&self) -> &str {
         &self.instruction_hash
     }
 
+    /// The hash of the prompt sent once an applied record changed it. Traces carry this hash.
+    pub fn optimized_instruction_hash(&self) -> Option<&str> {
+        self.optimized_instruction_hash.as_deref()
+    }
+
     pub fn model_name(&self) -> &str {
         &self.model_name
     }
@@ -68,62 +146,102 @@ impl SealedNode {
         &'a self,
         shared: &'a mut HashMap<String, NodeValue>,
         telemetry: Option<&'a dyn Telemetry>,
+    ) -> BoxFuture<'a, Option<String>> {
+        self.run_traced(shared, telemetry, &[], None)
+    }
+
+    /// Executes the sealed node with the hooks of `chain` around each phase, recording its span
+    /// under `parent`.
+    pub(crate) fn run_traced<'a>(
+        &'a self,
+        shared: &'a mut HashMap<String, NodeValue>,
+        telemetry: Option<&'a dyn Telemetry>,
+        chain: &'a [Arc<dyn NodeMiddleware>],
+        parent: Option<&'a SpanContext>,
     ) -> BoxFuture<'a, Option<String>> {
         Box::pin(async move {
-            // We need to capture the results for telemetry
-            // This is easier if we call the phases directly or if run() returns them.
-            // For now, we'll let the inner run() do its work, and we'll record telemetry here
-            // but we need the inputs/outputs.
-            
+            let span = Span::start(parent);
+
             // Re-implementing the run loop here to capture I/O
-            let (p, e, action) = match &self.inner {
+            let ((p, e, action), calls) = match &self.inner {
                 Executable::Sync(node) => {
-                    let p = node.behaviour.prep(&node.data.params, shared);
-                    let e = node.behaviour.exec(p.clone());
-                    let action = node.behaviour.post(shared, p.clone(), e.clone());
-                    (p, e, action)
+                    usage::collect(async { middleware::run_sync_phases(node, shared, chain) }).await
                 }
                 Executable::Async(node) => {
-                    let p = node.behaviour.prep(&node.data.params, shared).await;
-                    let e = node.behaviour.exec(p.clone()).await;
-                    let action = node.behaviour.post(shared, p.clone(), e.clone()).await;
-                    (p, e, action)
+                    usage::collect(middleware::run_async_phases(node, shared, chain)).await
                 }
                 Executable::Sealed(sealed) => {
                     // Nested sealed nodes will record their own telemetry
-                    return sealed.run(shared, telemetry).await;
+                    return sealed.run_traced(shared, telemetry, chain, parent).await;
                 }
             };
 
             if let Some(t) = telemetry {
-                let timestamp = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-
-                t.record(crate::core::telemetry::TraceEntry {
-                    timestamp,
+                let mut entry = span.finish(TraceEntry {
                     task_id: self.task_id.clone(),
                     signature_hash: self.signature_hash.clone(),
-                    instruction_hash: self.instruction_hash.clone(),
+                    instruction_hash: self
+                        .optimized_instruction_hash
+                        .clone()
+                        .unwrap_or_else(|| self.instruction_hash.clone()),
                     inputs: p,
                     outputs: e,
                     model_name: self.model_name.clone(),
                     training_hash: self.training_hash.clone(),
                     fitness_score: self.fitness_score,
-                    metadata: HashMap::new(),
+                    kind: SpanKind::Sealed,
+                    action: action.clone(),
+                    usage: calls.usage,
+                    llm_latency_us: (calls.count > 0).then_some(calls.latency.as_micros() as u64),
+                    cost: calls.cost,
+                    ..Default::default()
                 });
+                if let Some(record) = &self.applied {
+                    entry.metadata.insert("optimization.task_id".to_string(), record.task_id.clone());
+                    entry.metadata.insert("optimization.version".to_string(), record.version.to_string());
+                    if self.optimized_instruction_hash.is_some() {
+                        entry.metadata.insert(
+                            "optimization.base_instruction_hash".to_string(),
+                            self.instruction_hash.clone(),
+                        );
+                    }
+                }
+                redaction::redact(&self.signature, &mut entry);
+                t.record(entry);
             }
 
             action
         })
     }
-    
+
     pub fn inner(&self) -> &Executable {
         &self.inner
     }
 }
 
+fn core_mut(executable: &mut Executable) -> Option<&mut NodeCore> {
+    match executable {
+        Executable::Sync(node) => Some(&mut node.data),
+        Executable::Async(node) => Some(&mut node.data),
+        Executable::Sealed(_) => None,
+    }
+}
+
+/// The best promoted record for the given hashes. Lookup failures are logged and treated as
+/// no match, so a registry outage never prevents sealing.
+pub(crate) fn lookup(
+    registry: &dyn RegistryStore,
+    signature_hash: &str,
+    instruction_hash: &str,
+) -> Option<OptimizationRecord> {
+    registry
+        .find_best_match(signature_hash, instruction_hash)
+        .unwrap_or_else(|e| {
+            log::warn!("Optimization registry lookup failed, sealing unoptimized: {}", e);
+            None
+        })
+}
+
 impl Sealable for SealedNode {
     fn signature(&self) -> Signature {
         self.signature.clone()
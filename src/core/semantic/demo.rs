@@ -0,0 +1,74 @@
+use crate::core::semantic::signature::Signature;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// An input/output example shown to the model before the actual input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Demo {
+    /// A JSON object with one key per signature input.
+    pub inputs: Value,
+    /// A JSON object with one key per signature output.
+    pub outputs: Value,
+}
+
+/// How demos are presented to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum DemoFormat {
+    /// Listed as examples in the prompt, between the instruction and the input.
+    #[default]
+    Prompt,
+    /// Sent as prior user/assistant turns, each answered with the demo's outputs.
+    ChatTurns,
+}
+
+impl Demo {
+    pub fn new(inputs: Value, outputs: Value) -> Self {
+        Self { inputs, outputs }
+    }
+
+    /// Checks that the demo has exactly the fields of `signature`.
+    pub fn check(&self, signature: &Signature) -> Result<(), String> {
+        let sides = [
+            ("input", &self.inputs, &signature.inputs),
+            ("output", &self.outputs, &signature.outputs),
+        ];
+        for (side, value, fields) in sides {
+            let Value::Object(map) = value else {
+                return Err(format!("{}s must be a JSON object", side));
+            };
+            if let Some(field) = fields.iter().find(|f| !map.contains_key(&f.name)) {
+                return Err(format!("missing {} '{}'", side, field.name));
+            }
+            if let Some(key) = map.keys().find(|k| !fields.iter().any(|f| &f.name == *k)) {
+                return Err(format!("unknown {} '{}'", side, key));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_check_against_signature() {
+        let signature = crate::signature!("question -> answer");
+        let demo = |inputs, outputs| Demo::new(inputs, outputs).check(&signature);
+
+        assert_eq!(demo(json!({"question": "2+2?"}), json!({"answer": "4"})), Ok(()));
+        assert_eq!(
+            demo(json!({}), json!({"answer": "4"})),
+            Err("missing input 'question'".to_string())
+        );
+        assert_eq!(
+            demo(json!({"question": "2+2?"}), json!({"answer": "4", "why": "math"})),
+            Err("unknown output 'why'".to_string())
+        );
+        assert_eq!(
+            demo(json!("2+2?"), json!({"answer": "4"})),
+            Err("inputs must be a JSON object".to_string())
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Reading a JSON object out of a model's answer.
+//!
+//! Models asked for JSON often wrap it in Markdown fences, surround it with prose, or bend the
+//! syntax a little. [`extract_object`] looks past all of that before giving up.
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("No JSON object in the response")]
+    NoObject,
+
+    #[error("Invalid JSON: {0}")]
+    Invalid(#[from] serde_json::Error),
+}
+
+/// The first JSON object in `text`.
+///
+/// Looks inside ```` ``` ```` fences first, then at the whole text, taking the first balanced
+/// `{...}` of each. An object that does not parse is retried with common mistakes fixed:
+/// trailing commas, single-quoted strings, raw newlines or tabs in strings, and Python's
+/// `True`/`False`/`None`.
+pub fn extract_object(text: &str) -> Result<Map<String, Value>, ExtractError> {
+    let objects: Vec<&str> = fenced_blocks(text)
+        .into_iter()
+        .chain([text])
+        .filter_map(first_object)
+        .collect();
+    let mut error = None;
+    for object in &objects {
+        let attempts = [serde_json::from_str(object), serde_json::from_str(&repair(object))];
+        for attempt in attempts {
+            match attempt {
+                Ok(Value::Object(map)) => return Ok(map),
+                Ok(_) => {}
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+    }
+    Err(error.map_or(ExtractError::NoObject, ExtractError::Invalid))
+}
+
+/// The contents of each ```` ``` ```` block, without the language tag.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let body_start = after.find('\n').map_or(0, |i| i + 1);
+        let Some(end) = after[body_start..].find("```") else {
+            blocks.push(&after[body_start..]);
+            break;
+        };
+        blocks.push(&after[body_start..body_start + end]);
+        rest = &after[body_start + end + 3..];
+    }
+    blocks
+}
+
+/// From the first `{` to the brace closing it, skipping braces in strings; to the end of
+/// `text` if it is never closed.
+fn first_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text[start..].char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..=start + i]);
+                }
+            }
+            (None, _) => {}
+        }
+    }
+    Some(&text[start..])
+}
+
+/// `json` with common syntax mistakes fixed.
+fn repair(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                out.push('"');
+                while let Some(s) = chars.next() {
+                    match s {
+                        '\\' => match chars.next() {
+                            Some('\'') => out.push('\''),
+                            Some(escaped) => {
+                                out.push('\\');
+                                out.push(escaped);
+                            }
+                            None => {}
+                        },
+                        s if s == c => break,
+                        '"' => out.push_str("\\\""),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        s => out.push(s),
+                    }
+                }
+                out.push('"');
+            }
+            ',' => {
+                let next = chars.clone().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}' | ']')) {
+                    out.push(',');
+                }
+            }
+            c if c.is_alphabetic() => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    word.push(c);
+                    chars.next();
+                }
+                out.push_str(match word.as_str() {
+                    "True" => "true",
+                    "False" => "false",
+                    "None" => "null",
+                    word => word,
+                });
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extract(text: &str) -> Value {
+        Value::Object(extract_object(text).unwrap())
+    }
+
+    #[test]
+    fn test_extract_object() {
+        assert_eq!(extract(r#"{"answer": "4"}"#), json!({"answer": "4"}));
+        assert_eq!(
+            extract("Sure! Here it is:\n```json\n{\"answer\": \"{4}\"}\n```\nAnything else?"),
+            json!({"answer": "{4}"})
+        );
+        assert_eq!(
+            extract(r#"The answer is {"answer": "4", "steps": [1, 2,],} as requested."#),
+            json!({"answer": "4", "steps": [1, 2]})
+        );
+        assert_eq!(
+            extract("{'answer': 'it\\'s \"4\"', 'sure': True, 'note': None, 'text': 'a\nb'}"),
+            json!({"answer": "it's \"4\"", "sure": true, "note": null, "text": "a\nb"})
+        );
+        // Words inside strings are left alone.
+        assert_eq!(extract(r#"{"answer": "True, None",}"#), json!({"answer": "True, None"}));
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        assert!(matches!(extract_object("I don't know."), Err(ExtractError::NoObject)));
+        assert!(matches!(extract_object("[1, 2]"), Err(ExtractError::NoObject)));
+        let error = extract_object(r#"{"answer": "4"#).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid JSON: EOF"), "{}", error);
+    }
+}
@@ -1,8 +1,13 @@
+pub mod demo;
+pub mod extract;
 pub mod node;
+pub mod redaction;
 pub mod registry;
 pub mod signature;
 
+use crate::core::sealed::SealedNode;
 use crate::core::sync_impl::AsAny;
+use demo::Demo;
 use signature::Signature;
 
 /// Trait for units that have a defined structural contract and global identity.
@@ -22,4 +27,25 @@ pub trait Promptable: Send + Sync {
 
     /// Returns the name of the model bound to this unit.
     fn model(&self) -> Option<&str>;
+
+    /// Returns the demonstrations shown to the model, if any.
+    fn demos(&self) -> &[Demo] {
+        &[]
+    }
+
+    /// Identifies everything this unit sends the model besides the input, as sealing and the
+    /// optimization registry see it. By default only the instruction is hashed.
+    fn instruction_hash(&self) -> String {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        self.instruction().hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Returns a sealed copy of this unit driven by `instruction` instead, if it can be rebuilt.
+    /// Optimizers use it to try instruction variants.
+    fn with_instruction(&self, _instruction: &str) -> Option<SealedNode> {
+        None
+    }
 }
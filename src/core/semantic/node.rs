@@ -1,17 +1,25 @@
 use async_trait::async_trait;
-use serde_json::{json, Value};
+use serde_json::json;
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::llm::Client;
+use crate::llm::{Client, Turn};
 use crate::LLMError;
 use crate::core::async_impl::async_node::{AsyncNodeLogic, AsyncNode};
+use crate::core::events::{self, FlowEventKind};
 use crate::core::sync_impl::NodeValue;
 use crate::core::semantic::{Sealable, Promptable};
 use crate::core::Executable;
-use crate::core::sealed::SealedNode;
+use crate::core::sealed::{self, SealedNode};
+use crate::core::semantic::registry::RegistryStore;
+use crate::core::semantic::demo::{Demo, DemoFormat};
+use crate::core::semantic::extract::extract_object;
 use crate::core::semantic::signature::Signature;
 
+/// The action a semantic node returns when the model cannot be reached or its answer cannot be
+/// read as JSON.
+pub const ERROR_ACTION: &str = "error";
+
 /// Vanilla logic for a semantic LLM node.
 #[derive(Clone)]
 pub struct SemanticLLMLogic<S> {
@@ -20,6 +28,9 @@ pub struct SemanticLLMLogic<S> {
     instruction: String,
     task_id: String,
     model_override: Option<String>,
+    demos: Vec<Demo>,
+    demo_format: DemoFormat,
+    max_reasks: usize,
 }
 
 impl<S> SemanticLLMLogic<S>
@@ -38,21 +49,49 @@ where
             instruction,
             task_id,
             model_override: None,
+            demos: Vec::new(),
+            demo_format: DemoFormat::default(),
+            max_reasks: 0,
         }
     }
 
-    fn instruction_hash(&self) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut hasher = DefaultHasher::new();
-        self.instruction.hash(&mut hasher);
-        for field in &self.signature.inputs {
-            field.description.hash(&mut hasher);
+    /// The prompt for `input`, listing the demos before it if `with_demos`.
+    fn prompt(&self, input: &NodeValue, with_demos: bool) -> String {
+        let mut prompt = format!("Task Instruction: {}\n\n", self.instruction);
+        if with_demos && !self.demos.is_empty() {
+            prompt.push_str("Examples:\n\n");
+            for demo in &self.demos {
+                prompt.push_str(&format!("Input Data:\n{}\nOutput:\n{}\n\n", demo.inputs, demo.outputs));
+            }
         }
+        prompt.push_str(&format!("Input Data:\n{}\n\n", input));
+        prompt.push_str(&self.output_keys());
+        prompt
+    }
+
+    fn output_keys(&self) -> String {
+        let mut keys =
+            "Respond ONLY with a valid JSON object matching the following output keys:\n".to_string();
         for field in &self.signature.outputs {
-            field.description.hash(&mut hasher);
+            keys.push_str(&format!("- {}: {}\n", field.name, field.description));
+        }
+        keys
+    }
+
+    /// The conversation sent for `input`.
+    fn turns(&self, input: &NodeValue) -> Vec<Turn> {
+        match self.demo_format {
+            DemoFormat::Prompt => vec![Turn::User(self.prompt(input, true))],
+            DemoFormat::ChatTurns => {
+                let mut turns = Vec::new();
+                for demo in &self.demos {
+                    turns.push(Turn::User(self.prompt(&demo.inputs, false)));
+                    turns.push(Turn::Assistant(demo.outputs.to_string()));
+                }
+                turns.push(Turn::User(self.prompt(input, false)));
+                turns
+            }
         }
-        format!("{:016x}", hasher.finish())
     }
 }
 
@@ -78,18 +117,40 @@ where
     }
 
     async fn exec(&self, input: NodeValue) -> NodeValue {
-        let mut prompt = format!("Task Instruction: {}\n\nInput Data:\n{}\n\n", self.instruction, input);
-        prompt.push_str("Respond ONLY with a valid JSON object matching the following output keys:\n");
-        for field in &self.signature.outputs {
-            prompt.push_str(&format!("- {}: {}\n", field.name, field.description));
-        }
+        let mut turns = self.turns(&input);
+        let mut reasks = 0;
+        loop {
+            let model = self.model_override.clone();
+            let result: Result<String, LLMError> = self.execute_llm(&turns, model).await;
 
-        let model = self.model_override.clone();
-        let result: Result<String, LLMError> = self.execute_llm(&prompt, model).await;
-        
-        match result {
-            Ok(json_str) => json!(json_str),
-            Err(e) => json!({ "error": e.to_string() }),
+            let json_str = match result {
+                Ok(json_str) => json_str,
+                Err(e) => {
+                    events::emit(FlowEventKind::Error { message: e.to_string() });
+                    return json!({ "error": e.to_string() });
+                }
+            };
+            events::emit(FlowEventKind::LlmChunk { text: json_str.clone() });
+            match extract_object(&json_str) {
+                Err(e) if reasks < self.max_reasks => {
+                    reasks += 1;
+                    log::warn!(
+                        "Re-asking '{}' for JSON ({}/{}): {}",
+                        self.task_id,
+                        reasks,
+                        self.max_reasks,
+                        e
+                    );
+                    turns.push(Turn::Assistant(json_str));
+                    turns.push(Turn::User(format!(
+                        "Your response could not be parsed: {}\n\n{}",
+                        e,
+                        self.output_keys()
+                    )));
+                }
+                // `post` reads the answer again, and routes a failure as an error.
+                _ => return json!(json_str),
+            }
         }
     }
 
@@ -99,21 +160,24 @@ where
         _prep_res: NodeValue,
         exec_res: NodeValue,
     ) -> Option<String> {
+        // `exec` already reported the provider error.
+        if exec_res.get("error").is_some() {
+            return Some(ERROR_ACTION.to_string());
+        }
         if let Some(json_str) = exec_res.as_str() {
-            if let Ok(Value::Object(map)) = serde_json::from_str::<Value>(json_str) {
-                for field in &self.signature.outputs {
-                    if let Some(val) = map.get(&field.name) {
-                        shared.insert(field.name.clone(), val.clone());
-                    } else {
-                        log::warn!("LLM missed required output field: {}", field.name);
-                    }
+            let map = match extract_object(json_str) {
+                Ok(map) => map,
+                Err(e) => {
+                    log::warn!("Could not read the output of '{}': {}", self.task_id, e);
+                    events::emit(FlowEventKind::Error { message: e.to_string() });
+                    return Some(ERROR_ACTION.to_string());
                 }
-            } else if let Ok(Value::Object(map)) = serde_json::from_value::<Value>(exec_res.clone()) {
-                // If exec_res is already an object (though expected string from execute_llm)
-                 for field in &self.signature.outputs {
-                    if let Some(val) = map.get(&field.name) {
-                        shared.insert(field.name.clone(), val.clone());
-                    }
+            };
+            for field in &self.signature.outputs {
+                if let Some(val) = map.get(&field.name) {
+                    shared.insert(field.name.clone(), val.clone());
+                } else {
+                    log::warn!("LLM missed required output field: {}", field.name);
                 }
             }
         }
@@ -137,7 +201,7 @@ impl<S> SemanticLLMLogic<S>
 where
     S: Clone + Send + Sync + 'static,
 {
-    async fn execute_llm(&self, prompt: &str, model: Option<String>) -> Result<String, LLMError> {
+    async fn execute_llm(&self, turns: &[Turn], model: Option<String>) -> Result<String, LLMError> {
         // We cannot call specific provider methods directly because S is generic.
         // However, we can use the trait bounds if we had them, or use the config flags.
         // Since we want this to be generic, we'll use a dispatch approach.
@@ -148,7 +212,10 @@ where
         // Let's use a workaround: since we are inside the crate, we can see the configs.
         // We'll implement a hidden method on Client<S> that allows dispatching.
         
-        self.client.dispatch_complete(prompt, model).await
+        self.client
+            .dispatch_complete(turns, model)
+            .await
+            .map(|completion| completion.text)
     }
 }
 
@@ -176,6 +243,48 @@ where
     fn model(&self) -> Option<&str> {
         self.model_override.as_deref()
     }
+
+    fn demos(&self) -> &[Demo] {
+        &self.demos
+    }
+
+    /// Also covers the field descriptions and the demos, which are part of the prompt.
+    fn instruction_hash(&self) -> String {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let mut hasher = DefaultHasher::new();
+        self.instruction.hash(&mut hasher);
+        for field in &self.signature.inputs {
+            field.description.hash(&mut hasher);
+        }
+        for field in &self.signature.outputs {
+            field.description.hash(&mut hasher);
+        }
+        if !self.demos.is_empty() {
+            self.demo_format.hash(&mut hasher);
+            for demo in &self.demos {
+                demo.inputs.to_string().hash(&mut hasher);
+                demo.outputs.to_string().hash(&mut hasher);
+            }
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn with_instruction(&self, instruction: &str) -> Option<SealedNode> {
+        let mut logic = self.clone();
+        logic.instruction = instruction.to_string();
+        let sig_hash = logic.signature.structural_hash();
+        let instr_hash = logic.instruction_hash();
+        let model_name = logic.execute_model_name();
+        Some(SealedNode::new(
+            Executable::Async(AsyncNode::new(logic)),
+            self.task_id.clone(),
+            self.signature.clone(),
+            sig_hash,
+            instr_hash,
+            model_name,
+        ))
+    }
 }
 
 /// Builder for creating semantic LLM nodes.
@@ -185,6 +294,10 @@ pub struct SemanticNodeBuilder<S> {
     instruction: Option<String>,
     task_id: Option<String>,
     model_override: Option<String>,
+    demos: Vec<Demo>,
+    demo_format: DemoFormat,
+    max_reasks: usize,
+    registry: Option<Arc<dyn RegistryStore>>,
 }
 
 impl<S> SemanticNodeBuilder<S>
@@ -198,6 +311,10 @@ where
             instruction: None,
             task_id: None,
             model_override: None,
+            demos: Vec::new(),
+            demo_format: DemoFormat::default(),
+            max_reasks: 0,
+            registry: None,
         }
     }
 
@@ -221,6 +338,36 @@ where
         self
     }
 
+    /// Input/output examples shown to the model. Each must have exactly the signature's fields.
+    pub fn demos(mut self, demos: impl IntoIterator<Item = Demo>) -> Self {
+        self.demos.extend(demos);
+        self
+    }
+
+    /// How the demos are presented; listed in the prompt by default.
+    pub fn demo_format(mut self, format: DemoFormat) -> Self {
+        self.demo_format = format;
+        self
+    }
+
+    /// When the answer cannot be read as a JSON object, sends the parse error back to the model
+    /// and asks again, up to `max_reasks` times. If the last answer still cannot be read, the
+    /// node returns [`ERROR_ACTION`]. No re-asks by default.
+    pub fn max_reasks(mut self, max_reasks: usize) -> Self {
+        self.max_reasks = max_reasks;
+        self
+    }
+
+    /// Consults `registry` when sealing: the best promoted record for the node's signature and
+    /// instruction hashes replaces the instruction (and the demos, if it has any) and fills the
+    /// optimization metadata.
+    /// The sealed node keeps the hashes of the base instruction, which identify it in the registry;
+    /// its traces carry the hash of the optimized prompt.
+    pub fn registry(mut self, registry: Arc<dyn RegistryStore>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
     pub fn seal(self) -> Executable {
         let signature = self.signature.expect("Signature is required for semantic node");
         let instruction = self.instruction.expect("Instruction is required for semantic node");
@@ -234,22 +381,57 @@ where
             id
         });
 
+        for (i, demo) in self.demos.iter().enumerate() {
+            if let Err(e) = demo.check(&signature) {
+                panic!("Demo {} does not match the signature: {}", i, e);
+            }
+        }
+
         let mut logic = SemanticLLMLogic::new(self.client.clone(), signature.clone(), instruction, task_id.clone());
         logic.model_override = self.model_override;
+        logic.demos = self.demos;
+        logic.demo_format = self.demo_format;
+        logic.max_reasks = self.max_reasks;
 
         let sig_hash = signature.structural_hash();
         let instr_hash = logic.instruction_hash();
         let model_name = logic.execute_model_name();
 
+        let record = self
+            .registry
+            .as_deref()
+            .and_then(|registry| sealed::lookup(registry, &sig_hash, &instr_hash));
+        if let Some(record) = &record {
+            if let Some(instruction) = &record.instruction {
+                logic.instruction = instruction.clone();
+            }
+            match record.demos.iter().try_for_each(|demo| demo.check(&logic.signature)) {
+                Ok(()) if !record.demos.is_empty() => logic.demos = record.demos.clone(),
+                Ok(()) => {}
+                Err(e) => log::warn!(
+                    "Ignoring demos of registry record '{}' v{}: {}",
+                    record.task_id,
+                    record.version,
+                    e
+                ),
+            }
+        }
+
+        let effective_hash = logic.instruction_hash();
         let node = AsyncNode::new(logic);
-        Executable::Sealed(Arc::new(SealedNode::new(
+        let mut sealed = SealedNode::new(
             Executable::Async(node),
             task_id,
             signature,
             sig_hash,
             instr_hash,
             model_name,
-        )))
+        );
+        if let Some(record) = record {
+            sealed.set_effective_instruction_hash(effective_hash);
+            sealed.apply_record(record);
+        }
+        Executable::Sealed(Arc::new(sealed))
     }
 }
 
@@ -279,3 +461,224 @@ where
         SemanticNodeBuilder::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::semantic::registry::{OptimizationRecord, OptimizationRegistry};
+    use crate::llm::mock;
+
+    fn builder() -> SemanticNodeBuilder<impl Clone + Send + Sync + 'static> {
+        Client::new()
+            .with_ollama()
+            .semantic_node()
+            .signature(crate::signature!("question -> answer"))
+            .instruction("Answer the question.")
+            .task_id("answer")
+    }
+
+    fn sealed(executable: Executable) -> Arc<SealedNode> {
+        match executable {
+            Executable::Sealed(sealed) => sealed,
+            _ => unreachable!(),
+        }
+    }
+
+    fn promptable(sealed: &SealedNode) -> &dyn Promptable {
+        let Executable::Async(node) = sealed.inner() else { unreachable!() };
+        node.behaviour.as_promptable().unwrap()
+    }
+
+    fn instruction(sealed: &SealedNode) -> String {
+        promptable(sealed).instruction().unwrap().to_string()
+    }
+
+    fn demo() -> Demo {
+        Demo::new(json!({"question": "2+2?"}), json!({"answer": "4"}))
+    }
+
+    #[test]
+    fn test_demos_in_prompt_and_chat_turns() {
+        let mut logic = SemanticLLMLogic::new(
+            Client::new().with_ollama(),
+            crate::signature!("question -> answer"),
+            "Answer the question.".to_string(),
+            "answer".to_string(),
+        );
+        let base_hash = logic.instruction_hash();
+        let input = json!({"question": "3+3?"});
+        let no_demos = logic.turns(&input);
+
+        logic.demos = vec![demo()];
+        assert_ne!(logic.instruction_hash(), base_hash);
+        let turns = logic.turns(&input);
+        let [Turn::User(prompt)] = turns.as_slice() else { unreachable!() };
+        let examples = prompt.find("Examples:").unwrap();
+        assert!(examples < prompt.find(r#"{"question":"3+3?"}"#).unwrap());
+        assert!(prompt.contains(r#"{"question":"2+2?"}"#) && prompt.contains(r#"{"answer":"4"}"#));
+
+        logic.demo_format = DemoFormat::ChatTurns;
+        let chat_hash = logic.instruction_hash();
+        assert_ne!(chat_hash, base_hash);
+        let turns = logic.turns(&input);
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[1], Turn::Assistant(r#"{"answer":"4"}"#.to_string()));
+        assert_eq!(turns[2], no_demos[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Demo 0 does not match the signature: missing output 'answer'")]
+    fn test_seal_rejects_nonconforming_demos() {
+        builder().demos([Demo::new(json!({"question": "?"}), json!({}))]).seal();
+    }
+
+    #[test]
+    fn test_seal_applies_promoted_registry_record() {
+        let base = sealed(builder().seal());
+        let registry = Arc::new(OptimizationRegistry::new());
+        let record = OptimizationRecord {
+            task_id: "answer".to_string(),
+            version: 0,
+            active: false,
+            signature_hash: base.signature_hash().to_string(),
+            instruction_hash: base.instruction_hash().to_string(),
+            optimized_instruction_hash: None,
+            instruction: Some("Answer in one word.".to_string()),
+            demos: vec![demo()],
+            training_hash: Some("train".to_string()),
+            optimization_config_hash: None,
+            fitness_score: Some(0.9),
+            weights_path: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+        let version = registry.register(record).unwrap();
+
+        // Registered but not promoted: production ignores it.
+        let unoptimized = sealed(builder().registry(registry.clone()).seal());
+        assert_eq!(instruction(&unoptimized), "Answer the question.");
+        assert!(unoptimized.applied_record().is_none());
+
+        registry.promote("answer", version).unwrap();
+        let optimized = sealed(builder().registry(registry).seal());
+        assert_eq!(instruction(&optimized), "Answer in one word.");
+        assert_eq!(promptable(&optimized).demos(), &[demo()]);
+        assert_eq!(optimized.instruction_hash(), base.instruction_hash());
+        assert_eq!(
+            optimized.optimized_instruction_hash(),
+            Some(promptable(&optimized).instruction_hash().as_str())
+        );
+        assert_eq!(optimized.fitness_score, Some(0.9));
+        assert_eq!(optimized.training_hash.as_deref(), Some("train"));
+        assert_eq!(optimized.applied_record().unwrap().version, version);
+    }
+
+    #[tokio::test]
+    async fn test_seal_with_registry_applies_the_instruction() {
+        let host = mock::ollama(|messages| {
+            if mock::last_content(messages).starts_with("Task Instruction: Answer in one word.") {
+                r#"{"answer": "four"}"#.to_string()
+            } else {
+                r#"{"answer": "It is four."}"#.to_string()
+            }
+        });
+        let logic = SemanticLLMLogic::new(
+            Client::new().with_ollama_at(host),
+            crate::signature!("question -> answer"),
+            "Answer the question.".to_string(),
+            "answer".to_string(),
+        );
+        let node = || AsyncNode::new(logic.clone()).next(Executable::Async(AsyncNode::new(logic.clone())));
+        let base = node().seal().unwrap();
+        assert_eq!(base.instruction_hash(), logic.instruction_hash());
+
+        let registry = OptimizationRegistry::new();
+        let rewritten = logic.with_instruction("Answer in one word.").unwrap();
+        registry
+            .register_and_promote(OptimizationRecord {
+                task_id: "answer".to_string(),
+                version: 0,
+                active: false,
+                signature_hash: base.signature_hash().to_string(),
+                instruction_hash: base.instruction_hash().to_string(),
+                optimized_instruction_hash: Some(rewritten.instruction_hash().to_string()),
+                instruction: Some("Answer in one word.".to_string()),
+                demos: Vec::new(),
+                training_hash: None,
+                optimization_config_hash: None,
+                fitness_score: Some(1.0),
+                weights_path: None,
+                created_at: 0,
+                updated_at: 0,
+            })
+            .unwrap();
+
+        let optimized = node().seal_with_registry(&registry).unwrap();
+        assert_eq!(instruction(&optimized), "Answer in one word.");
+        assert_eq!(optimized.instruction_hash(), base.instruction_hash());
+        assert_eq!(optimized.optimized_instruction_hash(), Some(rewritten.instruction_hash()));
+        assert!(optimized.inner().successors().contains_key("default"));
+
+        let telemetry = crate::core::telemetry::MemoryTelemetry::new();
+        let mut shared = HashMap::from([("question".to_string(), json!("2+2?"))]);
+        optimized.run(&mut shared, Some(&telemetry)).await;
+        assert_eq!(shared["answer"], json!("four"));
+        let trace = &telemetry.get_traces()[0];
+        assert_eq!(trace.instruction_hash, rewritten.instruction_hash());
+        assert_eq!(trace.metadata["optimization.base_instruction_hash"], base.instruction_hash());
+    }
+
+    #[tokio::test]
+    async fn test_reasks_until_the_answer_parses() {
+        // Answers in prose until shown why its answer could not be read.
+        let host = mock::ollama(|messages| {
+            let reasked = mock::last_content(messages).starts_with("Your response could not be parsed");
+            if reasked && messages.len() == 3 {
+                "```json\n{'answer': '4',}\n```".to_string()
+            } else {
+                "It's 4.".to_string()
+            }
+        });
+        let node = |max_reasks| {
+            Client::new()
+                .with_ollama_at(host.clone())
+                .semantic_node()
+                .signature(crate::signature!("question -> answer"))
+                .instruction("Answer the question.")
+                .task_id("answer")
+                .max_reasks(max_reasks)
+                .seal()
+        };
+
+        let mut shared = HashMap::from([("question".to_string(), json!("2+2?"))]);
+        let action = AsyncFlow::new(node(1)).run(&mut shared).await;
+        assert_eq!(action.as_deref(), Some("default"));
+        assert_eq!(shared["answer"], json!("4"));
+
+        let mut shared = HashMap::from([("question".to_string(), json!("2+2?"))]);
+        let action = AsyncFlow::new(node(0)).run(&mut shared).await;
+        assert_eq!(action.as_deref(), Some(ERROR_ACTION));
+        assert!(!shared.contains_key("answer"));
+    }
+
+    #[tokio::test]
+    async fn test_provider_errors_route_as_errors() {
+        // Nothing listens on a port that was just released.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let node = Client::new()
+            .with_ollama_at(host)
+            .semantic_node()
+            .signature(crate::signature!("question -> answer"))
+            .instruction("Answer the question.")
+            .task_id("answer")
+            .seal();
+        let mut shared = HashMap::from([("question".to_string(), json!("2+2?"))]);
+        let action = AsyncFlow::new(node).run(&mut shared).await;
+        assert_eq!(action.as_deref(), Some(ERROR_ACTION));
+        assert!(!shared.contains_key("answer"));
+    }
+}
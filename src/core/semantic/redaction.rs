@@ -0,0 +1,352 @@
+//! Redaction of signature fields in recorded traces.
+//!
+//! Fields of a [`Signature`] can carry a [`Redaction`]; sealed and sealable nodes apply it to
+//! their inputs and outputs before the trace entry reaches any sink, and note what they did in its
+//! metadata.
+
+use crate::core::semantic::extract::extract_object;
+use crate::core::semantic::signature::{Field, Signature};
+use crate::core::sync_impl::NodeValue;
+use crate::core::telemetry::TraceEntry;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// What happens to a field's value before it is recorded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    /// Leaves the field out.
+    Drop,
+    /// Replaces the value with its SHA-256 digest, so equal values can still be correlated.
+    Hash,
+    /// Replaces the value with `***`.
+    Mask,
+    /// Replaces what the scrubbers match inside the value's strings.
+    Scrub(Vec<Scrubber>),
+}
+
+/// Sensitive patterns found inside text.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scrubber {
+    /// Email addresses, replaced with `[EMAIL]`.
+    Emails,
+    /// Phone numbers, replaced with `[PHONE]`.
+    Phones,
+    /// API keys and access tokens, replaced with `[KEY]`.
+    Keys,
+    /// A custom regular expression, replaced with `[REDACTED]`.
+    Pattern(ScrubPattern),
+}
+
+/// A compiled custom scrubber pattern. It is checked when built or deserialized, so a signature
+/// can never carry a pattern that fails to redact.
+#[derive(Debug, Clone)]
+pub struct ScrubPattern(Regex);
+
+impl ScrubPattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for ScrubPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ScrubPattern {}
+
+impl Serialize for ScrubPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ScrubPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Scrubber {
+    /// A custom pattern scrubber; fails if `pattern` is not a valid regular expression.
+    pub fn pattern(pattern: &str) -> Result<Self, regex::Error> {
+        ScrubPattern::new(pattern).map(Scrubber::Pattern)
+    }
+
+    /// Replaces the matches in `text`, returning how many there were.
+    fn scrub(&self, text: &mut String) -> usize {
+        static EMAILS: OnceLock<Regex> = OnceLock::new();
+        static PHONES: OnceLock<Regex> = OnceLock::new();
+        static KEYS: OnceLock<Regex> = OnceLock::new();
+
+        let (regex, replacement) = match self {
+            Scrubber::Emails => (
+                EMAILS.get_or_init(|| {
+                    Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap()
+                }),
+                "[EMAIL]",
+            ),
+            Scrubber::Phones => (
+                PHONES.get_or_init(|| Regex::new(r"\+?\d[\d\s().-]{6,}\d").unwrap()),
+                "[PHONE]",
+            ),
+            Scrubber::Keys => (
+                KEYS.get_or_init(|| {
+                    Regex::new(
+                        r"\b(?:(?:sk|pk|rk|api|key|token)[-_][A-Za-z0-9_-]{16,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36})\b",
+                    )
+                    .unwrap()
+                }),
+                "[KEY]",
+            ),
+            Scrubber::Pattern(pattern) => (&pattern.0, "[REDACTED]"),
+        };
+
+        let count = regex.find_iter(text).count();
+        if count > 0 {
+            *text = regex.replace_all(text, replacement).into_owned();
+        }
+        count
+    }
+}
+
+/// Applies the redactions of `signature` to the inputs and outputs of `entry`.
+pub(crate) fn redact(signature: &Signature, entry: &mut TraceEntry) {
+    for (side, fields, value) in [
+        ("inputs", &signature.inputs, &mut entry.inputs),
+        ("outputs", &signature.outputs, &mut entry.outputs),
+    ] {
+        for (field, note) in redact_fields(fields, value) {
+            entry.metadata.insert(format!("redaction.{}.{}", side, field), note);
+        }
+    }
+}
+
+/// Redacts the fields of `value`, returning a note per redacted field.
+///
+/// Semantic nodes trace the model's raw answer: a string is read with [`extract_object`] and
+/// written back redacted. A string that cannot be read is masked whole if any field is redacted.
+fn redact_fields(fields: &[Field], value: &mut NodeValue) -> Vec<(String, String)> {
+    let redacted = || fields.iter().filter(|f| f.redaction.is_some());
+    if redacted().next().is_none() {
+        return Vec::new();
+    }
+    let NodeValue::String(text) = value else {
+        return redact_object(fields, value);
+    };
+    match extract_object(text) {
+        Ok(object) => {
+            let mut object = NodeValue::Object(object);
+            let notes = redact_object(fields, &mut object);
+            *value = NodeValue::String(object.to_string());
+            notes
+        }
+        Err(_) => {
+            *value = NodeValue::String("***".to_string());
+            redacted().map(|f| (f.name.clone(), "mask".to_string())).collect()
+        }
+    }
+}
+
+/// Redacts the fields of the `value` object.
+fn redact_object(fields: &[Field], value: &mut NodeValue) -> Vec<(String, String)> {
+    let Some(object) = value.as_object_mut() else {
+        return Vec::new();
+    };
+    let mut notes = Vec::new();
+    for field in fields {
+        let Some(redaction) = &field.redaction else {
+            continue;
+        };
+        let note = match redaction {
+            Redaction::Drop => object.remove(&field.name).map(|_| "drop".to_string()),
+            Redaction::Hash => object.get_mut(&field.name).map(|v| {
+                let digest = Sha256::digest(v.to_string().as_bytes());
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                *v = NodeValue::String(format!("sha256:{}", hex));
+                "hash".to_string()
+            }),
+            Redaction::Mask => object.get_mut(&field.name).map(|v| {
+                *v = NodeValue::String("***".to_string());
+                "mask".to_string()
+            }),
+            Redaction::Scrub(scrubbers) => object
+                .get_mut(&field.name)
+                .map(|v| format!("scrub:{}", scrub(v, scrubbers))),
+        };
+        if let Some(note) = note {
+            notes.push((field.name.clone(), note));
+        }
+    }
+    notes
+}
+
+/// Scrubs every string nested in `value`, returning how many matches were replaced.
+fn scrub(value: &mut NodeValue, scrubbers: &[Scrubber]) -> usize {
+    match value {
+        NodeValue::String(text) => scrubbers.iter().map(|s| s.scrub(text)).sum(),
+        NodeValue::Array(items) => items.iter_mut().map(|v| scrub(v, scrubbers)).sum(),
+        NodeValue::Object(map) => map.values_mut().map(|v| scrub(v, scrubbers)).sum(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::semantic::Sealable;
+    use crate::core::sync_impl::node::{Node, NodeLogic};
+    use crate::core::telemetry::{MemoryTelemetry, SpanKind};
+    use crate::core::Executable;
+    use crate::llm::{mock, Client};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    async fn trace(flow: AsyncFlow, shared: Value) -> TraceEntry {
+        let Value::Object(shared) = shared else { unreachable!() };
+        let telemetry = Arc::new(MemoryTelemetry::new());
+        let mut shared: HashMap<String, NodeValue> = shared.into_iter().collect();
+        flow.run_with_telemetry(&mut shared, Some(telemetry.clone())).await;
+        telemetry.get_traces().into_iter().find(|t| t.kind != SpanKind::Flow).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_semantic_node_traces_are_redacted() {
+        let host = mock::ollama(|_| {
+            let answer = json!({
+                "reply": "Dear ada@example.com, we will call +1 (555) 010-9999.",
+                "summary": "Use sk-abcdefghijklmnop1234 to log in",
+            });
+            format!("```json\n{}\n```", answer)
+        });
+        let signature = crate::signature!("email, name, ticket, age -> reply, summary")
+            .redact("email", Redaction::Hash)
+            .redact("name", Redaction::Mask)
+            .redact("age", Redaction::Drop)
+            .redact("ticket", Redaction::Scrub(vec![Scrubber::Emails, Scrubber::Phones]))
+            .redact("reply", Redaction::Scrub(vec![Scrubber::Emails, Scrubber::Phones]))
+            .redact("summary", Redaction::Scrub(vec![Scrubber::Keys]));
+        let node = Client::new()
+            .with_ollama_at(host)
+            .semantic_node()
+            .signature(signature)
+            .instruction("Answer the ticket.")
+            .task_id("support")
+            .seal();
+
+        let entry = trace(
+            AsyncFlow::new(node),
+            json!({
+                "email": "ada@example.com",
+                "name": "Ada",
+                "age": 36,
+                "ticket": "Call me at +1 (555) 010-9999 or ada@example.com",
+            }),
+        )
+        .await;
+
+        let email = entry.inputs["email"].as_str().unwrap();
+        assert!(email.starts_with("sha256:") && !email.contains("ada"));
+        assert_eq!(entry.inputs["name"], json!("***"));
+        assert!(entry.inputs.get("age").is_none());
+        assert_eq!(entry.inputs["ticket"], json!("Call me at [PHONE] or [EMAIL]"));
+
+        // The raw answer is still recorded as a string, without what it leaked.
+        let outputs = entry.outputs.as_str().unwrap();
+        assert!(!outputs.contains("ada@") && !outputs.contains("sk-"), "{}", outputs);
+        let outputs: Value = serde_json::from_str(outputs).unwrap();
+        assert_eq!(outputs["reply"], json!("Dear [EMAIL], we will call [PHONE]."));
+        assert_eq!(outputs["summary"], json!("Use [KEY] to log in"));
+
+        assert_eq!(entry.metadata["redaction.inputs.email"], "hash");
+        assert_eq!(entry.metadata["redaction.inputs.age"], "drop");
+        assert_eq!(entry.metadata["redaction.inputs.ticket"], "scrub:2");
+        assert_eq!(entry.metadata["redaction.outputs.reply"], "scrub:2");
+        assert_eq!(entry.metadata["redaction.outputs.summary"], "scrub:1");
+    }
+
+    #[test]
+    fn test_custom_patterns_are_compiled_up_front() {
+        assert!(Scrubber::pattern("(unclosed").is_err());
+        assert!(serde_json::from_value::<Scrubber>(json!({"pattern": "(unclosed"})).is_err());
+
+        let scrubber = Scrubber::pattern(r"ACCT-\d+").unwrap();
+        let json = serde_json::to_value(&scrubber).unwrap();
+        assert_eq!(json, json!({"pattern": r"ACCT-\d+"}));
+        assert_eq!(serde_json::from_value::<Scrubber>(json).unwrap(), scrubber);
+
+        let signature = crate::signature!("ticket -> reply").redact("ticket", Redaction::Scrub(vec![scrubber]));
+        let mut entry = TraceEntry {
+            inputs: json!({"ticket": "Refund ACCT-1234 and ACCT-99"}),
+            ..Default::default()
+        };
+        redact(&signature, &mut entry);
+        assert_eq!(entry.inputs["ticket"], json!("Refund [REDACTED] and [REDACTED]"));
+        assert_eq!(entry.metadata["redaction.inputs.ticket"], "scrub:2");
+    }
+
+    #[test]
+    fn test_unreadable_answers_are_masked() {
+        let signature = crate::signature!("ticket -> reply").redact("reply", Redaction::Scrub(vec![Scrubber::Emails]));
+        let mut entry = TraceEntry {
+            outputs: json!("Dear ada@example.com, sorry, no JSON today."),
+            ..Default::default()
+        };
+        redact(&signature, &mut entry);
+        assert_eq!(entry.outputs, json!("***"));
+        assert_eq!(entry.metadata["redaction.outputs.reply"], "mask");
+    }
+
+    /// A sealable node that is never sealed.
+    #[derive(Clone)]
+    struct Signup;
+
+    impl NodeLogic for Signup {
+        fn prep(
+            &self,
+            _params: &HashMap<String, NodeValue>,
+            shared: &HashMap<String, NodeValue>,
+        ) -> NodeValue {
+            json!({ "email": shared["email"] })
+        }
+
+        fn clone_box(&self) -> Box<dyn NodeLogic> {
+            Box::new(self.clone())
+        }
+
+        fn as_sealable(&self) -> Option<&dyn Sealable> {
+            Some(self)
+        }
+    }
+
+    impl Sealable for Signup {
+        fn signature(&self) -> Signature {
+            crate::signature!("email -> ok").redact("email", Redaction::Mask)
+        }
+
+        fn task_id(&self) -> String {
+            "signup".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sealable_node_traces_are_redacted() {
+        let flow = AsyncFlow::new(Executable::Sync(Node::new(Signup)));
+        let entry = trace(flow, json!({"email": "ada@example.com"})).await;
+        assert_eq!(entry.task_id, "signup");
+        assert_eq!(entry.inputs, json!({"email": "***"}));
+        assert_eq!(entry.metadata["redaction.inputs.email"], "mask");
+    }
+}
@@ -0,0 +1,309 @@
+//! Portable bundles of registry records.
+//!
+//! A bundle carries every version of the exported tasks. Importing it registers one version per
+//! task, the one promoted in the bundle (or the latest), and promotes it only when asked to and
+//! only if the bundle had it promoted. Bundles are written as one JSON document, or as JSON Lines
+//! with one record per line.
+
+use super::{OptimizationRecord, RecordDiff, RegistryError, RegistryStore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// The bundle format written by this version.
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Records exported from a registry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegistryBundle {
+    pub format_version: u32,
+    /// Sorted by task id, then version.
+    pub records: Vec<OptimizationRecord>,
+}
+
+/// What to do when the target registry already has a promoted version of an imported task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Keep the target's version and import nothing.
+    #[default]
+    Skip,
+    /// Import the bundle's version.
+    Overwrite,
+    /// Import the bundle's version only if its fitness is higher than the target's.
+    KeepHigherFitness,
+}
+
+/// How [`RegistryBundle::import_into`] treats the target registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportOptions {
+    pub policy: ConflictPolicy,
+    /// Whether to promote the imported versions that were promoted in the bundle.
+    pub promote: bool,
+}
+
+/// The outcome of [`RegistryBundle::import_into`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    /// The imported tasks and the version each got in the target registry.
+    pub imported: Vec<(String, u32)>,
+    /// The tasks and versions promoted in the target registry.
+    pub promoted: Vec<(String, u32)>,
+    /// Tasks left as they were because of the conflict policy.
+    pub skipped: Vec<String>,
+    /// Tasks that already have a version matching the imported one, and nothing to promote.
+    pub unchanged: Vec<String>,
+}
+
+impl ImportOptions {
+    /// Registers without promoting, skipping tasks the target has promoted a version of.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Promotes the imported versions that were promoted in the bundle.
+    pub fn with_promotion(mut self, promote: bool) -> Self {
+        self.promote = promote;
+        self
+    }
+}
+
+impl RegistryBundle {
+    /// Exports every version of `task_ids`, or of every task if `None`.
+    pub fn export(store: &dyn RegistryStore, task_ids: Option<&[&str]>) -> Result<Self, RegistryError> {
+        let task_ids = match task_ids {
+            Some(task_ids) => {
+                let mut task_ids: Vec<String> = task_ids.iter().map(|id| id.to_string()).collect();
+                task_ids.sort();
+                task_ids.dedup();
+                task_ids
+            }
+            None => store.task_ids()?,
+        };
+        let mut records = Vec::new();
+        for task_id in task_ids {
+            records.extend(store.versions(&task_id)?);
+        }
+        Ok(Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            records,
+        })
+    }
+
+    /// Imports the records into `store`.
+    ///
+    /// For each task, the version promoted in the bundle (or its latest version if none is) is
+    /// registered in `store`, unless `store` already has a matching version or the conflict policy
+    /// keeps the target's promoted version. With [`ImportOptions::promote`], a version promoted in
+    /// the bundle is promoted in `store` too; others never are. Versions are renumbered by the
+    /// target; the rest of each task's history is not imported.
+    pub fn import_into(
+        &self,
+        store: &dyn RegistryStore,
+        options: ImportOptions,
+    ) -> Result<ImportReport, RegistryError> {
+        if self.format_version > BUNDLE_FORMAT_VERSION {
+            return Err(RegistryError::UnsupportedBundle(self.format_version));
+        }
+        let mut chosen: BTreeMap<&str, &OptimizationRecord> = BTreeMap::new();
+        for record in &self.records {
+            let replace = chosen.get(record.task_id.as_str()).is_none_or(|current| {
+                record.active || (!current.active && record.version > current.version)
+            });
+            if replace {
+                chosen.insert(&record.task_id, record);
+            }
+        }
+
+        let mut report = ImportReport::default();
+        for (task_id, record) in chosen {
+            let promote = options.promote && record.active;
+            let existing = store
+                .versions(task_id)?
+                .into_iter()
+                .find(|version| RecordDiff::between(version, record).is_empty());
+            if existing.as_ref().is_some_and(|version| version.active || !promote) {
+                report.unchanged.push(task_id.to_string());
+                continue;
+            }
+            if let Some(current) = store.get_by_task_id(task_id)? {
+                let keep_current = match options.policy {
+                    ConflictPolicy::Skip => true,
+                    ConflictPolicy::Overwrite => false,
+                    ConflictPolicy::KeepHigherFitness => {
+                        record.fitness_score.unwrap_or(f64::NEG_INFINITY)
+                            <= current.fitness_score.unwrap_or(f64::NEG_INFINITY)
+                    }
+                };
+                if keep_current {
+                    report.skipped.push(task_id.to_string());
+                    continue;
+                }
+            }
+            let version = match existing {
+                Some(existing) => existing.version,
+                None => {
+                    let version = store.register(record.clone())?;
+                    report.imported.push((task_id.to_string(), version));
+                    version
+                }
+            };
+            if promote {
+                store.promote(task_id, version)?;
+                report.promoted.push((task_id.to_string(), version));
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn to_json(&self) -> Result<String, RegistryError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RegistryError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// One record per line.
+    pub fn to_jsonl(&self) -> Result<String, RegistryError> {
+        let mut jsonl = String::new();
+        for record in &self.records {
+            jsonl.push_str(&serde_json::to_string(record)?);
+            jsonl.push('\n');
+        }
+        Ok(jsonl)
+    }
+
+    pub fn from_jsonl(jsonl: &str) -> Result<Self, RegistryError> {
+        let records = jsonl
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            format_version: BUNDLE_FORMAT_VERSION,
+            records,
+        })
+    }
+
+    /// Writes the bundle as JSON Lines if `path` ends in `.jsonl`, as JSON otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RegistryError> {
+        let path = path.as_ref();
+        let contents = if is_jsonl(path) { self.to_jsonl()? } else { self.to_json()? };
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Reads a bundle written by [`save`](Self::save).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        if is_jsonl(path) {
+            Self::from_jsonl(&contents)
+        } else {
+            Self::from_json(&contents)
+        }
+    }
+}
+
+fn is_jsonl(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "jsonl")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::semantic::registry::tests::{publish, record};
+    use crate::core::semantic::registry::OptimizationRegistry;
+
+    fn research() -> OptimizationRegistry {
+        let registry = OptimizationRegistry::new();
+        publish(&registry, record("summarize", "i1", Some(0.6)));
+        publish(&registry, record("summarize", "i2", Some(0.8)));
+        registry.register(record("summarize", "i3", Some(0.9))).unwrap();
+        publish(&registry, record("classify", "i1", Some(0.5)));
+        registry
+    }
+
+    #[test]
+    fn test_export_filters_and_round_trips() {
+        let bundle = RegistryBundle::export(&research(), Some(&["summarize"])).unwrap();
+        assert_eq!(bundle.records.len(), 3);
+        assert!(bundle.records[1].active);
+
+        let path = std::env::temp_dir().join(format!("orichalcum-{}.jsonl", uuid::Uuid::new_v4()));
+        bundle.save(&path).unwrap();
+        assert_eq!(RegistryBundle::load(&path).unwrap(), bundle);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(RegistryBundle::from_json(&bundle.to_json().unwrap()).unwrap(), bundle);
+    }
+
+    #[test]
+    fn test_import_policies() {
+        let bundle = RegistryBundle::export(&research(), None).unwrap();
+        let promoting = |policy| ImportOptions::new().with_policy(policy).with_promotion(true);
+
+        let production = OptimizationRegistry::new();
+        publish(&production, record("summarize", "i0", Some(0.7)));
+        publish(&production, record("classify", "i0", Some(0.9)));
+
+        let report = bundle.import_into(&production, promoting(ConflictPolicy::Skip)).unwrap();
+        assert_eq!(report.skipped, vec!["classify", "summarize"]);
+
+        // The bundle's promoted summarize version (i2, 0.8) beats 0.7; classify's 0.5 does not.
+        let report = bundle
+            .import_into(&production, promoting(ConflictPolicy::KeepHigherFitness))
+            .unwrap();
+        assert_eq!(report.imported, vec![("summarize".to_string(), 2)]);
+        assert_eq!(report.promoted, vec![("summarize".to_string(), 2)]);
+        assert_eq!(report.skipped, vec!["classify"]);
+        assert_eq!(production.get_by_task_id("summarize").unwrap().unwrap().instruction_hash, "i2");
+
+        let report = bundle.import_into(&production, promoting(ConflictPolicy::Overwrite)).unwrap();
+        assert_eq!(report.imported, vec![("classify".to_string(), 2)]);
+        assert_eq!(report.unchanged, vec!["summarize"]);
+
+        let staging = OptimizationRegistry::new();
+        let report = bundle.import_into(&staging, promoting(ConflictPolicy::Skip)).unwrap();
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(staging.get_by_task_id("classify").unwrap().unwrap().fitness_score, Some(0.5));
+    }
+
+    #[test]
+    fn test_import_promotes_only_when_asked_and_only_active_versions() {
+        let bundle = RegistryBundle::export(&research(), None).unwrap();
+
+        let staging = OptimizationRegistry::new();
+        let report = bundle.import_into(&staging, ImportOptions::new()).unwrap();
+        assert_eq!(report.imported, vec![("classify".to_string(), 1), ("summarize".to_string(), 1)]);
+        assert!(report.promoted.is_empty());
+        assert_eq!(staging.get_by_task_id("summarize").unwrap(), None);
+        assert_eq!(staging.get_version("summarize", 1).unwrap().unwrap().instruction_hash, "i2");
+
+        // Importing again finds the registered versions, and promotes them once asked to.
+        let report = bundle.import_into(&staging, ImportOptions::new()).unwrap();
+        assert_eq!(report.unchanged, vec!["classify", "summarize"]);
+        let report = bundle
+            .import_into(&staging, ImportOptions::new().with_promotion(true))
+            .unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.promoted.len(), 2);
+        assert_eq!(staging.get_by_task_id("summarize").unwrap().unwrap().version, 1);
+
+        // A task with nothing promoted in the bundle imports its latest version, unpromoted.
+        let drafts = OptimizationRegistry::new();
+        drafts.register(record("translate", "i1", Some(0.4))).unwrap();
+        drafts.register(record("translate", "i2", Some(0.6))).unwrap();
+        let bundle = RegistryBundle::export(&drafts, None).unwrap();
+        let report = bundle
+            .import_into(&staging, ImportOptions::new().with_promotion(true))
+            .unwrap();
+        assert_eq!(report.imported, vec![("translate".to_string(), 1)]);
+        assert!(report.promoted.is_empty());
+        assert_eq!(staging.get_by_task_id("translate").unwrap(), None);
+    }
+}
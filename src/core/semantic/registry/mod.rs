@@ -0,0 +1,450 @@
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use thiserror::Error;
+use crate::core::semantic::demo::Demo;
+
+pub mod bundle;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// Represents a record of an optimization run for a specific task.
+///
+/// Every registration of a task is kept as a new version. Only the promoted (`active`) version
+/// of each task is returned by [`RegistryStore::get_by_task_id`] and
+/// [`RegistryStore::find_best_match`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OptimizationRecord {
+    pub task_id: String,
+    /// Assigned by the registry on registration, starting at 1.
+    #[serde(default)]
+    pub version: u32,
+    /// Whether this is the promoted version of its task. Set by the registry.
+    #[serde(default)]
+    pub active: bool,
+    pub signature_hash: String,
+    /// The instruction hash of the node the record optimizes.
+    pub instruction_hash: String,
+    /// The instruction hash of the node rebuilt with the optimized instruction, when it differs
+    /// from `instruction_hash`. Lookups by instruction hash match either.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub optimized_instruction_hash: Option<String>,
+    /// The optimized instruction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instruction: Option<String>,
+    /// The optimized demonstrations.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub demos: Vec<Demo>,
+    pub training_hash: Option<String>,
+    pub optimization_config_hash: Option<String>,
+    pub fitness_score: Option<f64>,
+    pub weights_path: Option<PathBuf>,
+    /// Milliseconds since the Unix epoch, like `updated_at`.
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl OptimizationRecord {
+    /// Whether `instruction_hash` is the record's base or optimized instruction hash.
+    pub fn matches_instruction(&self, instruction_hash: &str) -> bool {
+        self.instruction_hash == instruction_hash
+            || self.optimized_instruction_hash.as_deref() == Some(instruction_hash)
+    }
+}
+
+/// A field that differs between two versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+/// What changed between two versions of a task. Fields that did not change are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordDiff {
+    pub task_id: String,
+    pub from: u32,
+    pub to: u32,
+    pub signature_hash: Option<Change<String>>,
+    pub instruction_hash: Option<Change<String>>,
+    pub optimized_instruction_hash: Option<Change<Option<String>>>,
+    pub instruction: Option<Change<Option<String>>>,
+    pub demos: Option<Change<Vec<Demo>>>,
+    pub fitness_score: Option<Change<Option<f64>>>,
+    pub training_hash: Option<Change<Option<String>>>,
+    pub optimization_config_hash: Option<Change<Option<String>>>,
+    pub weights_path: Option<Change<Option<PathBuf>>>,
+}
+
+impl RecordDiff {
+    pub fn between(from: &OptimizationRecord, to: &OptimizationRecord) -> Self {
+        fn change<T: PartialEq + Clone>(before: &T, after: &T) -> Option<Change<T>> {
+            (before != after).then(|| Change {
+                before: before.clone(),
+                after: after.clone(),
+            })
+        }
+        Self {
+            task_id: to.task_id.clone(),
+            from: from.version,
+            to: to.version,
+            signature_hash: change(&from.signature_hash, &to.signature_hash),
+            instruction_hash: change(&from.instruction_hash, &to.instruction_hash),
+            optimized_instruction_hash: change(
+                &from.optimized_instruction_hash,
+                &to.optimized_instruction_hash,
+            ),
+            instruction: change(&from.instruction, &to.instruction),
+            demos: change(&from.demos, &to.demos),
+            fitness_score: change(&from.fitness_score, &to.fitness_score),
+            training_hash: change(&from.training_hash, &to.training_hash),
+            optimization_config_hash: change(
+                &from.optimization_config_hash,
+                &to.optimization_config_hash,
+            ),
+            weights_path: change(&from.weights_path, &to.weights_path),
+        }
+    }
+
+    /// Whether the versions agree on every compared field.
+    pub fn is_empty(&self) -> bool {
+        self.signature_hash.is_none()
+            && self.instruction_hash.is_none()
+            && self.optimized_instruction_hash.is_none()
+            && self.instruction.is_none()
+            && self.demos.is_none()
+            && self.fitness_score.is_none()
+            && self.training_hash.is_none()
+            && self.optimization_config_hash.is_none()
+            && self.weights_path.is_none()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RegistryError {
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error("Registry schema version {found} is newer than the supported version {supported}")]
+    UnsupportedSchema { found: i64, supported: i64 },
+
+    #[error("Task '{task_id}' has no version {version}")]
+    UnknownVersion { task_id: String, version: u32 },
+
+    #[error("Task '{0}' has no previously promoted version to roll back to")]
+    NoPriorVersion(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid bundle: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Bundle format version {0} is not supported")]
+    UnsupportedBundle(u32),
+}
+
+/// Storage for optimization records, shared by everything that seals or optimizes nodes.
+pub trait RegistryStore: Send + Sync {
+    /// Stores `record` as the next version of its task and returns that version.
+    /// The version is not promoted; see [`promote`](Self::promote).
+    fn register(&self, record: OptimizationRecord) -> Result<u32, RegistryError>;
+
+    /// Registers `record` and promotes the new version, which is returned.
+    fn register_and_promote(&self, record: OptimizationRecord) -> Result<u32, RegistryError> {
+        let task_id = record.task_id.clone();
+        let version = self.register(record)?;
+        self.promote(&task_id, version)?;
+        Ok(version)
+    }
+
+    /// The promoted version of `task_id`.
+    fn get_by_task_id(&self, task_id: &str) -> Result<Option<OptimizationRecord>, RegistryError>;
+
+    /// The promoted record with the best fitness among those matching `signature_hash` and
+    /// `instruction_hash`, as either its base or its optimized instruction hash.
+    fn find_best_match(
+        &self,
+        signature_hash: &str,
+        instruction_hash: &str,
+    ) -> Result<Option<OptimizationRecord>, RegistryError>;
+
+    /// The tasks with at least one version, sorted.
+    fn task_ids(&self) -> Result<Vec<String>, RegistryError>;
+
+    /// Every version of `task_id`, oldest first.
+    fn versions(&self, task_id: &str) -> Result<Vec<OptimizationRecord>, RegistryError>;
+
+    fn get_version(
+        &self,
+        task_id: &str,
+        version: u32,
+    ) -> Result<Option<OptimizationRecord>, RegistryError>;
+
+    /// Makes `version` the active version of `task_id`.
+    fn promote(&self, task_id: &str, version: u32) -> Result<(), RegistryError>;
+
+    /// Demotes the active version of `task_id` and reactivates the one promoted before it,
+    /// which is returned with `updated_at` set to now.
+    fn rollback(&self, task_id: &str) -> Result<OptimizationRecord, RegistryError>;
+
+    /// What changed from version `from` to version `to` of `task_id`.
+    fn diff(&self, task_id: &str, from: u32, to: u32) -> Result<RecordDiff, RegistryError> {
+        let get = |version| {
+            self.get_version(task_id, version)?
+                .ok_or_else(|| RegistryError::UnknownVersion {
+                    task_id: task_id.to_string(),
+                    version,
+                })
+        };
+        Ok(RecordDiff::between(&get(from)?, &get(to)?))
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// In-memory registry, for tests and single-process use.
+/// Use `SqliteRegistry` (`sqlite` feature) to keep records across restarts.
+#[derive(Default)]
+pub struct OptimizationRegistry {
+    tasks: RwLock<HashMap<String, TaskHistory>>,
+}
+
+#[derive(Default)]
+struct TaskHistory {
+    versions: Vec<OptimizationRecord>,
+    /// Promoted versions, the active one last.
+    promotions: Vec<u32>,
+}
+
+impl TaskHistory {
+    fn record(&self, version: u32) -> Option<OptimizationRecord> {
+        let mut record = self.versions.get((version as usize).checked_sub(1)?)?.clone();
+        record.active = self.promotions.last() == Some(&version);
+        Some(record)
+    }
+
+    fn active(&self) -> Option<OptimizationRecord> {
+        self.record(*self.promotions.last()?)
+    }
+}
+
+impl OptimizationRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RegistryStore for OptimizationRegistry {
+    fn register(&self, mut record: OptimizationRecord) -> Result<u32, RegistryError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let history = tasks.entry(record.task_id.clone()).or_default();
+        let version = history.versions.len() as u32 + 1;
+        let now = now();
+        record.version = version;
+        record.active = false;
+        record.created_at = now;
+        record.updated_at = now;
+        history.versions.push(record);
+        Ok(version)
+    }
+
+    fn get_by_task_id(&self, task_id: &str) -> Result<Option<OptimizationRecord>, RegistryError> {
+        Ok(self.tasks.read().unwrap().get(task_id).and_then(TaskHistory::active))
+    }
+
+    fn find_best_match(
+        &self,
+        signature_hash: &str,
+        instruction_hash: &str,
+    ) -> Result<Option<OptimizationRecord>, RegistryError> {
+        Ok(self.tasks.read().unwrap().values()
+            .filter_map(TaskHistory::active)
+            .filter(|r| r.signature_hash == signature_hash && r.matches_instruction(instruction_hash))
+            .max_by(|a, b| a.fitness_score.partial_cmp(&b.fitness_score).unwrap_or(std::cmp::Ordering::Equal)))
+    }
+
+    fn task_ids(&self) -> Result<Vec<String>, RegistryError> {
+        let mut task_ids: Vec<String> = self.tasks.read().unwrap().keys().cloned().collect();
+        task_ids.sort();
+        Ok(task_ids)
+    }
+
+    fn versions(&self, task_id: &str) -> Result<Vec<OptimizationRecord>, RegistryError> {
+        let tasks = self.tasks.read().unwrap();
+        let Some(history) = tasks.get(task_id) else {
+            return Ok(Vec::new());
+        };
+        Ok((1..=history.versions.len() as u32)
+            .filter_map(|version| history.record(version))
+            .collect())
+    }
+
+    fn get_version(
+        &self,
+        task_id: &str,
+        version: u32,
+    ) -> Result<Option<OptimizationRecord>, RegistryError> {
+        Ok(self.tasks.read().unwrap().get(task_id).and_then(|h| h.record(version)))
+    }
+
+    fn promote(&self, task_id: &str, version: u32) -> Result<(), RegistryError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let record = tasks
+            .get_mut(task_id)
+            .and_then(|h| h.versions.get_mut((version as usize).checked_sub(1)?))
+            .ok_or_else(|| RegistryError::UnknownVersion {
+                task_id: task_id.to_string(),
+                version,
+            })?;
+        record.updated_at = now();
+        let history = tasks.get_mut(task_id).unwrap();
+        history.promotions.retain(|&v| v != version);
+        history.promotions.push(version);
+        Ok(())
+    }
+
+    fn rollback(&self, task_id: &str) -> Result<OptimizationRecord, RegistryError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let history = tasks
+            .get_mut(task_id)
+            .filter(|h| h.promotions.len() >= 2)
+            .ok_or_else(|| RegistryError::NoPriorVersion(task_id.to_string()))?;
+        history.promotions.pop();
+        let version = *history.promotions.last().unwrap();
+        history.versions[version as usize - 1].updated_at = now();
+        Ok(history.active().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) fn record(task_id: &str, instruction_hash: &str, fitness: Option<f64>) -> OptimizationRecord {
+        OptimizationRecord {
+            task_id: task_id.to_string(),
+            version: 0,
+            active: false,
+            signature_hash: "sig".to_string(),
+            instruction_hash: instruction_hash.to_string(),
+            optimized_instruction_hash: None,
+            instruction: Some(format!("Instruction {}", instruction_hash)),
+            demos: Vec::new(),
+            training_hash: None,
+            optimization_config_hash: None,
+            fitness_score: fitness,
+            weights_path: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    /// Registers `record` and promotes it right away.
+    pub(crate) fn publish(store: &dyn RegistryStore, record: OptimizationRecord) -> u32 {
+        store.register_and_promote(record).unwrap()
+    }
+
+    /// The behaviour every store must share.
+    pub(crate) fn check_store(store: &dyn RegistryStore) {
+        publish(store, record("a", "i1", Some(0.4)));
+        publish(store, record("b", "i1", Some(0.9)));
+        publish(store, record("c", "i1", None));
+        publish(store, record("d", "i2", Some(1.0)));
+        assert_eq!(publish(store, record("a", "i1", Some(0.95))), 2);
+
+        let active = store.get_by_task_id("a").unwrap().unwrap();
+        assert_eq!((active.version, active.active, active.fitness_score), (2, true, Some(0.95)));
+        assert!(active.created_at > 0);
+        assert_eq!(store.get_by_task_id("missing").unwrap(), None);
+        assert_eq!(store.find_best_match("sig", "i1").unwrap().unwrap().task_id, "a");
+        assert_eq!(store.find_best_match("sig", "i3").unwrap(), None);
+        assert_eq!(store.task_ids().unwrap(), vec!["a", "b", "c", "d"]);
+
+        let demos = vec![Demo::new(serde_json::json!({"q": "1+1"}), serde_json::json!({"a": 2}))];
+        publish(store, OptimizationRecord { demos: demos.clone(), ..record("e", "i1", None) });
+        assert_eq!(store.get_by_task_id("e").unwrap().unwrap().demos, demos);
+
+        let optimized = OptimizationRecord {
+            optimized_instruction_hash: Some("i9".to_string()),
+            ..record("f", "i8", Some(0.5))
+        };
+        publish(store, optimized.clone());
+        assert_eq!(store.find_best_match("sig", "i8").unwrap().unwrap().task_id, "f");
+        let found = store.find_best_match("sig", "i9").unwrap().unwrap();
+        assert_eq!(found.optimized_instruction_hash, optimized.optimized_instruction_hash);
+    }
+
+    /// Versions, promotion, rollback and diffs.
+    pub(crate) fn check_history(store: &dyn RegistryStore) {
+        assert_eq!(store.register(record("t", "i1", Some(0.5))).unwrap(), 1);
+        assert_eq!(store.register(record("t", "i2", Some(0.7))).unwrap(), 2);
+        assert_eq!(store.register(record("t", "i3", Some(0.6))).unwrap(), 3);
+
+        // Nothing reaches production until it is promoted.
+        assert_eq!(store.get_by_task_id("t").unwrap(), None);
+        assert_eq!(store.find_best_match("sig", "i2").unwrap(), None);
+
+        store.promote("t", 1).unwrap();
+        store.promote("t", 2).unwrap();
+        assert_eq!(store.get_by_task_id("t").unwrap().unwrap().version, 2);
+        assert_eq!(store.find_best_match("sig", "i1").unwrap(), None);
+        assert_eq!(store.find_best_match("sig", "i2").unwrap().unwrap().version, 2);
+
+        let versions = store.versions("t").unwrap();
+        assert_eq!(versions.iter().map(|r| r.version).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(versions.iter().map(|r| r.active).collect::<Vec<_>>(), vec![false, true, false]);
+
+        assert!(matches!(
+            store.promote("t", 4),
+            Err(RegistryError::UnknownVersion { version: 4, .. })
+        ));
+
+        let before = store.get_version("t", 1).unwrap().unwrap().updated_at;
+        // Timestamps are in milliseconds.
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let reactivated = store.rollback("t").unwrap();
+        assert_eq!(reactivated.version, 1);
+        assert!(reactivated.updated_at > before);
+        let active = store.get_by_task_id("t").unwrap().unwrap();
+        assert_eq!((active.version, active.updated_at), (1, reactivated.updated_at));
+        assert!(matches!(store.rollback("t"), Err(RegistryError::NoPriorVersion(_))));
+        assert_eq!(store.get_by_task_id("t").unwrap().unwrap().version, 1);
+
+        let diff = store.diff("t", 1, 2).unwrap();
+        assert_eq!(
+            diff.instruction_hash,
+            Some(Change { before: "i1".to_string(), after: "i2".to_string() })
+        );
+        assert_eq!(diff.fitness_score, Some(Change { before: Some(0.5), after: Some(0.7) }));
+        assert!(diff.instruction.is_some());
+        assert_eq!(diff.training_hash, None);
+        assert!(store.diff("t", 2, 2).unwrap().is_empty());
+
+        let weighted = OptimizationRecord {
+            weights_path: Some(PathBuf::from("weights.bin")),
+            ..record("t", "i1", Some(0.5))
+        };
+        let diff = RecordDiff::between(&store.get_version("t", 1).unwrap().unwrap(), &weighted);
+        assert_eq!(diff.weights_path, Some(Change { before: None, after: Some("weights.bin".into()) }));
+        assert!(!diff.is_empty());
+        assert!(store.diff("t", 1, 9).is_err());
+    }
+
+    #[test]
+    fn test_memory_registry() {
+        check_store(&OptimizationRegistry::new());
+    }
+
+    #[test]
+    fn test_memory_registry_history() {
+        check_history(&OptimizationRegistry::new());
+    }
+}
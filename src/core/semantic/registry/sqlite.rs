@@ -0,0 +1,325 @@
+//! SQLite-backed optimization registry.
+//!
+//! The database can be shared by several processes: it runs in WAL mode, waits on locks instead
+//! of failing, and migrates its schema inside an immediate transaction so only one process
+//! applies a migration.
+
+use super::{now, OptimizationRecord, RegistryError, RegistryStore};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Params, Row, TransactionBehavior};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Schema migrations, in order. The schema version is the number of migrations applied and is
+/// kept in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // Every registration is kept as a version. `promotion` orders the promotions of a task: the
+    // version with the highest one is active. `demos` is a JSON array.
+    "
+    CREATE TABLE optimization_versions (
+        task_id TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        signature_hash TEXT NOT NULL,
+        instruction_hash TEXT NOT NULL,
+        optimized_instruction_hash TEXT,
+        instruction TEXT,
+        demos TEXT,
+        training_hash TEXT,
+        optimization_config_hash TEXT,
+        fitness_score REAL,
+        weights_path TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        promotion INTEGER,
+        PRIMARY KEY (task_id, version)
+    );
+    CREATE INDEX idx_versions_signature_hash ON optimization_versions (signature_hash);
+    CREATE INDEX idx_versions_instruction_hash ON optimization_versions (instruction_hash);
+    CREATE INDEX idx_versions_optimized_instruction_hash
+        ON optimization_versions (optimized_instruction_hash);
+    CREATE INDEX idx_versions_promotion ON optimization_versions (task_id, promotion);
+    ",
+];
+
+/// Selects the columns of `from_row` from `optimization_versions v`.
+const SELECT: &str = "SELECT v.task_id, v.version, v.signature_hash, v.instruction_hash, \
+    v.instruction, v.training_hash, v.optimization_config_hash, v.fitness_score, v.weights_path, \
+    v.created_at, v.updated_at, \
+    v.promotion IS NOT NULL AND v.promotion = \
+        (SELECT MAX(p.promotion) FROM optimization_versions p WHERE p.task_id = v.task_id), \
+    v.demos, v.optimized_instruction_hash \
+    FROM optimization_versions v";
+
+/// Restricts `SELECT` to active versions.
+const ACTIVE: &str = "v.promotion IS NOT NULL AND v.promotion = \
+    (SELECT MAX(p.promotion) FROM optimization_versions p WHERE p.task_id = v.task_id)";
+
+/// Registry persisted in a SQLite database file.
+pub struct SqliteRegistry {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteRegistry {
+    /// Opens the registry at `path`, creating and migrating the database as needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// A registry that lives in memory, for tests.
+    pub fn open_in_memory() -> Result<Self, RegistryError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, RegistryError> {
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// The schema version of the database.
+    pub fn schema_version(&self) -> Result<i64, RegistryError> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    fn query_one(
+        &self,
+        condition: &str,
+        params: impl Params,
+    ) -> Result<Option<OptimizationRecord>, RegistryError> {
+        let connection = self.connection.lock().unwrap();
+        Ok(connection
+            .query_row(&format!("{} WHERE {}", SELECT, condition), params, from_row)
+            .optional()?)
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), RegistryError> {
+    let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: i64 = transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let supported = MIGRATIONS.len() as i64;
+    if version > supported {
+        return Err(RegistryError::UnsupportedSchema {
+            found: version,
+            supported,
+        });
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", supported)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn from_row(row: &Row) -> rusqlite::Result<OptimizationRecord> {
+    Ok(OptimizationRecord {
+        task_id: row.get(0)?,
+        version: row.get(1)?,
+        signature_hash: row.get(2)?,
+        instruction_hash: row.get(3)?,
+        instruction: row.get(4)?,
+        training_hash: row.get(5)?,
+        optimization_config_hash: row.get(6)?,
+        fitness_score: row.get(7)?,
+        weights_path: row.get::<_, Option<String>>(8)?.map(PathBuf::from),
+        created_at: row.get::<_, i64>(9)? as u64,
+        updated_at: row.get::<_, i64>(10)? as u64,
+        active: row.get(11)?,
+        demos: match row.get::<_, Option<String>>(12)? {
+            Some(json) => serde_json::from_str(&json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(12, Type::Text, Box::new(e))
+            })?,
+            None => Vec::new(),
+        },
+        optimized_instruction_hash: row.get(13)?,
+    })
+}
+
+impl RegistryStore for SqliteRegistry {
+    fn register(&self, record: OptimizationRecord) -> Result<u32, RegistryError> {
+        let mut connection = self.connection.lock().unwrap();
+        // Immediate, so two processes cannot pick the same version number.
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let version: u32 = transaction.query_row(
+            "SELECT COALESCE(MAX(version), 0) + 1 FROM optimization_versions WHERE task_id = ?1",
+            [&record.task_id],
+            |row| row.get(0),
+        )?;
+        let now = now() as i64;
+        let demos = match record.demos.is_empty() {
+            true => None,
+            false => Some(serde_json::to_string(&record.demos)?),
+        };
+        transaction.execute(
+            "INSERT INTO optimization_versions (task_id, version, signature_hash, instruction_hash, \
+             instruction, training_hash, optimization_config_hash, fitness_score, weights_path, \
+             created_at, updated_at, demos, optimized_instruction_hash) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10, ?11, ?12)",
+            params![
+                record.task_id,
+                version,
+                record.signature_hash,
+                record.instruction_hash,
+                record.instruction,
+                record.training_hash,
+                record.optimization_config_hash,
+                record.fitness_score,
+                record.weights_path.map(|p| p.to_string_lossy().into_owned()),
+                now,
+                demos,
+                record.optimized_instruction_hash,
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(version)
+    }
+
+    fn get_by_task_id(&self, task_id: &str) -> Result<Option<OptimizationRecord>, RegistryError> {
+        self.query_one(&format!("v.task_id = ?1 AND {}", ACTIVE), [task_id])
+    }
+
+    fn find_best_match(
+        &self,
+        signature_hash: &str,
+        instruction_hash: &str,
+    ) -> Result<Option<OptimizationRecord>, RegistryError> {
+        self.query_one(
+            &format!(
+                "v.signature_hash = ?1 \
+                 AND (v.instruction_hash = ?2 OR v.optimized_instruction_hash = ?2) AND {} \
+                 ORDER BY v.fitness_score IS NULL, v.fitness_score DESC LIMIT 1",
+                ACTIVE
+            ),
+            [signature_hash, instruction_hash],
+        )
+    }
+
+    fn task_ids(&self) -> Result<Vec<String>, RegistryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT DISTINCT task_id FROM optimization_versions ORDER BY task_id")?;
+        let task_ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(task_ids)
+    }
+
+    fn versions(&self, task_id: &str) -> Result<Vec<OptimizationRecord>, RegistryError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare(&format!("{} WHERE v.task_id = ?1 ORDER BY v.version", SELECT))?;
+        let records = statement
+            .query_map([task_id], from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(records)
+    }
+
+    fn get_version(
+        &self,
+        task_id: &str,
+        version: u32,
+    ) -> Result<Option<OptimizationRecord>, RegistryError> {
+        self.query_one("v.task_id = ?1 AND v.version = ?2", params![task_id, version])
+    }
+
+    fn promote(&self, task_id: &str, version: u32) -> Result<(), RegistryError> {
+        let connection = self.connection.lock().unwrap();
+        let updated = connection.execute(
+            "UPDATE optimization_versions SET updated_at = ?3, promotion = \
+                (SELECT COALESCE(MAX(promotion), 0) + 1 FROM optimization_versions WHERE task_id = ?1) \
+             WHERE task_id = ?1 AND version = ?2",
+            params![task_id, version, now() as i64],
+        )?;
+        if updated == 0 {
+            return Err(RegistryError::UnknownVersion {
+                task_id: task_id.to_string(),
+                version,
+            });
+        }
+        Ok(())
+    }
+
+    fn rollback(&self, task_id: &str) -> Result<OptimizationRecord, RegistryError> {
+        {
+            let mut connection = self.connection.lock().unwrap();
+            let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let promoted: i64 = transaction.query_row(
+                "SELECT COUNT(*) FROM optimization_versions WHERE task_id = ?1 AND promotion IS NOT NULL",
+                [task_id],
+                |row| row.get(0),
+            )?;
+            if promoted < 2 {
+                return Err(RegistryError::NoPriorVersion(task_id.to_string()));
+            }
+            transaction.execute(
+                "UPDATE optimization_versions SET promotion = NULL WHERE task_id = ?1 AND promotion = \
+                    (SELECT MAX(promotion) FROM optimization_versions WHERE task_id = ?1)",
+                [task_id],
+            )?;
+            transaction.execute(
+                "UPDATE optimization_versions SET updated_at = ?2 WHERE task_id = ?1 AND promotion = \
+                    (SELECT MAX(promotion) FROM optimization_versions WHERE task_id = ?1)",
+                params![task_id, now() as i64],
+            )?;
+            transaction.commit()?;
+        }
+        Ok(self.get_by_task_id(task_id)?.expect("a promoted version remains"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::semantic::registry::tests::{check_history, check_store, publish, record};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_sqlite_registry() {
+        check_store(&SqliteRegistry::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_sqlite_registry_history() {
+        check_history(&SqliteRegistry::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn test_records_are_shared_and_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("orichalcum-{}.db", uuid::Uuid::new_v4()));
+
+        // Two handles on the same file stand in for two processes.
+        let first = Arc::new(SqliteRegistry::open(&path).unwrap());
+        let second = Arc::new(SqliteRegistry::open(&path).unwrap());
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let registry = if i % 2 == 0 { first.clone() } else { second.clone() };
+                std::thread::spawn(move || {
+                    for j in 0..10 {
+                        let fitness = Some((i * 10 + j) as f64);
+                        publish(&*registry, record(&format!("task-{}-{}", i, j), "i", fitness));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        drop((first, second));
+
+        let reopened = SqliteRegistry::open(&path).unwrap();
+        assert_eq!(reopened.schema_version().unwrap(), MIGRATIONS.len() as i64);
+        assert_eq!(reopened.find_best_match("sig", "i").unwrap().unwrap().task_id, "task-7-9");
+        let record = reopened.get_by_task_id("task-3-4").unwrap().unwrap();
+        assert_eq!(record.fitness_score, Some(34.0));
+
+        drop(reopened);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
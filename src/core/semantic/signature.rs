@@ -1,3 +1,4 @@
+use crate::core::semantic::redaction::Redaction;
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
@@ -8,6 +9,9 @@ use std::str::FromStr;
 pub struct Field {
     pub name: String,
     pub description: String,
+    /// How the field's value is redacted in traces, if at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<Redaction>,
 }
 
 impl Field {
@@ -15,6 +19,7 @@ impl Field {
         Self {
             name: name.into(),
             description: description.into(),
+            redaction: None,
         }
     }
 }
@@ -46,6 +51,16 @@ impl Signature {
         self
     }
 
+    /// Redacts the input or output field `name` in recorded traces.
+    pub fn redact(mut self, name: &str, redaction: Redaction) -> Self {
+        for field in self.inputs.iter_mut().chain(self.outputs.iter_mut()) {
+            if field.name == name {
+                field.redaction = Some(redaction.clone());
+            }
+        }
+        self
+    }
+
     /// Returns a stable, structural hash of the signature.
     /// Descriptions are EXCLUDED from this hash as per the spec,
     /// to ensure prompt refinement doesn't break structural identity.
@@ -62,6 +77,20 @@ impl Signature {
     }
 }
 
+/// Formats the signature using the same shorthand `FromStr` accepts: "input1, input2 -> output1"
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = |fields: &[Field]| {
+            fields
+                .iter()
+                .map(|field| field.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "{} -> {}", names(&self.inputs), names(&self.outputs))
+    }
+}
+
 impl FromStr for Signature {
     type Err = String;
 
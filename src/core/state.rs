@@ -0,0 +1,253 @@
+//! Typed access to the shared state.
+//!
+//! Every node still reads and writes a plain `HashMap<String, NodeValue>`, this module only
+//! layers serde-based conversions on top of it so that keys can be declared once with their type
+//! and reused across nodes.
+
+use crate::core::sync_impl::NodeValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use thiserror::Error;
+
+/// Errors raised by typed shared state accesses.
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("Key '{0}' is missing from the shared state")]
+    Missing(String),
+
+    #[error("Key '{key}' does not hold a value of type {expected}: {source}")]
+    TypeMismatch {
+        key: String,
+        expected: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("Value for key '{key}' could not be serialized: {source}")]
+    Serialization {
+        key: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// A typed handle on a shared state entry.
+///
+/// Declare it once and reuse it in every node touching that entry:
+/// ```rust
+/// use orichalcum::{Key, HashMap};
+///
+/// const SUMMARY: Key<String> = Key::new("summary");
+///
+/// let mut shared = HashMap::new();
+/// SUMMARY.set(&mut shared, "short".to_string()).unwrap();
+/// assert_eq!(SUMMARY.get(&shared).unwrap(), "short");
+/// ```
+pub struct Key<T> {
+    name: &'static str,
+    _marker: PhantomData<fn() -> T>,
+}
+
+// Manual impls, deriving would needlessly require `T: Clone`.
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Key<T> {}
+
+impl<T> std::fmt::Debug for Key<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Key")
+            .field("name", &self.name)
+            .field("type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+impl<T> Key<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T> Key<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Reads the entry, failing if it is missing or holds another type.
+    pub fn get(&self, shared: &HashMap<String, NodeValue>) -> Result<T, StateError> {
+        self.get_opt(shared)?
+            .ok_or_else(|| StateError::Missing(self.name.to_string()))
+    }
+
+    /// Reads the entry, returning `None` if it is missing.
+    pub fn get_opt(&self, shared: &HashMap<String, NodeValue>) -> Result<Option<T>, StateError> {
+        get_typed(shared, self.name)
+    }
+
+    /// Writes the entry, replacing any previous value.
+    pub fn set(&self, shared: &mut HashMap<String, NodeValue>, value: T) -> Result<(), StateError> {
+        set_typed(shared, self.name, value)
+    }
+}
+
+fn get_typed<T: DeserializeOwned>(
+    shared: &HashMap<String, NodeValue>,
+    key: &str,
+) -> Result<Option<T>, StateError> {
+    match shared.get(key) {
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|source| StateError::TypeMismatch {
+                key: key.to_string(),
+                expected: std::any::type_name::<T>(),
+                source,
+            }),
+        None => Ok(None),
+    }
+}
+
+fn set_typed<T: Serialize>(
+    shared: &mut HashMap<String, NodeValue>,
+    key: &str,
+    value: T,
+) -> Result<(), StateError> {
+    let value = serde_json::to_value(value).map_err(|source| StateError::Serialization {
+        key: key.to_string(),
+        source,
+    })?;
+    shared.insert(key.to_string(), value);
+    Ok(())
+}
+
+/// A typed wrapper around the shared state map.
+///
+/// It derefs to the underlying `HashMap`, so it can be handed to `Flow::run` and friends as-is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SharedState(HashMap<String, NodeValue>);
+
+impl SharedState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `key` as a `T`, failing if it is missing or holds another type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T, StateError> {
+        self.get_opt(key)?
+            .ok_or_else(|| StateError::Missing(key.to_string()))
+    }
+
+    /// Reads `key` as a `T`, returning `None` if it is missing.
+    pub fn get_opt<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StateError> {
+        get_typed(&self.0, key)
+    }
+
+    /// Writes `value` under `key`, replacing any previous value.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), StateError> {
+        set_typed(&mut self.0, key, value)
+    }
+
+    pub fn as_map(&self) -> &HashMap<String, NodeValue> {
+        &self.0
+    }
+
+    pub fn as_map_mut(&mut self) -> &mut HashMap<String, NodeValue> {
+        &mut self.0
+    }
+
+    pub fn into_inner(self) -> HashMap<String, NodeValue> {
+        self.0
+    }
+}
+
+impl From<HashMap<String, NodeValue>> for SharedState {
+    fn from(map: HashMap<String, NodeValue>) -> Self {
+        Self(map)
+    }
+}
+
+impl From<SharedState> for HashMap<String, NodeValue> {
+    fn from(state: SharedState) -> Self {
+        state.0
+    }
+}
+
+impl std::ops::Deref for SharedState {
+    type Target = HashMap<String, NodeValue>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for SharedState {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Review {
+        score: u8,
+        comment: String,
+    }
+
+    const REVIEW: Key<Review> = Key::new("review");
+    const COUNT: Key<u32> = Key::new("count");
+
+    #[test]
+    fn test_key_roundtrip() {
+        let mut shared = HashMap::new();
+        let review = Review {
+            score: 4,
+            comment: "solid".to_string(),
+        };
+        REVIEW.set(&mut shared, review.clone()).unwrap();
+
+        assert_eq!(shared.get("review"), Some(&json!({"score": 4, "comment": "solid"})));
+        assert_eq!(REVIEW.get(&shared).unwrap(), review);
+    }
+
+    #[test]
+    fn test_missing_and_mismatched_keys_are_named() {
+        let mut shared = HashMap::new();
+        assert!(matches!(COUNT.get(&shared), Err(StateError::Missing(key)) if key == "count"));
+        assert!(COUNT.get_opt(&shared).unwrap().is_none());
+
+        shared.insert("count".to_string(), json!("three"));
+        let err = COUNT.get(&shared).unwrap_err();
+        assert!(err.to_string().contains("'count'"));
+        assert!(err.to_string().contains("u32"));
+    }
+
+    #[test]
+    fn test_shared_state_wraps_map() {
+        let mut state = SharedState::new();
+        state.set("name", "Orichalcum").unwrap();
+        COUNT.set(&mut state, 2).unwrap();
+
+        assert_eq!(state.get::<String>("name").unwrap(), "Orichalcum");
+        assert_eq!(state.get::<u32>("count").unwrap(), 2);
+        assert_eq!(state.as_map().get("name"), Some(&json!("Orichalcum")));
+
+        let map: HashMap<String, NodeValue> = state.into();
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -1,16 +1,22 @@
 use crate::core::sync_impl::node::{Node, NodeLogic};
+use crate::core::graph::FlowGraph;
+use crate::core::middleware::{MiddlewareChain, NodeMiddleware};
+use crate::core::runner::{self, RunEnv};
 use crate::core::sync_impl::NodeValue;
-use crate::core::telemetry::Telemetry;
-use crate::core::validation::ValidationResult;
+use crate::core::telemetry::{SpanContext, Telemetry};
+use crate::core::validation::{self, ValidationResult};
 use crate::core::Executable;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 /// The logic that is specific to orchestration of nodes.
 #[derive(Clone)]
 pub struct FlowLogic {
-    start: Node,
-    telemetry: Option<Arc<dyn Telemetry>>,
+    pub(crate) start: Node,
+    pub(crate) telemetry: Option<Arc<dyn Telemetry>>,
+    pub(crate) middleware: MiddlewareChain,
+    /// The span of the current run of this flow, which its nodes are nested in.
+    pub(crate) span: Option<SpanContext>,
 }
 
 /// A flow really, just is a Node with orchestration logic
@@ -38,9 +44,23 @@ impl Flow {
         Flow(Node::new(FlowLogic {
             start,
             telemetry: None,
+            middleware: Vec::new(),
+            span: None,
         }))
     }
 
+    /// Wraps every node of this flow, and of the flows nested in it, with `middleware`.
+    pub fn with_middleware(mut self, middleware: impl NodeMiddleware + 'static) -> Self {
+        let behaviour: &mut dyn NodeLogic = &mut *self.behaviour;
+
+        if let Some(flow_logic) = behaviour.as_any_mut().downcast_mut::<FlowLogic>() {
+            flow_logic.middleware.push(Arc::new(middleware));
+        } else {
+            panic!("Error: Flow's logic is not of type FlowLogic");
+        }
+        self
+    }
+
     pub fn run(&self, shared: &mut HashMap<String, NodeValue>) -> Option<String> {
         self.run_with_telemetry(shared, None)
     }
@@ -51,18 +71,14 @@ impl Flow {
         shared: &mut HashMap<String, NodeValue>,
         telemetry: Option<Arc<dyn Telemetry>>,
     ) -> Option<String> {
-        let mut cloned_self = self.clone();
-        if let Some(logic) = cloned_self
-            .behaviour
-            .as_any_mut()
-            .downcast_mut::<FlowLogic>()
-        {
-            logic.telemetry = telemetry.clone();
-        }
-
-        cloned_self
-            .0
-            .run_with_telemetry(shared, telemetry.as_deref().map(|t| t as &dyn Telemetry))
+        runner::run_node(
+            &self.0,
+            shared,
+            RunEnv {
+                telemetry: telemetry.as_ref(),
+                ..Default::default()
+            },
+        )
     }
 
     pub fn start(&mut self, start: Node) {
@@ -78,67 +94,40 @@ impl Flow {
         }
     }
 
-    /// Validates the data flow integrity of the entire workflow.
-    pub fn validate(&self, initial_keys: Vec<String>) -> ValidationResult {
-        let mut result = ValidationResult::new();
-        let mut visited = HashSet::new();
-        let mut available_keys = initial_keys.into_iter().collect::<HashSet<_>>();
-
-        let behaviour: &dyn NodeLogic = &*self.behaviour;
-        if let Some(flow_logic) = behaviour.as_any().downcast_ref::<FlowLogic>() {
-            self.validate_recursive(
-                &Executable::Sync(flow_logic.start.clone()),
-                &mut available_keys,
-                &mut visited,
-                &mut result,
-            );
-        }
-
-        result
+    /// Builds the static graph model of this flow.
+    pub fn graph(&self) -> FlowGraph {
+        FlowGraph::from_executable(&self.start_executable())
     }
 
-    fn validate_recursive(
-        &self,
-        current: &Executable,
-        available_keys: &mut HashSet<String>,
-        visited: &mut HashSet<String>,
-        result: &mut ValidationResult,
-    ) {
-        let sealable = match current {
-            Executable::Sync(node) => node.behaviour.as_sealable(),
-            Executable::Async(node) => node.behaviour.as_sealable(),
-            Executable::Sealed(sealed) => {
-                Some(sealed.as_ref() as &dyn crate::core::semantic::Sealable)
-            }
-        };
-
-        if let Some(s) = sealable {
-            let signature = s.signature();
-            let task_id = s.task_id();
-
-            if visited.contains(&task_id) {
-                return;
-            }
-            visited.insert(task_id.clone());
+    /// Renders the flow's graph as a Graphviz DOT digraph.
+    pub fn to_dot(&self) -> String {
+        self.graph().to_dot()
+    }
 
-            for input in signature.inputs {
-                if !available_keys.contains(&input.name) {
-                    result.add_error(format!(
-                        "Node '{}' requires input '{}' which is missing from the shared state.",
-                        task_id, input.name
-                    ));
-                }
-            }
+    /// Renders the flow's graph as a Mermaid flowchart.
+    pub fn to_mermaid(&self) -> String {
+        self.graph().to_mermaid()
+    }
 
-            for output in signature.outputs {
-                available_keys.insert(output.name);
-            }
+    fn start_executable(&self) -> Executable {
+        let behaviour: &dyn NodeLogic = &*self.behaviour;
+        match behaviour.as_any().downcast_ref::<FlowLogic>() {
+            Some(flow_logic) => Executable::Sync(flow_logic.start.clone()),
+            None => panic!("Error: Flow's logic is not of type FlowLogic"),
         }
+    }
 
-        for successor in current.successors().values() {
-            let mut branch_keys = available_keys.clone();
-            self.validate_recursive(successor, &mut branch_keys, visited, result);
-        }
+    /// Validates the data flow integrity of the entire workflow.
+    pub fn validate(&self, initial_keys: Vec<String>) -> ValidationResult {
+        let mut result = ValidationResult::new();
+        let graph = self.graph();
+        validation::validate_paths(
+            &graph,
+            &graph.root().id,
+            initial_keys.into_iter().collect(),
+            &mut result,
+        );
+        result
     }
 }
 
@@ -171,12 +160,16 @@ impl NodeLogic for FlowLogic {
         // This is the orchestration logic
         while let Some(mut curr) = current {
             curr.set_params(params.clone());
-            last_action = curr
-                .run_with_telemetry(
-                    &mut shared,
-                    self.telemetry.as_deref().map(|t| t as &dyn Telemetry),
-                )
-                .unwrap_or("default".into());
+            last_action = runner::run_node(
+                &curr,
+                &mut shared,
+                RunEnv {
+                    telemetry: self.telemetry.as_ref(),
+                    middleware: &self.middleware,
+                    parent: self.span.as_ref(),
+                },
+            )
+            .unwrap_or("default".into());
             let next_executable = curr.data.successors.get(&last_action).cloned();
 
             match next_executable {
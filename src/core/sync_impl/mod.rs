@@ -23,6 +23,8 @@ use std::any::Any;
 pub trait AsAny {
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// The concrete type name, mostly useful for labelling nodes in diagnostics.
+    fn type_name(&self) -> &'static str;
 }
 
 impl<T: 'static> AsAny for T {
@@ -32,4 +34,7 @@ impl<T: 'static> AsAny for T {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
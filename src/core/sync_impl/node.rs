@@ -1,8 +1,12 @@
 use crate::core::sealed::SealedNode;
 use crate::core::semantic::{Promptable, Sealable};
+use crate::core::semantic::registry::RegistryStore;
 use crate::core::sync_impl::AsAny;
 use crate::core::sync_impl::NodeValue;
-use crate::core::telemetry::Telemetry;
+use crate::core::graph::display_name;
+use crate::core::middleware;
+use crate::core::runner;
+use crate::core::telemetry::{Span, Telemetry};
 use crate::core::Executable;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -35,6 +39,16 @@ impl Node {
 
     /// Seals the node, making it immutable and Snapshotting its identity.
     pub fn seal(self) -> Result<Arc<SealedNode>, String> {
+        self.seal_node().map(Arc::new)
+    }
+
+    /// Seals the node and applies the best promoted record for it in `registry`.
+    /// See [`SealedNode::optimized`].
+    pub fn seal_with_registry(self, registry: &dyn RegistryStore) -> Result<Arc<SealedNode>, String> {
+        Ok(Arc::new(self.seal_node()?.optimized(registry)))
+    }
+
+    fn seal_node(self) -> Result<SealedNode, String> {
         let sealable = self
             .behaviour
             .as_sealable()
@@ -45,11 +59,7 @@ impl Node {
         let sig_hash = signature.structural_hash();
 
         let instr_hash = if let Some(promptable) = self.behaviour.as_promptable() {
-            use std::collections::hash_map::DefaultHasher;
-            use std::hash::{Hash, Hasher};
-            let mut hasher = DefaultHasher::new();
-            promptable.instruction().hash(&mut hasher);
-            format!("{:016x}", hasher.finish())
+            promptable.instruction_hash()
         } else {
             use std::collections::hash_map::DefaultHasher;
             use std::hash::{Hash, Hasher};
@@ -72,14 +82,14 @@ impl Node {
             .unwrap_or("native")
             .to_string();
 
-        Ok(Arc::new(SealedNode::new(
+        Ok(SealedNode::new(
             Executable::Sync(self),
             task_id,
             signature,
             sig_hash,
             instr_hash,
             model_name,
-        )))
+        ))
     }
 
     /// Sets the node's parameters.
@@ -114,11 +124,17 @@ impl Node {
     pub fn run_with_telemetry(
         &self,
         shared: &mut HashMap<String, NodeValue>,
-        _telemetry: Option<&dyn Telemetry>,
+        telemetry: Option<&dyn Telemetry>,
     ) -> Option<String> {
-        let p = self.behaviour.prep(&self.data.params, shared);
-        let e = self.behaviour.exec(p.clone());
-        self.behaviour.post(shared, p, e)
+        let span = Span::start(None);
+        let (p, e, action) = middleware::run_sync_phases(self, shared, &[]);
+        let behaviour: &dyn NodeLogic = &*self.behaviour;
+        let sealable = behaviour.as_sealable();
+        let name = sealable
+            .map(|s| s.task_id())
+            .unwrap_or_else(|| display_name(behaviour.type_name()));
+        runner::record_node(telemetry, span, sealable, name, p, e, &action);
+        action
     }
 
     /// Executes the node with the given parameters, ignoring stored parameters.
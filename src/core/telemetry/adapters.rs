@@ -0,0 +1,337 @@
+//! Adapters composing `Telemetry` sinks.
+//!
+//! Flows take a single sink; fanning out, sampling, filtering and buffering are layered on top
+//! of it with these wrappers instead of being implemented by each sink.
+
+use super::{Telemetry, TraceEntry};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// Sends every entry to several sinks.
+#[derive(Default)]
+pub struct MultiTelemetry {
+    sinks: Vec<Arc<dyn Telemetry>>,
+}
+
+impl MultiTelemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, sink: Arc<dyn Telemetry>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+}
+
+impl From<Vec<Arc<dyn Telemetry>>> for MultiTelemetry {
+    fn from(sinks: Vec<Arc<dyn Telemetry>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl Telemetry for MultiTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        if let Some((last, rest)) = self.sinks.split_last() {
+            for sink in rest {
+                sink.record(entry.clone());
+            }
+            last.record(entry);
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.sinks {
+            sink.flush();
+        }
+    }
+}
+
+/// Keeps a fraction of the runs.
+///
+/// The decision is made per run id, so with a single ratio a run is either kept whole or
+/// dropped whole, and its trace tree has no holes. Task ids can get a rate of their own, which
+/// breaks that: a kept run loses the entries of tasks with a lower rate, and a dropped run keeps
+/// those of tasks with a higher one. The runs kept at a rate are also kept at every higher rate.
+pub struct SampledTelemetry {
+    inner: Arc<dyn Telemetry>,
+    ratio: f64,
+    rates: HashMap<String, f64>,
+}
+
+impl SampledTelemetry {
+    /// Keeps `ratio` (between 0 and 1) of the runs.
+    pub fn new(inner: Arc<dyn Telemetry>, ratio: f64) -> Self {
+        Self {
+            inner,
+            ratio: ratio.clamp(0.0, 1.0),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Keeps `ratio` of the runs for the entries of `task_id`, instead of the default ratio.
+    /// Runs may then be kept with some of their entries missing; see [`SampledTelemetry`].
+    pub fn with_rate(mut self, task_id: impl Into<String>, ratio: f64) -> Self {
+        self.rates.insert(task_id.into(), ratio.clamp(0.0, 1.0));
+        self
+    }
+
+    fn keeps(&self, entry: &TraceEntry) -> bool {
+        let ratio = self.rates.get(&entry.task_id).copied().unwrap_or(self.ratio);
+        let mut hasher = DefaultHasher::new();
+        entry.run_id.hash(&mut hasher);
+        ratio >= 1.0 || (hasher.finish() as f64 / u64::MAX as f64) < ratio
+    }
+}
+
+impl Telemetry for SampledTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        if self.keeps(&entry) {
+            self.inner.record(entry);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Only forwards the entries matching a predicate.
+pub struct FilteredTelemetry {
+    inner: Arc<dyn Telemetry>,
+    predicate: Box<dyn Fn(&TraceEntry) -> bool + Send + Sync>,
+}
+
+impl FilteredTelemetry {
+    pub fn new<P>(inner: Arc<dyn Telemetry>, predicate: P) -> Self
+    where
+        P: Fn(&TraceEntry) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            predicate: Box::new(predicate),
+        }
+    }
+}
+
+impl Telemetry for FilteredTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        if (self.predicate)(&entry) {
+            self.inner.record(entry);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+enum Message {
+    Entry(Box<TraceEntry>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// Hands entries to a background thread that records them, so slow sinks don't hold up nodes.
+///
+/// `flush` waits until every entry queued before it was recorded and the inner sink flushed.
+/// Dropping the adapter drains the queue.
+pub struct BufferedTelemetry {
+    /// Only taken on drop; senders are cloned out so no send happens under the lock.
+    sender: Mutex<Option<SyncSender<Message>>>,
+    worker: Option<JoinHandle<()>>,
+    dropped: AtomicU64,
+}
+
+impl BufferedTelemetry {
+    /// Queues up to `capacity` entries; entries arriving while the queue is full are dropped.
+    pub fn new(inner: Arc<dyn Telemetry>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let worker = std::thread::Builder::new()
+            .name("orichalcum-telemetry".to_string())
+            .spawn(move || drain(inner, receiver))
+            .expect("failed to spawn the telemetry thread");
+        Self {
+            sender: Mutex::new(Some(sender)),
+            worker: Some(worker),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    fn sender(&self) -> Option<SyncSender<Message>> {
+        self.sender.lock().unwrap().clone()
+    }
+
+    /// How many entries were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn drain(inner: Arc<dyn Telemetry>, receiver: Receiver<Message>) {
+    for message in receiver {
+        match message {
+            Message::Entry(entry) => inner.record(*entry),
+            Message::Flush(done) => {
+                inner.flush();
+                let _ = done.send(());
+            }
+        }
+    }
+    inner.flush();
+}
+
+impl Telemetry for BufferedTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        let Some(sender) = self.sender() else {
+            return;
+        };
+        if let Err(TrySendError::Full(_)) = sender.try_send(Message::Entry(Box::new(entry))) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        let sent = self.sender().is_some_and(|sender| sender.send(Message::Flush(done)).is_ok());
+        if sent {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+impl Drop for BufferedTelemetry {
+    fn drop(&mut self) {
+        self.sender.lock().unwrap().take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::telemetry::MemoryTelemetry;
+
+    fn entry(run_id: &str, task_id: &str) -> TraceEntry {
+        TraceEntry {
+            run_id: run_id.to_string(),
+            task_id: task_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_adapters_compose() {
+        let all = Arc::new(MemoryTelemetry::new());
+        let llm = Arc::new(MemoryTelemetry::new());
+        let telemetry = MultiTelemetry::new().with(all.clone()).with(Arc::new(
+            FilteredTelemetry::new(llm.clone(), |e| !e.model_name.is_empty()),
+        ));
+
+        telemetry.record(entry("run", "plain"));
+        telemetry.record(TraceEntry {
+            model_name: "gpt-4o".to_string(),
+            ..entry("run", "semantic")
+        });
+
+        assert_eq!(all.get_traces().len(), 2);
+        let llm = llm.get_traces();
+        assert_eq!(llm.len(), 1);
+        assert_eq!(llm[0].task_id, "semantic");
+    }
+
+    #[test]
+    fn test_sampling_keeps_whole_runs() {
+        let memory = Arc::new(MemoryTelemetry::new());
+        let telemetry = SampledTelemetry::new(memory.clone(), 0.5)
+            .with_rate("always", 1.0)
+            .with_rate("never", 0.0);
+
+        for run in 0..200 {
+            let run_id = format!("run-{}", run);
+            for task_id in ["a", "b", "always", "never"] {
+                telemetry.record(entry(&run_id, task_id));
+            }
+        }
+
+        let traces = memory.get_traces();
+        let count = |task_id: &str| traces.iter().filter(|t| t.task_id == task_id).count();
+        assert_eq!(count("always"), 200);
+        assert_eq!(count("never"), 0);
+        assert_eq!(count("a"), count("b"));
+        assert!((50..150).contains(&count("a")));
+        for trace in traces.iter().filter(|t| t.task_id == "a") {
+            assert!(traces.iter().any(|t| t.task_id == "b" && t.run_id == trace.run_id));
+        }
+    }
+
+    #[test]
+    fn test_buffered_flush_waits_for_queued_entries() {
+        let memory = Arc::new(MemoryTelemetry::new());
+        let telemetry = BufferedTelemetry::new(memory.clone(), 1_000);
+        for i in 0..100 {
+            telemetry.record(entry("run", &i.to_string()));
+        }
+        telemetry.flush();
+        assert_eq!(memory.get_traces().len(), 100);
+
+        telemetry.record(entry("run", "last"));
+        drop(telemetry);
+        assert_eq!(memory.get_traces().len(), 101);
+    }
+
+    /// Records nothing until the gate opens, after reporting each entry it holds up.
+    struct Gated {
+        gate: Arc<Mutex<()>>,
+        entered: Mutex<mpsc::Sender<()>>,
+    }
+
+    impl Telemetry for Gated {
+        fn record(&self, _entry: TraceEntry) {
+            let _ = self.entered.lock().unwrap().send(());
+            drop(self.gate.lock().unwrap());
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_buffered_record_drops_instead_of_waiting_on_a_blocked_flush() {
+        let gate = Arc::new(Mutex::new(()));
+        let closed = gate.lock().unwrap();
+        let (entered, recording) = mpsc::channel();
+        let inner = Arc::new(Gated { gate: gate.clone(), entered: Mutex::new(entered) });
+        let telemetry = Arc::new(BufferedTelemetry::new(inner, 1));
+
+        // The worker holds the first entry and the second fills the queue.
+        telemetry.record(entry("run", "held"));
+        recording.recv().unwrap();
+        telemetry.record(entry("run", "queued"));
+
+        let flushing = std::thread::spawn({
+            let telemetry = telemetry.clone();
+            move || telemetry.flush()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let (done, recorded) = mpsc::channel();
+        std::thread::spawn({
+            let telemetry = telemetry.clone();
+            move || {
+                telemetry.record(entry("run", "dropped"));
+                let _ = done.send(());
+            }
+        });
+        let result = recorded.recv_timeout(std::time::Duration::from_secs(2));
+        drop(closed);
+        assert!(result.is_ok(), "record blocked behind flush");
+        assert_eq!(telemetry.dropped(), 1);
+        flushing.join().unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+//! JSON Lines sink for execution traces, meant for local debugging.
+//!
+//! Each entry is appended as one line. The active file can be rotated once it grows past a size
+//! or gets too old; rotated files are renamed to `<file>.<micros>-<n>` and optionally gzipped.
+
+use super::{Telemetry, TraceEntry, TraceQuery};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Appends trace entries to a `.jsonl` file, rotating it by size and/or age.
+///
+/// Writes go through a mutex, so a single sink can be shared by concurrent tasks.
+pub struct JsonlTelemetry {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    gzip: bool,
+    state: Mutex<JsonlState>,
+}
+
+struct JsonlState {
+    writer: BufWriter<File>,
+    bytes: u64,
+    opened_at: Instant,
+    rotations: usize,
+    rotated: Vec<PathBuf>,
+}
+
+impl JsonlTelemetry {
+    /// Appends to `path`, creating it (and its directory) if needed.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let (writer, bytes) = open(&path)?;
+        Ok(Self {
+            path,
+            max_bytes: None,
+            max_age: None,
+            gzip: false,
+            state: Mutex::new(JsonlState {
+                writer,
+                bytes,
+                opened_at: Instant::now(),
+                rotations: 0,
+                rotated: Vec::new(),
+            }),
+        })
+    }
+
+    /// Rotates the file before it would grow past `bytes`.
+    pub fn with_max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Rotates the file once it has been open for `age`.
+    pub fn with_max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Gzips rotated files (`<file>.<micros>-<n>.gz`).
+    pub fn with_gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// The path of the file currently written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The files rotated out by this sink so far, oldest first.
+    pub fn rotated_files(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().rotated.clone()
+    }
+
+    /// Every entry recorded by this sink: its rotated files, oldest first, then the current one.
+    pub fn traces(&self) -> io::Result<Vec<TraceEntry>> {
+        self.flush();
+        let mut paths = self.rotated_files();
+        paths.push(self.path.clone());
+        let mut entries = Vec::new();
+        for path in paths {
+            // A rotated file may have been compressed since it was listed.
+            let reader = Self::read(&path).or_else(|e| match e.kind() {
+                io::ErrorKind::NotFound => Self::read(gzipped(&path)),
+                _ => Err(e),
+            })?;
+            for entry in reader {
+                entries.push(entry?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The entries recorded by this sink that match `query`.
+    pub fn query(&self, query: &TraceQuery) -> io::Result<Vec<TraceEntry>> {
+        Ok(query.apply(self.traces()?))
+    }
+
+    /// Iterates over the entries of a file written by this sink, gzipped (`.gz`) or not.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<TraceReader> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let reader: Box<dyn Read + Send> = if path.extension().is_some_and(|ext| ext == "gz") {
+            Box::new(GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        Ok(TraceReader {
+            lines: BufReader::new(reader).lines(),
+        })
+    }
+
+    fn write_line(&self, line: &[u8]) -> io::Result<()> {
+        let rotated = {
+            let mut state = self.state.lock().unwrap();
+            let too_big = self
+                .max_bytes
+                .is_some_and(|max| state.bytes > 0 && state.bytes + line.len() as u64 > max);
+            let too_old = self
+                .max_age
+                .is_some_and(|age| state.bytes > 0 && state.opened_at.elapsed() >= age);
+            // A failed rotation keeps writing to the current file rather than losing the entry.
+            let rotated = match too_big || too_old {
+                true => self
+                    .rotate(&mut state)
+                    .map_err(|e| log::error!("Failed to rotate {}: {}", self.path.display(), e))
+                    .ok(),
+                false => None,
+            };
+            state.writer.write_all(line)?;
+            state.bytes += line.len() as u64;
+            rotated
+        };
+        if let Some(rotated) = rotated.filter(|_| self.gzip) {
+            self.compress(rotated);
+        }
+        Ok(())
+    }
+
+    /// Renames the file out of the way and starts a new one. Returns the renamed file, which is
+    /// tracked right away so it is read back even if compressing it fails.
+    fn rotate(&self, state: &mut JsonlState) -> io::Result<PathBuf> {
+        state.writer.flush()?;
+        state.rotations += 1;
+        let micros = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}-{}", micros, state.rotations));
+        let rotated = PathBuf::from(name);
+        std::fs::rename(&self.path, &rotated)?;
+        state.rotated.push(rotated.clone());
+
+        let (writer, bytes) = open(&self.path)?;
+        state.writer = writer;
+        state.bytes = bytes;
+        state.opened_at = Instant::now();
+        Ok(rotated)
+    }
+
+    /// Gzips a rotated file without holding the writer lock, then tracks the compressed file in
+    /// its place. On failure the uncompressed file stays tracked.
+    fn compress(&self, rotated: PathBuf) {
+        let compressed = match gzip(&rotated) {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                log::error!("Failed to gzip {}: {}", rotated.display(), e);
+                let _ = std::fs::remove_file(gzipped(&rotated));
+                return;
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        if let Some(tracked) = state.rotated.iter_mut().find(|path| **path == rotated) {
+            *tracked = compressed;
+        }
+        drop(state);
+        if let Err(e) = std::fs::remove_file(&rotated) {
+            log::error!("Failed to remove {}: {}", rotated.display(), e);
+        }
+    }
+}
+
+impl Telemetry for JsonlTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                log::error!("Failed to serialize trace entry for '{}': {}", entry.task_id, e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = self.write_line(&line) {
+            log::error!("Failed to write trace to {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Err(e) = state
+            .writer
+            .flush()
+            .and_then(|_| state.writer.get_ref().sync_data())
+        {
+            log::error!("Failed to flush traces to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl Drop for JsonlTelemetry {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Iterator over the entries of a JSONL trace file.
+pub struct TraceReader {
+    lines: io::Lines<BufReader<Box<dyn Read + Send>>>,
+}
+
+impl Iterator for TraceReader {
+    type Item = io::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            );
+        }
+    }
+}
+
+fn open(path: &Path) -> io::Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let bytes = file.metadata()?.len();
+    Ok((BufWriter::new(file), bytes))
+}
+
+fn gzipped(path: &Path) -> PathBuf {
+    let mut name = path.to_path_buf().into_os_string();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Compresses `path` into `path.gz`, leaving the original for the caller to remove.
+fn gzip(path: &Path) -> io::Result<PathBuf> {
+    let target = gzipped(path);
+    let mut encoder = GzEncoder::new(File::create(&target)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn entry(task_id: String) -> TraceEntry {
+        TraceEntry {
+            task_id,
+            inputs: serde_json::json!({"text": "x".repeat(50)}),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_writes_rotate_and_read_back() {
+        let directory = std::env::temp_dir().join(format!("orichalcum-{}", uuid::Uuid::new_v4()));
+        let telemetry = Arc::new(
+            JsonlTelemetry::new(directory.join("traces.jsonl"))
+                .unwrap()
+                .with_max_bytes(1_000)
+                .with_gzip(true),
+        );
+
+        let tasks: Vec<_> = (0..40)
+            .map(|i| {
+                let telemetry = telemetry.clone();
+                tokio::spawn(async move { telemetry.record(entry(format!("task-{}", i))) })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        telemetry.flush();
+
+        let rotated = telemetry.rotated_files();
+        assert!(!rotated.is_empty());
+        assert!(rotated.iter().all(|p| p.extension().is_some_and(|ext| ext == "gz")));
+
+        let mut task_ids: Vec<String> = telemetry
+            .traces()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.task_id)
+            .collect();
+        task_ids.sort();
+        let mut expected: Vec<String> = (0..40).map(|i| format!("task-{}", i)).collect();
+        expected.sort();
+        assert_eq!(task_ids, expected);
+
+        drop(telemetry);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_rotated_files_stay_tracked_when_gzip_fails() {
+        let directory = std::env::temp_dir().join(format!("orichalcum-{}", uuid::Uuid::new_v4()));
+        let telemetry = JsonlTelemetry::new(directory.join("traces.jsonl"))
+            .unwrap()
+            .with_max_bytes(200);
+        for i in 0..3 {
+            telemetry.record(entry(format!("task-{}", i)));
+        }
+        let rotated = telemetry.rotated_files();
+        assert_eq!(rotated.len(), 2);
+
+        // A directory in the way of the compressed file makes gzip fail.
+        std::fs::create_dir(gzipped(&rotated[0])).unwrap();
+        telemetry.compress(rotated[0].clone());
+        telemetry.compress(rotated[1].clone());
+
+        let tracked = telemetry.rotated_files();
+        assert_eq!(tracked[0], rotated[0]);
+        assert_eq!(tracked[1], gzipped(&rotated[1]));
+        assert!(!rotated[1].exists());
+        let task_ids: Vec<String> = telemetry.traces().unwrap().into_iter().map(|e| e.task_id).collect();
+        assert_eq!(task_ids, vec!["task-0", "task-1", "task-2"]);
+
+        drop(telemetry);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_drop_flushes_buffered_entries() {
+        let path = std::env::temp_dir().join(format!("orichalcum-{}.jsonl", uuid::Uuid::new_v4()));
+        let telemetry = JsonlTelemetry::new(&path).unwrap();
+        telemetry.record(entry("only".to_string()));
+        drop(telemetry);
+
+        let entries: Vec<TraceEntry> = JsonlTelemetry::read(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].task_id, "only");
+        std::fs::remove_file(path).unwrap();
+    }
+}
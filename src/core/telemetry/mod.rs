@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use crate::core::sync_impl::NodeValue;
+use crate::core::events;
+use serde::{Serialize, Deserialize};
+
+pub mod adapters;
+#[cfg(feature = "telemetry")]
+pub mod jsonl;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "telemetry")]
+pub mod parquet;
+pub mod query;
+pub mod usage;
+
+pub use query::{GroupBy, TraceQuery, TraceStats};
+pub use usage::TokenUsage;
+
+/// What a trace entry describes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    #[default]
+    Node,
+    Sealed,
+    Flow,
+}
+
+/// A single entry in the execution trace.
+///
+/// Every entry is a span: `span_id`/`parent_span_id` link the nodes of a run (shared `run_id`)
+/// to the flows they ran in, so the whole execution tree can be rebuilt from the entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TraceEntry {
+    /// Start of the span, in seconds since the epoch.
+    pub timestamp: u64,
+    /// The task id, or the node's type name for nodes without one.
+    pub task_id: String,
+    pub signature_hash: String,
+    pub instruction_hash: String,
+    pub inputs: NodeValue,
+    pub outputs: NodeValue,
+    pub model_name: String,
+    pub training_hash: Option<String>,
+    pub fitness_score: Option<f64>,
+    pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub run_id: String,
+    #[serde(default)]
+    pub span_id: String,
+    #[serde(default)]
+    pub parent_span_id: Option<String>,
+    #[serde(default)]
+    pub kind: SpanKind,
+    /// Start of the span, in microseconds since the epoch.
+    #[serde(default)]
+    pub start_time_us: u64,
+    /// End of the span, in microseconds since the epoch.
+    #[serde(default)]
+    pub end_time_us: u64,
+    #[serde(default)]
+    pub duration_us: u64,
+    /// The action returned by the node or flow.
+    #[serde(default)]
+    pub action: Option<String>,
+    /// Tokens used by the LLM calls made during the span.
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    /// Time spent waiting on those calls, in microseconds.
+    #[serde(default)]
+    pub llm_latency_us: Option<u64>,
+    /// Cost of those calls, if the client's price table knows the model.
+    #[serde(default)]
+    pub cost: Option<f64>,
+}
+
+/// The run a span belongs to, and the span it is nested in.
+#[derive(Debug, Clone)]
+pub(crate) struct SpanContext {
+    run_id: String,
+    parent_span_id: Option<String>,
+}
+
+impl SpanContext {
+    /// A new run, under the run id of its events if anyone is listening to them.
+    fn root() -> Self {
+        Self {
+            run_id: events::run_id().unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()),
+            parent_span_id: None,
+        }
+    }
+}
+
+/// A span being measured.
+pub(crate) struct Span {
+    id: String,
+    context: SpanContext,
+    started_at_us: u64,
+    started: std::time::Instant,
+}
+
+impl Span {
+    /// Starts a span under `parent`, or as the root of a new run.
+    pub(crate) fn start(parent: Option<&SpanContext>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string()[..16].to_string(),
+            context: parent.cloned().unwrap_or_else(SpanContext::root),
+            started_at_us: now_us(),
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// The context of the spans nested in this one.
+    pub(crate) fn children(&self) -> SpanContext {
+        SpanContext {
+            run_id: self.context.run_id.clone(),
+            parent_span_id: Some(self.id.clone()),
+        }
+    }
+
+    /// Stamps `entry` with this span's identity and timing.
+    pub(crate) fn finish(self, entry: TraceEntry) -> TraceEntry {
+        let duration_us = self.started.elapsed().as_micros() as u64;
+        TraceEntry {
+            timestamp: self.started_at_us / 1_000_000,
+            run_id: self.context.run_id,
+            span_id: self.id,
+            parent_span_id: self.context.parent_span_id,
+            start_time_us: self.started_at_us,
+            end_time_us: self.started_at_us + duration_us,
+            duration_us,
+            ..entry
+        }
+    }
+}
+
+fn now_us() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Trait for recording execution traces.
+pub trait Telemetry: Send + Sync {
+    fn record(&self, entry: TraceEntry);
+    fn flush(&self);
+}
+
+/// Simple in-memory collector for traces.
+pub struct MemoryTelemetry {
+    traces: std::sync::Mutex<Vec<TraceEntry>>,
+}
+
+impl MemoryTelemetry {
+    pub fn new() -> Self {
+        Self {
+            traces: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get_traces(&self) -> Vec<TraceEntry> {
+        self.traces.lock().unwrap().clone()
+    }
+
+    /// The traces matching `query`, without cloning the others.
+    pub fn query(&self, query: &TraceQuery) -> Vec<TraceEntry> {
+        let traces = self.traces.lock().unwrap();
+        traces.iter().filter(|e| query.matches(e)).cloned().collect()
+    }
+}
+
+impl Telemetry for MemoryTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        self.traces.lock().unwrap().push(entry);
+    }
+
+    fn flush(&self) {
+        // No-op for memory collector
+    }
+}
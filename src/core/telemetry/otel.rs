@@ -0,0 +1,261 @@
+//! Exports execution traces as OpenTelemetry spans.
+//!
+//! Each trace entry becomes one span: the run id is the trace id, and span ids and parents are
+//! kept, so the execution tree shows up as-is in any OTLP backend. LLM calls carry the GenAI
+//! semantic-convention attributes next to Orichalcum's own.
+
+use super::{SpanKind, Telemetry, TraceEntry};
+use opentelemetry::trace::{
+    Span as _, SpanBuilder, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId,
+    TraceState, Tracer, TracerProvider,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OtelTelemetryError {
+    #[error("Failed to build the OTLP exporter: {0}")]
+    Exporter(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+/// Turns trace entries into OpenTelemetry spans, sent through the given tracer provider.
+pub struct OtelTelemetry {
+    provider: SdkTracerProvider,
+    tracer: SdkTracer,
+}
+
+impl OtelTelemetry {
+    /// Records spans through `provider`, which decides how and where they are exported.
+    pub fn new(provider: SdkTracerProvider) -> Self {
+        let tracer = provider.tracer("orichalcum");
+        Self { provider, tracer }
+    }
+
+    /// Exports spans in batches to an OTLP/HTTP collector, e.g. `http://localhost:4318/v1/traces`.
+    pub fn otlp(endpoint: impl Into<String>) -> Result<Self, OtelTelemetryError> {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()?;
+        let provider = SdkTracerProvider::builder()
+            .with_resource(Resource::builder().with_service_name("orichalcum").build())
+            .with_batch_exporter(exporter)
+            .build();
+        Ok(Self::new(provider))
+    }
+
+    pub fn provider(&self) -> &SdkTracerProvider {
+        &self.provider
+    }
+}
+
+impl Telemetry for OtelTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        let trace_id = TraceId::from_hex(&entry.run_id).ok();
+        let parent = entry
+            .parent_span_id
+            .as_deref()
+            .and_then(|id| SpanId::from_hex(id).ok())
+            .zip(trace_id)
+            .map(|(span_id, trace_id)| {
+                Context::new().with_remote_span_context(SpanContext::new(
+                    trace_id,
+                    span_id,
+                    TraceFlags::SAMPLED,
+                    true,
+                    TraceState::default(),
+                ))
+            })
+            .unwrap_or_default();
+
+        let (start, end) = if entry.start_time_us == 0 {
+            (SystemTime::now(), SystemTime::now())
+        } else {
+            (time(entry.start_time_us), time(entry.end_time_us))
+        };
+        let mut builder = SpanBuilder::from_name(entry.task_id.clone())
+            .with_kind(opentelemetry::trace::SpanKind::Internal)
+            .with_start_time(start)
+            .with_attributes(attributes(&entry));
+        builder.trace_id = trace_id;
+        builder.span_id = SpanId::from_hex(&entry.span_id).ok();
+
+        let mut span = self.tracer.build_with_context(builder, &parent);
+        span.end_with_timestamp(end);
+    }
+
+    fn flush(&self) {
+        if let Err(e) = self.provider.force_flush() {
+            log::error!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+fn time(us: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(us)
+}
+
+/// GenAI semantic-convention attributes for LLM calls, and `orichalcum.*` for the rest. An entry
+/// is an LLM call if it has a latency or token usage; other nodes may still name a model.
+fn attributes(entry: &TraceEntry) -> Vec<KeyValue> {
+    let kind = match entry.kind {
+        SpanKind::Node => "node",
+        SpanKind::Sealed => "sealed",
+        SpanKind::Flow => "flow",
+    };
+    let mut attributes = vec![
+        KeyValue::new("orichalcum.task_id", entry.task_id.clone()),
+        KeyValue::new("orichalcum.span_kind", kind),
+    ];
+    if let Some(action) = &entry.action {
+        attributes.push(KeyValue::new("orichalcum.action", action.clone()));
+    }
+    if !entry.signature_hash.is_empty() {
+        attributes.push(KeyValue::new("orichalcum.signature_hash", entry.signature_hash.clone()));
+    }
+    if !entry.instruction_hash.is_empty() {
+        attributes.push(KeyValue::new("orichalcum.instruction_hash", entry.instruction_hash.clone()));
+    }
+    let llm_call = entry.llm_latency_us.is_some() || entry.usage.is_some();
+    if llm_call {
+        attributes.push(KeyValue::new("gen_ai.operation.name", "chat"));
+        if !entry.model_name.is_empty() {
+            attributes.push(KeyValue::new("gen_ai.request.model", entry.model_name.clone()));
+        }
+    } else if !entry.model_name.is_empty() {
+        attributes.push(KeyValue::new("orichalcum.model_name", entry.model_name.clone()));
+    }
+    if let Some(usage) = entry.usage {
+        attributes.push(KeyValue::new("gen_ai.usage.input_tokens", usage.input_tokens as i64));
+        attributes.push(KeyValue::new("gen_ai.usage.output_tokens", usage.output_tokens as i64));
+    }
+    if let Some(cost) = entry.cost {
+        attributes.push(KeyValue::new("orichalcum.cost", cost));
+    }
+    let mut metadata: Vec<_> = entry.metadata.iter().collect();
+    metadata.sort();
+    for (key, value) in metadata {
+        attributes.push(KeyValue::new(format!("orichalcum.metadata.{}", key), value.clone()));
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+    use opentelemetry_proto::tonic::common::v1::any_value::Value;
+    use prost::Message;
+    use crate::core::telemetry::TokenUsage;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Answers OTLP/HTTP export requests and hands their bodies over.
+    fn collector() -> (String, mpsc::Receiver<ExportTraceServiceRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+                let _ = sender.send(ExportTraceServiceRequest::decode(body.as_slice()).unwrap());
+            }
+        });
+        (endpoint, receiver)
+    }
+
+    #[test]
+    fn test_spans_are_exported_over_otlp() {
+        let (endpoint, requests) = collector();
+        let telemetry = OtelTelemetry::otlp(endpoint).unwrap();
+
+        let run_id = "0af7651916cd43dd8448eb211c80319c".to_string();
+        telemetry.record(TraceEntry {
+            task_id: "summarize".to_string(),
+            signature_hash: "abc123".to_string(),
+            model_name: "gpt-4o".to_string(),
+            usage: Some(TokenUsage::new(42, 7)),
+            run_id: run_id.clone(),
+            span_id: "b7ad6b7169203331".to_string(),
+            parent_span_id: Some("00f067aa0ba902b7".to_string()),
+            kind: SpanKind::Sealed,
+            start_time_us: 1_700_000_000_000_000,
+            end_time_us: 1_700_000_000_250_000,
+            duration_us: 250_000,
+            ..Default::default()
+        });
+        telemetry.flush();
+
+        let request = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        let span = &request.resource_spans[0].scope_spans[0].spans[0];
+        assert_eq!(span.name, "summarize");
+        assert_eq!(hex(&span.trace_id), run_id);
+        assert_eq!(hex(&span.span_id), "b7ad6b7169203331");
+        assert_eq!(hex(&span.parent_span_id), "00f067aa0ba902b7");
+        assert_eq!(span.end_time_unix_nano - span.start_time_unix_nano, 250_000_000);
+
+        let attribute = |key: &str| {
+            span.attributes
+                .iter()
+                .find(|a| a.key == key)
+                .and_then(|a| a.value.as_ref()?.value.clone())
+        };
+        assert_eq!(attribute("gen_ai.request.model"), Some(Value::StringValue("gpt-4o".into())));
+        assert_eq!(attribute("gen_ai.usage.input_tokens"), Some(Value::IntValue(42)));
+        assert_eq!(attribute("gen_ai.usage.output_tokens"), Some(Value::IntValue(7)));
+        assert_eq!(attribute("orichalcum.signature_hash"), Some(Value::StringValue("abc123".into())));
+        assert_eq!(attribute("orichalcum.span_kind"), Some(Value::StringValue("sealed".into())));
+    }
+
+    #[test]
+    fn test_gen_ai_attributes_only_on_llm_calls() {
+        let keys = |entry: &TraceEntry| -> Vec<String> {
+            attributes(entry).into_iter().map(|a| a.key.to_string()).collect()
+        };
+        let native = TraceEntry {
+            task_id: "parse".to_string(),
+            model_name: "native".to_string(),
+            ..Default::default()
+        };
+        let native_keys = keys(&native);
+        assert!(native_keys.iter().all(|key| !key.starts_with("gen_ai.")), "{:?}", native_keys);
+        assert!(native_keys.contains(&"orichalcum.model_name".to_string()));
+
+        let call = TraceEntry {
+            llm_latency_us: Some(1_000),
+            model_name: "llama3".to_string(),
+            ..native
+        };
+        let call_keys = keys(&call);
+        assert!(call_keys.contains(&"gen_ai.operation.name".to_string()));
+        assert!(call_keys.contains(&"gen_ai.request.model".to_string()));
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
@@ -0,0 +1,518 @@
+//! Parquet sink for execution traces.
+//!
+//! Entries are buffered in memory and written as one Parquet file per flush, either when
+//! [`Telemetry::flush`] is called or once the buffer reaches its row threshold. Files are written
+//! under a temporary name and renamed when complete, so readers never see a partial file.
+//!
+//! `inputs` and `outputs` are stored as JSON text (query them with your engine's JSON functions)
+//! and `metadata` as a `map<string, string>` column; every other field has its own column.
+
+use super::{SpanKind, Telemetry, TokenUsage, TraceEntry, TraceQuery};
+use arrow::array::{
+    Array, ArrayRef, AsArray, Float64Array, MapBuilder, RecordBatch, StringArray, StringBuilder,
+    UInt64Array,
+};
+use arrow::datatypes::{Float64Type, UInt64Type};
+use arrow::error::ArrowError;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ParquetTelemetryError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+
+    #[error("Invalid trace file: {0}")]
+    Decode(String),
+}
+
+/// Writes trace entries to rolling Parquet files in a directory.
+pub struct ParquetTelemetry {
+    directory: PathBuf,
+    prefix: String,
+    max_buffered_rows: usize,
+    state: Mutex<ParquetState>,
+}
+
+#[derive(Default)]
+struct ParquetState {
+    buffer: Vec<TraceEntry>,
+    files: Vec<PathBuf>,
+    sequence: usize,
+}
+
+impl ParquetTelemetry {
+    /// Creates a sink writing to `directory`, which is created if needed.
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, ParquetTelemetryError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            prefix: "traces".to_string(),
+            max_buffered_rows: 10_000,
+            state: Mutex::new(ParquetState::default()),
+        })
+    }
+
+    /// File names start with `prefix` (`traces` by default).
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Writes a file as soon as `rows` entries are buffered (10 000 by default).
+    pub fn with_max_buffered_rows(mut self, rows: usize) -> Self {
+        self.max_buffered_rows = rows.max(1);
+        self
+    }
+
+    /// The files written so far by this sink.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.state.lock().unwrap().files.clone()
+    }
+
+    /// Writes the buffered entries to a new file, returning its path if there was anything to write.
+    /// If writing fails, the entries stay buffered for the next attempt.
+    pub fn write_pending(&self) -> Result<Option<PathBuf>, ParquetTelemetryError> {
+        let (entries, sequence) = {
+            let mut state = self.state.lock().unwrap();
+            if state.buffer.is_empty() {
+                return Ok(None);
+            }
+            state.sequence += 1;
+            (std::mem::take(&mut state.buffer), state.sequence)
+        };
+
+        let started = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let path = self
+            .directory
+            .join(format!("{}-{}-{:05}.parquet", self.prefix, started, sequence));
+        let partial = path.with_extension("parquet.partial");
+
+        let written = write_file(&entries, &partial, &path);
+        let mut state = self.state.lock().unwrap();
+        match written {
+            Ok(()) => {
+                state.files.push(path.clone());
+                Ok(Some(path))
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial);
+                // Entries recorded meanwhile were appended after these.
+                state.buffer.splice(0..0, entries);
+                Err(e)
+            }
+        }
+    }
+
+    /// Every entry recorded by this sink: its files, then the entries still buffered.
+    pub fn traces(&self) -> Result<Vec<TraceEntry>, ParquetTelemetryError> {
+        let (files, buffered) = {
+            let state = self.state.lock().unwrap();
+            (state.files.clone(), state.buffer.clone())
+        };
+        let mut entries = Vec::new();
+        for file in files {
+            entries.extend(Self::read_file(file)?);
+        }
+        entries.extend(buffered);
+        Ok(entries)
+    }
+
+    /// The entries recorded by this sink that match `query`.
+    pub fn query(&self, query: &TraceQuery) -> Result<Vec<TraceEntry>, ParquetTelemetryError> {
+        Ok(query.apply(self.traces()?))
+    }
+
+    /// Reads back the entries of a file written by this sink.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<TraceEntry>, ParquetTelemetryError> {
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path)?)?.build()?;
+        let mut entries = Vec::new();
+        for batch in reader {
+            entries.extend(from_record_batch(&batch?)?);
+        }
+        Ok(entries)
+    }
+}
+
+impl Telemetry for ParquetTelemetry {
+    fn record(&self, entry: TraceEntry) {
+        let full = {
+            let mut state = self.state.lock().unwrap();
+            state.buffer.push(entry);
+            state.buffer.len() >= self.max_buffered_rows
+        };
+        if full {
+            self.flush();
+        }
+    }
+
+    fn flush(&self) {
+        if let Err(e) = self.write_pending() {
+            log::error!("Failed to write traces to {}: {}", self.directory.display(), e);
+        }
+    }
+}
+
+impl Drop for ParquetTelemetry {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Writes `entries` to `partial`, then renames it to `path`.
+fn write_file(entries: &[TraceEntry], partial: &Path, path: &Path) -> Result<(), ParquetTelemetryError> {
+    let batch = to_record_batch(entries)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(std::fs::File::create(partial)?, batch.schema(), Some(properties))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    std::fs::rename(partial, path)?;
+    Ok(())
+}
+
+fn to_record_batch(entries: &[TraceEntry]) -> Result<RecordBatch, ArrowError> {
+    let strings = |f: fn(&TraceEntry) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(entries.iter().map(f)))
+    };
+    let optional_strings = |f: fn(&TraceEntry) -> Option<&str>| -> ArrayRef {
+        Arc::new(entries.iter().map(f).collect::<StringArray>())
+    };
+    let numbers = |f: fn(&TraceEntry) -> u64| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(entries.iter().map(f)))
+    };
+    let optional_numbers = |f: fn(&TraceEntry) -> Option<u64>| -> ArrayRef {
+        Arc::new(entries.iter().map(f).collect::<UInt64Array>())
+    };
+    let json = |f: fn(&TraceEntry) -> &crate::core::sync_impl::NodeValue| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(
+            entries.iter().map(|e| f(e).to_string()),
+        ))
+    };
+
+    let mut metadata = MapBuilder::new(None, StringBuilder::new(), StringBuilder::new());
+    for entry in entries {
+        let mut pairs: Vec<_> = entry.metadata.iter().collect();
+        pairs.sort();
+        for (key, value) in pairs {
+            metadata.keys().append_value(key);
+            metadata.values().append_value(value);
+        }
+        metadata.append(true)?;
+    }
+
+    RecordBatch::try_from_iter_with_nullable(vec![
+        ("timestamp", numbers(|e| e.timestamp), false),
+        ("task_id", strings(|e| &e.task_id), false),
+        ("signature_hash", strings(|e| &e.signature_hash), false),
+        ("instruction_hash", strings(|e| &e.instruction_hash), false),
+        ("inputs", json(|e| &e.inputs), false),
+        ("outputs", json(|e| &e.outputs), false),
+        ("model_name", strings(|e| &e.model_name), false),
+        ("training_hash", optional_strings(|e| e.training_hash.as_deref()), true),
+        (
+            "fitness_score",
+            Arc::new(entries.iter().map(|e| e.fitness_score).collect::<Float64Array>()) as ArrayRef,
+            true,
+        ),
+        ("metadata", Arc::new(metadata.finish()) as ArrayRef, false),
+        ("run_id", strings(|e| &e.run_id), false),
+        ("span_id", strings(|e| &e.span_id), false),
+        ("parent_span_id", optional_strings(|e| e.parent_span_id.as_deref()), true),
+        ("kind", strings(|e| kind_name(e.kind)), false),
+        ("start_time_us", numbers(|e| e.start_time_us), false),
+        ("end_time_us", numbers(|e| e.end_time_us), false),
+        ("duration_us", numbers(|e| e.duration_us), false),
+        ("action", optional_strings(|e| e.action.as_deref()), true),
+        ("input_tokens", optional_numbers(|e| e.usage.map(|u| u.input_tokens)), true),
+        ("output_tokens", optional_numbers(|e| e.usage.map(|u| u.output_tokens)), true),
+        ("llm_latency_us", optional_numbers(|e| e.llm_latency_us), true),
+        (
+            "cost",
+            Arc::new(entries.iter().map(|e| e.cost).collect::<Float64Array>()) as ArrayRef,
+            true,
+        ),
+    ])
+}
+
+fn mismatch(name: &str, column: &ArrayRef) -> ParquetTelemetryError {
+    ParquetTelemetryError::Decode(format!(
+        "column '{}' has unexpected type {}",
+        name,
+        column.data_type()
+    ))
+}
+
+fn kind_name(kind: SpanKind) -> &'static str {
+    match kind {
+        SpanKind::Node => "node",
+        SpanKind::Sealed => "sealed",
+        SpanKind::Flow => "flow",
+    }
+}
+
+fn from_record_batch(batch: &RecordBatch) -> Result<Vec<TraceEntry>, ParquetTelemetryError> {
+    let column = |name: &str| {
+        batch
+            .column_by_name(name)
+            .ok_or_else(|| ParquetTelemetryError::Decode(format!("missing column '{}'", name)))
+    };
+    // Files written by something else may have columns of other types.
+    let strings = |name: &str| -> Result<StringArray, ParquetTelemetryError> {
+        let c = column(name)?;
+        c.as_string_opt::<i32>().cloned().ok_or_else(|| mismatch(name, c))
+    };
+    let numbers = |name: &str| -> Result<UInt64Array, ParquetTelemetryError> {
+        let c = column(name)?;
+        c.as_primitive_opt::<UInt64Type>().cloned().ok_or_else(|| mismatch(name, c))
+    };
+    let floats = |name: &str, c: &ArrayRef| -> Result<Float64Array, ParquetTelemetryError> {
+        c.as_primitive_opt::<Float64Type>().cloned().ok_or_else(|| mismatch(name, c))
+    };
+    let optional = |array: &StringArray, row: usize| {
+        (!array.is_null(row)).then(|| array.value(row).to_string())
+    };
+    let json = |array: &StringArray, row: usize| {
+        serde_json::from_str(array.value(row))
+            .map_err(|e| ParquetTelemetryError::Decode(format!("invalid JSON: {}", e)))
+    };
+
+    let (timestamp, start, end, duration) = (
+        numbers("timestamp")?,
+        numbers("start_time_us")?,
+        numbers("end_time_us")?,
+        numbers("duration_us")?,
+    );
+    let (task_id, signature_hash, instruction_hash, model_name) = (
+        strings("task_id")?,
+        strings("signature_hash")?,
+        strings("instruction_hash")?,
+        strings("model_name")?,
+    );
+    let (inputs, outputs, training_hash, run_id) = (
+        strings("inputs")?,
+        strings("outputs")?,
+        strings("training_hash")?,
+        strings("run_id")?,
+    );
+    let (span_id, parent_span_id, kind, action) = (
+        strings("span_id")?,
+        strings("parent_span_id")?,
+        strings("kind")?,
+        strings("action")?,
+    );
+    let fitness_score = floats("fitness_score", column("fitness_score")?)?;
+    let metadata = column("metadata")?;
+    let metadata = metadata.as_map_opt().cloned().ok_or_else(|| mismatch("metadata", metadata))?;
+    // Usage columns are missing from files written before they were added.
+    let optional_numbers = |name: &str| -> Result<Option<UInt64Array>, ParquetTelemetryError> {
+        batch
+            .column_by_name(name)
+            .map(|c| c.as_primitive_opt::<UInt64Type>().cloned().ok_or_else(|| mismatch(name, c)))
+            .transpose()
+    };
+    let (input_tokens, output_tokens, llm_latency_us) = (
+        optional_numbers("input_tokens")?,
+        optional_numbers("output_tokens")?,
+        optional_numbers("llm_latency_us")?,
+    );
+    let cost = batch
+        .column_by_name("cost")
+        .map(|c| floats("cost", c))
+        .transpose()?;
+    let number = |array: &Option<UInt64Array>, row: usize| {
+        array
+            .as_ref()
+            .filter(|a| !a.is_null(row))
+            .map(|a| a.value(row))
+    };
+
+    (0..batch.num_rows())
+        .map(|row| {
+            let pairs = metadata.value(row);
+            let (Some(keys), Some(values)) =
+                (pairs.column(0).as_string_opt::<i32>(), pairs.column(1).as_string_opt::<i32>())
+            else {
+                return Err(ParquetTelemetryError::Decode(
+                    "column 'metadata' is not a map of strings".to_string(),
+                ));
+            };
+            let metadata: HashMap<String, String> = (0..pairs.len())
+                .map(|i| (keys.value(i).to_string(), values.value(i).to_string()))
+                .collect();
+
+            Ok(TraceEntry {
+                timestamp: timestamp.value(row),
+                task_id: task_id.value(row).to_string(),
+                signature_hash: signature_hash.value(row).to_string(),
+                instruction_hash: instruction_hash.value(row).to_string(),
+                inputs: json(&inputs, row)?,
+                outputs: json(&outputs, row)?,
+                model_name: model_name.value(row).to_string(),
+                training_hash: optional(&training_hash, row),
+                fitness_score: (!fitness_score.is_null(row)).then(|| fitness_score.value(row)),
+                metadata,
+                run_id: run_id.value(row).to_string(),
+                span_id: span_id.value(row).to_string(),
+                parent_span_id: optional(&parent_span_id, row),
+                kind: serde_json::from_value(kind.value(row).into())
+                    .map_err(|e| ParquetTelemetryError::Decode(format!("invalid kind: {}", e)))?,
+                start_time_us: start.value(row),
+                end_time_us: end.value(row),
+                duration_us: duration.value(row),
+                action: optional(&action, row),
+                usage: number(&input_tokens, row)
+                    .zip(number(&output_tokens, row))
+                    .map(|(input, output)| TokenUsage::new(input, output)),
+                llm_latency_us: number(&llm_latency_us, row),
+                cost: cost.as_ref().filter(|c| !c.is_null(row)).map(|c| c.value(row)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(task_id: &str) -> TraceEntry {
+        TraceEntry {
+            timestamp: 1,
+            task_id: task_id.to_string(),
+            inputs: json!({"question": "why?"}),
+            outputs: json!({"answer": 42}),
+            model_name: "phi4".to_string(),
+            fitness_score: Some(0.5),
+            metadata: HashMap::from([("user".to_string(), "alice".to_string())]),
+            run_id: "run".to_string(),
+            span_id: format!("span-{}", task_id),
+            parent_span_id: Some("root".to_string()),
+            kind: SpanKind::Sealed,
+            start_time_us: 1_000_000,
+            end_time_us: 1_000_250,
+            duration_us: 250,
+            action: Some("default".to_string()),
+            usage: Some(TokenUsage::new(120, 30)),
+            cost: Some(0.25),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rolls_files_and_reads_them_back() {
+        let directory = std::env::temp_dir().join(format!("orichalcum-{}", uuid::Uuid::new_v4()));
+        let telemetry = ParquetTelemetry::new(&directory)
+            .unwrap()
+            .with_max_buffered_rows(2);
+
+        telemetry.record(entry("a"));
+        assert!(telemetry.files().is_empty());
+        telemetry.record(entry("b"));
+        assert_eq!(telemetry.files().len(), 1);
+        telemetry.record(entry("c"));
+        telemetry.flush();
+        telemetry.flush();
+
+        let files = telemetry.files();
+        assert_eq!(files.len(), 2);
+        let first = ParquetTelemetry::read_file(&files[0]).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].task_id, "b");
+        assert_eq!(first[1].inputs, json!({"question": "why?"}));
+        assert_eq!(first[1].metadata.get("user").map(String::as_str), Some("alice"));
+        assert_eq!(first[1].kind, SpanKind::Sealed);
+        assert_eq!(first[1].training_hash, None);
+        assert_eq!(first[1].fitness_score, Some(0.5));
+        assert_eq!(first[1].usage, Some(TokenUsage::new(120, 30)));
+        assert_eq!(first[1].llm_latency_us, None);
+        assert_eq!(first[1].cost, Some(0.25));
+        assert_eq!(ParquetTelemetry::read_file(&files[1]).unwrap()[0].task_id, "c");
+
+        telemetry.record(entry("d"));
+        let traces = telemetry.query(&TraceQuery::new().model_name("phi4")).unwrap();
+        let task_ids: Vec<&str> = traces.iter().map(|t| t.task_id.as_str()).collect();
+        assert_eq!(task_ids, ["a", "b", "c", "d"]);
+
+        drop(telemetry);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_failed_writes_keep_the_entries_buffered() {
+        let directory = std::env::temp_dir().join(format!("orichalcum-{}", uuid::Uuid::new_v4()));
+        let telemetry = ParquetTelemetry::new(&directory).unwrap();
+        telemetry.record(entry("a"));
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(telemetry.write_pending().is_err());
+        telemetry.record(entry("b"));
+        let task_ids = |entries: Vec<TraceEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.task_id).collect()
+        };
+        assert_eq!(task_ids(telemetry.traces().unwrap()), ["a", "b"]);
+
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = telemetry.write_pending().unwrap().unwrap();
+        assert_eq!(task_ids(ParquetTelemetry::read_file(&path).unwrap()), ["a", "b"]);
+        let names: Vec<_> = std::fs::read_dir(&directory).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(names, [path]);
+
+        drop(telemetry);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_wrongly_typed_columns_are_decode_errors() {
+        let directory = std::env::temp_dir().join(format!("orichalcum-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let valid = to_record_batch(&[entry("a")]).unwrap();
+        let schema = valid.schema();
+
+        for name in ["timestamp", "task_id", "fitness_score", "metadata", "cost"] {
+            let columns = schema.fields().iter().zip(valid.columns()).map(|(field, c)| {
+                let c: ArrayRef = match field.name() == name {
+                    false => c.clone(),
+                    true if c.as_string_opt::<i32>().is_some() => Arc::new(UInt64Array::from(vec![7])),
+                    true => Arc::new(StringArray::from(vec!["7"])),
+                };
+                (field.name().clone(), c)
+            });
+            let batch = RecordBatch::try_from_iter(columns).unwrap();
+            let path = directory.join(format!("{}.parquet", name));
+            let mut writer =
+                ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), batch.schema(), None).unwrap();
+            writer.write(&batch).unwrap();
+            writer.close().unwrap();
+
+            match ParquetTelemetry::read_file(&path) {
+                Err(ParquetTelemetryError::Decode(message)) => {
+                    assert!(message.contains(&format!("column '{}'", name)), "{}", message)
+                }
+                other => panic!("expected a decode error for '{}', got {:?}", name, other),
+            }
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+//! Filtering and aggregation over recorded traces.
+//!
+//! A [`TraceQuery`] works over any collection of entries: `MemoryTelemetry::query` and the
+//! `traces`/`query` methods of the file sinks feed it, and [`TraceQuery::group_by`] answers
+//! questions like "how did instruction hash X do versus Y" in one call.
+
+use super::{SpanKind, TokenUsage, TraceEntry};
+use crate::core::semantic::node::ERROR_ACTION;
+use std::collections::BTreeMap;
+
+/// The entries a query keeps. Every criterion left unset matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceQuery {
+    task_id: Option<String>,
+    signature_hash: Option<String>,
+    instruction_hash: Option<String>,
+    model_name: Option<String>,
+    kind: Option<SpanKind>,
+    since_us: Option<u64>,
+    until_us: Option<u64>,
+}
+
+/// What [`TraceQuery::group_by`] groups entries by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    TaskId,
+    SignatureHash,
+    InstructionHash,
+    ModelName,
+}
+
+/// Aggregates over a group of entries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceStats {
+    /// The value the entries were grouped by.
+    pub key: String,
+    pub count: usize,
+    pub errors: usize,
+    pub error_rate: f64,
+    /// Median span duration, in microseconds.
+    pub p50_latency_us: u64,
+    /// 95th percentile span duration, in microseconds.
+    pub p95_latency_us: u64,
+    /// `None` if no entry has a fitness score.
+    pub mean_fitness: Option<f64>,
+    /// `None` if no entry reported usage.
+    pub usage: Option<TokenUsage>,
+    /// Summed over the entries that have a cost.
+    pub cost: Option<f64>,
+}
+
+impl TraceQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn task_id(mut self, task_id: impl Into<String>) -> Self {
+        self.task_id = Some(task_id.into());
+        self
+    }
+
+    pub fn signature_hash(mut self, hash: impl Into<String>) -> Self {
+        self.signature_hash = Some(hash.into());
+        self
+    }
+
+    pub fn instruction_hash(mut self, hash: impl Into<String>) -> Self {
+        self.instruction_hash = Some(hash.into());
+        self
+    }
+
+    pub fn model_name(mut self, model: impl Into<String>) -> Self {
+        self.model_name = Some(model.into());
+        self
+    }
+
+    pub fn kind(mut self, kind: SpanKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Keeps spans that started at or after `start_us` (microseconds since the epoch).
+    pub fn since(mut self, start_us: u64) -> Self {
+        self.since_us = Some(start_us);
+        self
+    }
+
+    /// Keeps spans that started before `end_us` (microseconds since the epoch).
+    pub fn until(mut self, end_us: u64) -> Self {
+        self.until_us = Some(end_us);
+        self
+    }
+
+    pub fn matches(&self, entry: &TraceEntry) -> bool {
+        let equals = |wanted: &Option<String>, value: &str| wanted.as_deref().is_none_or(|w| w == value);
+        let start = start_us(entry);
+        equals(&self.task_id, &entry.task_id)
+            && equals(&self.signature_hash, &entry.signature_hash)
+            && equals(&self.instruction_hash, &entry.instruction_hash)
+            && equals(&self.model_name, &entry.model_name)
+            && self.kind.is_none_or(|kind| kind == entry.kind)
+            && self.since_us.is_none_or(|since| start >= since)
+            && self.until_us.is_none_or(|until| start < until)
+    }
+
+    /// The matching entries, in their original order.
+    pub fn apply<I>(&self, entries: I) -> Vec<TraceEntry>
+    where
+        I: IntoIterator<Item = TraceEntry>,
+    {
+        entries.into_iter().filter(|e| self.matches(e)).collect()
+    }
+
+    /// Aggregates the matching entries over all of them.
+    pub fn stats<I>(&self, entries: I) -> TraceStats
+    where
+        I: IntoIterator<Item = TraceEntry>,
+    {
+        aggregate(String::new(), &self.apply(entries))
+    }
+
+    /// Aggregates the matching entries per value of `key`, sorted by that value.
+    pub fn group_by<I>(&self, entries: I, key: GroupBy) -> Vec<TraceStats>
+    where
+        I: IntoIterator<Item = TraceEntry>,
+    {
+        let mut groups: BTreeMap<String, Vec<TraceEntry>> = BTreeMap::new();
+        for entry in self.apply(entries) {
+            let value = match key {
+                GroupBy::TaskId => &entry.task_id,
+                GroupBy::SignatureHash => &entry.signature_hash,
+                GroupBy::InstructionHash => &entry.instruction_hash,
+                GroupBy::ModelName => &entry.model_name,
+            };
+            groups.entry(value.clone()).or_default().push(entry);
+        }
+        groups
+            .into_iter()
+            .map(|(key, entries)| aggregate(key, &entries))
+            .collect()
+    }
+}
+
+/// Whether the node failed: semantic nodes output `{"error": ...}` when their call fails, and
+/// route to [`ERROR_ACTION`] when their answer cannot be read.
+pub fn is_error(entry: &TraceEntry) -> bool {
+    entry.outputs.get("error").is_some()
+        || entry.metadata.contains_key("error")
+        || entry.action.as_deref() == Some(ERROR_ACTION)
+}
+
+fn start_us(entry: &TraceEntry) -> u64 {
+    match entry.start_time_us {
+        0 => entry.timestamp * 1_000_000,
+        start => start,
+    }
+}
+
+fn aggregate(key: String, entries: &[TraceEntry]) -> TraceStats {
+    let count = entries.len();
+    let errors = entries.iter().filter(|e| is_error(e)).count();
+
+    let mut durations: Vec<u64> = entries.iter().map(|e| e.duration_us).collect();
+    durations.sort_unstable();
+    let percentile = |p: f64| match durations.len() {
+        0 => 0,
+        n => durations[((p * n as f64).ceil() as usize).clamp(1, n) - 1],
+    };
+
+    let scores: Vec<f64> = entries.iter().filter_map(|e| e.fitness_score).collect();
+    let usage = entries
+        .iter()
+        .filter_map(|e| e.usage)
+        .reduce(|total, usage| total + usage);
+    let cost = entries.iter().filter_map(|e| e.cost).reduce(|total, cost| total + cost);
+
+    TraceStats {
+        key,
+        count,
+        errors,
+        error_rate: if count == 0 { 0.0 } else { errors as f64 / count as f64 },
+        p50_latency_us: percentile(0.5),
+        p95_latency_us: percentile(0.95),
+        mean_fitness: (!scores.is_empty())
+            .then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+        usage,
+        cost,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::telemetry::{MemoryTelemetry, Telemetry};
+    use serde_json::json;
+
+    fn entry(instruction_hash: &str, duration_us: u64, fitness: f64, failed: bool) -> TraceEntry {
+        TraceEntry {
+            task_id: "summarize".to_string(),
+            instruction_hash: instruction_hash.to_string(),
+            start_time_us: 1_000 + duration_us,
+            duration_us,
+            fitness_score: Some(fitness),
+            outputs: if failed { json!({"error": "timeout"}) } else { json!("ok") },
+            kind: SpanKind::Sealed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compare_instruction_hashes() {
+        let telemetry = MemoryTelemetry::new();
+        for i in 1..=20 {
+            telemetry.record(entry("x", i * 10, 0.5, i % 10 == 0));
+            telemetry.record(entry("y", i * 100, 0.75, false));
+        }
+        telemetry.record(TraceEntry {
+            task_id: "other".to_string(),
+            ..entry("x", 1, 0.0, true)
+        });
+
+        let stats = TraceQuery::new()
+            .task_id("summarize")
+            .group_by(telemetry.get_traces(), GroupBy::InstructionHash);
+
+        assert_eq!(stats.len(), 2);
+        let (x, y) = (&stats[0], &stats[1]);
+        assert_eq!((x.key.as_str(), x.count, x.errors), ("x", 20, 2));
+        assert_eq!(x.error_rate, 0.1);
+        assert_eq!((x.p50_latency_us, x.p95_latency_us), (100, 190));
+        assert_eq!(x.mean_fitness, Some(0.5));
+        assert_eq!((y.error_rate, y.p95_latency_us), (0.0, 1_900));
+        assert_eq!(y.mean_fitness, Some(0.75));
+
+        let recent = telemetry.query(&TraceQuery::new().instruction_hash("y").since(2_000));
+        assert_eq!(recent.len(), 11);
+        assert!(TraceQuery::new().until(1_000).apply(telemetry.get_traces()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_answers_count_as_errors() {
+        use crate::core::async_impl::async_flow::AsyncFlow;
+        use crate::llm::{mock, Client};
+        use std::collections::HashMap;
+        use std::sync::Arc;
+
+        let host = mock::ollama(|_| "It's 4.".to_string());
+        let node = Client::new()
+            .with_ollama_at(host)
+            .semantic_node()
+            .signature(crate::signature!("question -> answer"))
+            .instruction("Answer the question.")
+            .task_id("answer")
+            .seal();
+        let telemetry = Arc::new(MemoryTelemetry::new());
+        let mut shared = HashMap::from([("question".to_string(), json!("2+2?"))]);
+        AsyncFlow::new(node)
+            .run_with_telemetry(&mut shared, Some(telemetry.clone()))
+            .await;
+
+        let stats = TraceQuery::new()
+            .task_id("answer")
+            .group_by(telemetry.get_traces(), GroupBy::TaskId);
+        assert_eq!((stats[0].count, stats[0].errors), (1, 1));
+    }
+}
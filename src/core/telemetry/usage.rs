@@ -0,0 +1,115 @@
+//! Token usage, latency and cost of the LLM calls made while a sealed node runs.
+//!
+//! LLM calls report to the collector of the task they run in; sealed nodes open one around their
+//! phases and copy the totals into their trace entry.
+
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::future::Future;
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+/// Tokens consumed by one or more LLM calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn new(input_tokens: u64, output_tokens: u64) -> Self {
+        Self {
+            input_tokens,
+            output_tokens,
+        }
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(
+            self.input_tokens + other.input_tokens,
+            self.output_tokens + other.output_tokens,
+        )
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+/// What the LLM calls of a span added up to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LlmCalls {
+    pub(crate) count: usize,
+    /// `None` if no call reported any usage.
+    pub(crate) usage: Option<TokenUsage>,
+    pub(crate) latency: Duration,
+    /// `None` unless every call could be priced.
+    pub(crate) cost: Option<f64>,
+}
+
+tokio::task_local! {
+    static CALLS: RefCell<LlmCalls>;
+}
+
+/// Adds a call to the collector of the current task, if there is one.
+#[cfg_attr(not(feature = "llm"), allow(dead_code))]
+pub(crate) fn report(usage: Option<TokenUsage>, latency: Duration, cost: Option<f64>) {
+    let _ = CALLS.try_with(|calls| {
+        let mut calls = calls.borrow_mut();
+        calls.cost = match calls.count {
+            0 => cost,
+            _ => calls.cost.zip(cost).map(|(total, cost)| total + cost),
+        };
+        calls.count += 1;
+        calls.latency += latency;
+        if let Some(usage) = usage {
+            *calls.usage.get_or_insert_default() += usage;
+        }
+    });
+}
+
+/// Runs `future`, collecting the LLM calls it makes.
+pub(crate) async fn collect<F: Future>(future: F) -> (F::Output, LlmCalls) {
+    CALLS
+        .scope(RefCell::new(LlmCalls::default()), async move {
+            let output = future.await;
+            (output, CALLS.with(|calls| calls.take()))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_calls_add_up_within_their_scope() {
+        report(Some(TokenUsage::new(1, 1)), Duration::from_millis(5), None);
+
+        let ((), calls) = collect(async {
+            report(Some(TokenUsage::new(10, 2)), Duration::from_millis(20), Some(0.5));
+            let ((), nested) = collect(async {
+                report(None, Duration::from_millis(1), Some(1.0));
+            })
+            .await;
+            assert_eq!(nested.count, 1);
+            report(Some(TokenUsage::new(5, 3)), Duration::from_millis(30), Some(0.25));
+        })
+        .await;
+
+        assert_eq!(calls.count, 2);
+        assert_eq!(calls.usage, Some(TokenUsage::new(15, 5)));
+        assert_eq!(calls.latency, Duration::from_millis(50));
+        assert_eq!(calls.cost, Some(0.75));
+    }
+}
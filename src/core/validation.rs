@@ -1,3 +1,12 @@
+//! Static checks of the data flow of a workflow.
+//!
+//! Validation walks the [`FlowGraph`] of a flow from its start, checking every sealable node's
+//! inputs against the keys available on its path.
+
+use crate::core::graph::FlowGraph;
+use crate::core::scope::FlowScope;
+use std::collections::HashSet;
+
 /// Represents the availability of a key in the shared state during workflow validation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyAvailability {
@@ -9,6 +18,20 @@ pub enum KeyAvailability {
     Never,
 }
 
+impl KeyAvailability {
+    /// Computes the availability of `key` given the keys available at the end of each path.
+    pub(crate) fn across_paths(paths: &[HashSet<String>], key: &str) -> Self {
+        let hits = paths.iter().filter(|keys| keys.contains(key)).count();
+        if hits == 0 {
+            KeyAvailability::Never
+        } else if hits == paths.len() {
+            KeyAvailability::Always
+        } else {
+            KeyAvailability::Sometimes
+        }
+    }
+}
+
 /// Represents an issue found during workflow validation.
 #[derive(Debug, Clone)]
 pub enum ValidationIssue {
@@ -59,3 +82,107 @@ impl ValidationResult {
         }
     }
 }
+
+/// Walks `graph` from the node `start` with `keys` available, and returns the keys available at
+/// the end of each path.
+///
+/// A node reached a second time (a loop back to a task id) ends the path there. Scoped nested
+/// flows are checked against their mappings; unscoped ones stay opaque.
+pub(crate) fn validate_paths(
+    graph: &FlowGraph,
+    start: &str,
+    keys: HashSet<String>,
+    result: &mut ValidationResult,
+) -> Vec<HashSet<String>> {
+    let mut exits = Vec::new();
+    walk(graph, start, keys, &mut HashSet::new(), result, &mut exits);
+    exits
+}
+
+/// Validates the scoped flow starting at `start` against the keys of its caller, and returns
+/// the caller's keys it writes back.
+pub(crate) fn validate_scoped(
+    graph: &FlowGraph,
+    start: &str,
+    scope: &FlowScope,
+    available_keys: &HashSet<String>,
+    result: &mut ValidationResult,
+) -> HashSet<String> {
+    scope.check(result);
+
+    let mut child_keys = HashSet::new();
+    for (parent_key, child_key) in &scope.inputs {
+        if !available_keys.contains(parent_key) {
+            result.add_error(format!(
+                "Scoped sub-flow maps '{}' to input '{}', but '{}' is missing from the shared state.",
+                parent_key, child_key, parent_key
+            ));
+        }
+        // Assume it is there anyway so that the nested nodes don't repeat the error.
+        child_keys.insert(child_key.clone());
+    }
+
+    let exits = validate_paths(graph, start, child_keys, result);
+
+    let mut produced = HashSet::new();
+    for (child_key, parent_key) in &scope.outputs {
+        match KeyAvailability::across_paths(&exits, child_key) {
+            KeyAvailability::Always => {}
+            KeyAvailability::Sometimes => result.add_warning(format!(
+                "Scoped sub-flow output '{}' (mapped to '{}') is only produced on some paths.",
+                child_key, parent_key
+            )),
+            KeyAvailability::Never => {
+                result.add_warning(format!(
+                    "Scoped sub-flow output '{}' (mapped to '{}') is never produced.",
+                    child_key, parent_key
+                ));
+                continue;
+            }
+        }
+        produced.insert(parent_key.clone());
+    }
+    produced
+}
+
+fn walk(
+    graph: &FlowGraph,
+    id: &str,
+    mut keys: HashSet<String>,
+    visited: &mut HashSet<String>,
+    result: &mut ValidationResult,
+    exits: &mut Vec<HashSet<String>>,
+) {
+    if !visited.insert(id.to_string()) {
+        exits.push(keys);
+        return;
+    }
+    let node = graph.node(id).expect("edges point at nodes of the graph");
+
+    if let Some(signature) = &node.signature {
+        let task_id = node.task_id.as_deref().unwrap_or(&node.id);
+        for input in &signature.inputs {
+            if !keys.contains(&input.name) {
+                result.add_error(format!(
+                    "Node '{}' requires input '{}' which is missing from the shared state.",
+                    task_id, input.name
+                ));
+            }
+        }
+        keys.extend(signature.outputs.iter().map(|output| output.name.clone()));
+    }
+
+    if let (Some(scope), Some(start)) = (&node.scope, &node.nested_start) {
+        let produced = validate_scoped(graph, start, scope, &keys, result);
+        keys.extend(produced);
+    }
+
+    let mut successors = graph.successors(id).peekable();
+    if successors.peek().is_none() {
+        exits.push(keys);
+        return;
+    }
+    for edge in successors {
+        walk(graph, &edge.to, keys.clone(), visited, result, exits);
+    }
+}
@@ -70,10 +70,40 @@ mod core;
 
 // Core types
 pub use core::Executable;
-pub use core::semantic::registry::{OptimizationRecord, OptimizationRegistry};
+pub use core::definition::{
+    DefinitionError, DefinitionIssue, EdgeDefinition, FlowDefinition, NodeDefinition,
+    NodeFactory, NodeRegistry, Position, SignatureDefinition,
+};
+pub use core::events::{FlowEvent, FlowEventKind, FlowListener};
+pub use core::graph::{FlowGraph, GraphEdge, GraphNode, NodeKind};
+pub use core::middleware::{NodeInfo, NodeMiddleware};
+pub use core::scope::FlowScope;
+pub use core::semantic::registry::{
+    Change, OptimizationRecord, OptimizationRegistry, RecordDiff, RegistryError, RegistryStore,
+};
+pub use core::semantic::registry::bundle::{
+    ConflictPolicy, ImportOptions, ImportReport, RegistryBundle,
+};
+pub use core::semantic::redaction::{Redaction, ScrubPattern, Scrubber};
+pub use core::optimize::{Example, Metric, OptimizeError};
+pub use core::optimize::bootstrap::{BootstrapFewShot, BootstrapReport};
+pub use core::optimize::evaluate::{EvaluationReport, Evaluator, ExampleResult, MetricSummary};
+pub use core::optimize::metrics::{ExactMatch, JsonSubset, LlmJudge, NumericTolerance};
+pub use core::optimize::instruction::{
+    InstructionCandidate, InstructionOptimizer, InstructionReport,
+};
+pub use core::semantic::demo::{Demo, DemoFormat};
+pub use core::semantic::extract::{extract_object, ExtractError};
+pub use core::semantic::node::ERROR_ACTION;
 pub use core::semantic::signature::{Signature, Field};
 pub use core::semantic::{Promptable, Sealable};
-pub use core::telemetry::{Telemetry, TraceEntry, MemoryTelemetry};
+pub use core::state::{Key, SharedState, StateError};
+pub use core::telemetry::{
+    GroupBy, MemoryTelemetry, SpanKind, Telemetry, TokenUsage, TraceEntry, TraceQuery, TraceStats,
+};
+pub use core::telemetry::adapters::{
+    BufferedTelemetry, FilteredTelemetry, MultiTelemetry, SampledTelemetry,
+};
 pub use core::validation::{KeyAvailability, ValidationIssue, ValidationResult};
 
 // Synchronous implementations
@@ -85,7 +115,7 @@ pub use core::sync_impl::NodeValue;
 
 // Asynchronous implementations
 pub use core::async_impl::async_batch_node::{new_async_batch_node, AsyncBatchLogic};
-pub use core::async_impl::async_flow::{AsyncFlow, AsyncFlowLogic};
+pub use core::async_impl::async_flow::{AsyncFlow, AsyncFlowLogic, FlowRunHandle};
 pub use core::async_impl::async_node::{AsyncNode, AsyncNodeLogic};
 pub use core::async_impl::async_parallel_batch_node::{
     new_async_parallel_batch_node, AsyncParallelBatchLogic,
@@ -118,10 +148,22 @@ pub mod prelude {
         BatchLogic,
         // Core
         Executable,
+        FlowDefinition,
+        FlowEvent,
+        FlowEventKind,
+        FlowListener,
+        FlowGraph,
+        FlowScope,
+        NodeMiddleware,
+        NodeRegistry,
         OptimizationRecord,
         OptimizationRegistry,
+        RegistryStore,
         Sealable,
         Promptable,
+        Key,
+        SharedState,
+        StateError,
         Telemetry,
         TraceEntry,
         MemoryTelemetry,
@@ -149,8 +191,8 @@ pub mod prelude {
 /// ```
 pub mod sync_prelude {
     pub use super::{
-        new_batch_node, BatchFlow, BatchLogic, Executable, Flow, FlowLogic, Node, NodeCore,
-        NodeLogic, NodeValue,
+        new_batch_node, BatchFlow, BatchLogic, Executable, Flow, FlowLogic, Key, Node, NodeCore,
+        NodeLogic, NodeMiddleware, NodeValue, SharedState, StateError,
     };
 }
 
@@ -166,7 +208,9 @@ pub mod sync_prelude {
 pub mod async_prelude {
     pub use super::{
         new_async_batch_node, new_async_parallel_batch_node, AsyncBatchLogic, AsyncFlow,
-        AsyncFlowLogic, AsyncNode, AsyncNodeLogic, AsyncParallelBatchLogic, Executable, NodeValue,
+        AsyncFlowLogic, AsyncNode, AsyncNodeLogic, AsyncParallelBatchLogic, Executable, FlowEvent,
+        FlowEventKind, FlowListener, FlowScope, Key, NodeMiddleware, NodeValue, SharedState,
+        StateError,
     };
 }
 
@@ -181,9 +225,28 @@ pub mod llm;
 pub use llm::{
     error::LLMError,
     ollama::{Ollama, OllamaResponse},
+    usage::{Completion, ModelPrice, PriceTable},
     Client,
 };
 
+// ============================================================================
+// Telemetry Feature
+// ============================================================================
+
+#[cfg(feature = "telemetry")]
+pub use core::telemetry::jsonl::{JsonlTelemetry, TraceReader};
+#[cfg(feature = "telemetry")]
+pub use core::telemetry::parquet::{ParquetTelemetry, ParquetTelemetryError};
+#[cfg(feature = "otel")]
+pub use core::telemetry::otel::{OtelTelemetry, OtelTelemetryError};
+
+// ============================================================================
+// SQLite Feature
+// ============================================================================
+
+#[cfg(feature = "sqlite")]
+pub use core::semantic::registry::sqlite::SqliteRegistry;
+
 // ============================================================================
 // Re-export commonly used external types for convenience
 // ============================================================================
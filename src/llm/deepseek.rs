@@ -4,7 +4,8 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::llm::{error::LLMError, Client, HasProvider};
+use crate::core::telemetry::TokenUsage;
+use crate::llm::{error::LLMError, Client, Completion, HasProvider};
 
 /// Marker type for DeepSeek provider
 pub struct DeepSeek;
@@ -109,8 +110,15 @@ pub struct DeepSeekUsage {
     pub total_tokens: u32,
 }
 
+impl From<&DeepSeekUsage> for TokenUsage {
+    fn from(usage: &DeepSeekUsage) -> Self {
+        TokenUsage::new(usage.prompt_tokens as u64, usage.completion_tokens as u64)
+    }
+}
+
 use std::future::IntoFuture;
 use std::pin::Pin;
+use std::time::Instant;
 
 /// Builder for DeepSeek chat completions
 pub struct DeepSeekCompletionBuilder<'a, S> {
@@ -204,6 +212,10 @@ where
     }
 
     pub(crate) async fn execute(self) -> Result<String, LLMError> {
+        self.complete().await.map(|completion| completion.text)
+    }
+
+    pub(crate) async fn complete(self) -> Result<Completion, LLMError> {
         let config = self.client.deepseek_config.as_ref().ok_or_else(|| {
             LLMError::ProviderNotConfigured("DeepSeek not configured".to_string())
         })?;
@@ -229,10 +241,11 @@ where
             }
         }
 
+        let started = Instant::now();
         let response = self
             .client
             .call_deepseek(
-                model_to_use,
+                model_to_use.clone(),
                 self.messages,
                 self.temperature,
                 self.max_tokens,
@@ -242,11 +255,23 @@ where
             )
             .await?;
 
-        response
+        let text = response
             .choices
             .first()
             .map(|c| c.message.content.clone())
-            .ok_or_else(|| LLMError::InvalidResponse("No choices in response".to_string()))
+            .ok_or_else(|| LLMError::InvalidResponse("No choices in response".to_string()))?;
+        let usage = Some(TokenUsage::from(&response.usage));
+        Ok(self.client.finish_completion(text, model_to_use, usage, started))
+    }
+}
+
+impl<'a, S> DeepSeekCompletionBuilder<'a, S>
+where
+    S: HasProvider<DeepSeek> + Send + Sync + Clone + 'static,
+{
+    /// Run the completion, keeping its token usage, latency and cost
+    pub async fn completion(self) -> Result<Completion, LLMError> {
+        self.complete().await
     }
 }
 
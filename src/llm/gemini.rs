@@ -4,7 +4,8 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::llm::{error::LLMError, Client, HasProvider};
+use crate::core::telemetry::TokenUsage;
+use crate::llm::{error::LLMError, Client, Completion, HasProvider};
 
 /// Marker type for Gemini provider
 pub struct Gemini;
@@ -149,8 +150,18 @@ pub struct GeminiUsageMetadata {
     pub total_token_count: u32,
 }
 
+impl From<&GeminiUsageMetadata> for TokenUsage {
+    fn from(usage: &GeminiUsageMetadata) -> Self {
+        TokenUsage::new(
+            usage.prompt_token_count as u64,
+            usage.candidates_token_count as u64,
+        )
+    }
+}
+
 use std::future::IntoFuture;
 use std::pin::Pin;
+use std::time::Instant;
 
 /// Builder for Gemini content generation
 pub struct GeminiCompletionBuilder<'a, S> {
@@ -254,6 +265,10 @@ where
     }
 
     pub(crate) async fn execute(self) -> Result<String, LLMError> {
+        self.complete().await.map(|completion| completion.text)
+    }
+
+    pub(crate) async fn complete(self) -> Result<Completion, LLMError> {
         let config = self.client.gemini_config.as_ref().ok_or_else(|| {
             LLMError::ProviderNotConfigured("Gemini not configured".to_string())
         })?;
@@ -298,10 +313,11 @@ where
             response_mime_type: if self.json_mode { Some("application/json".to_string()) } else { None },
         });
 
+        let started = Instant::now();
         let response = self
             .client
             .call_gemini(
-                model_to_use,
+                model_to_use.clone(),
                 self.contents,
                 system_instruction,
                 generation_config,
@@ -309,12 +325,24 @@ where
             )
             .await?;
 
-        response
+        let text = response
             .candidates
             .first()
             .and_then(|c| c.content.parts.first())
             .and_then(|p| p.text.clone())
-            .ok_or_else(|| LLMError::InvalidResponse("No text in response".to_string()))
+            .ok_or_else(|| LLMError::InvalidResponse("No text in response".to_string()))?;
+        let usage = response.usage_metadata.as_ref().map(TokenUsage::from);
+        Ok(self.client.finish_completion(text, model_to_use, usage, started))
+    }
+}
+
+impl<'a, S> GeminiCompletionBuilder<'a, S>
+where
+    S: HasProvider<Gemini> + Send + Sync + Clone + 'static,
+{
+    /// Run the completion, keeping its token usage, latency and cost
+    pub async fn completion(self) -> Result<Completion, LLMError> {
+        self.complete().await
     }
 }
 
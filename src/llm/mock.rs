@@ -0,0 +1,67 @@
+//! A local stand-in for an Ollama server, for tests.
+
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+/// Serves `/api/chat` like Ollama would, answering each request with `respond(messages)`, and
+/// 404s everything else. Returns the host to pass to `Client::with_ollama_at`.
+pub(crate) fn ollama<F>(respond: F) -> String
+where
+    F: Fn(&[Value]) -> String + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let (status, body) = if request_line.contains("/api/chat") {
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let messages = request["messages"].as_array().cloned().unwrap_or_default();
+                let body = serde_json::json!({
+                    "model": "phi4",
+                    "created_at": "2024-01-01T00:00:00Z",
+                    "message": {"role": "assistant", "content": respond(&messages)},
+                    "done": true,
+                    "prompt_eval_count": 1200,
+                    "eval_count": 300,
+                });
+                ("200 OK", body.to_string())
+            } else {
+                ("404 Not Found", String::new())
+            };
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    host
+}
+
+/// The content of the last message of a conversation.
+pub(crate) fn last_content(messages: &[Value]) -> &str {
+    messages.last().and_then(|m| m["content"].as_str()).unwrap_or_default()
+}
@@ -6,7 +6,10 @@
 pub mod deepseek;
 pub mod error;
 pub mod gemini;
+#[cfg(test)]
+pub(crate) mod mock;
 pub mod ollama;
+pub mod usage;
 
 use std::marker::PhantomData;
 use std::sync::{Arc, RwLock};
@@ -15,6 +18,7 @@ pub use deepseek::{DeepSeek, DeepSeekConfig, DeepSeekMessage, DeepSeekResponse};
 pub use error::LLMError;
 pub use gemini::{Gemini, GeminiConfig, GeminiContent, GeminiGenerationConfig, GeminiResponse};
 pub use ollama::{Ollama, OllamaConfig};
+pub use usage::{Completion, ModelPrice, PriceTable};
 
 /// LLM client wrapper around reqwest::Client
 /// Uses typestate pattern to track which providers are configured
@@ -32,6 +36,8 @@ pub struct Client<S> {
     pub(crate) gemini_config: Option<GeminiConfig>,
     /// Cache for available models to support implicit validation
     pub(crate) model_cache: ModelCache,
+    /// Prices used to compute the cost of completions
+    pub(crate) prices: Option<Arc<PriceTable>>,
 }
 
 /// Thread-safe cache for provider model lists
@@ -93,6 +99,7 @@ impl Client<Providers<Disabled, Disabled, Disabled>> {
             deepseek_config: None,
             gemini_config: None,
             model_cache: ModelCache::default(),
+            prices: None,
         }
     }
 }
@@ -123,6 +130,7 @@ impl<D, G> Client<Providers<Disabled, D, G>> {
             deepseek_config: self.deepseek_config,
             gemini_config: self.gemini_config,
             model_cache: self.model_cache,
+            prices: self.prices,
         }
     }
 }
@@ -150,6 +158,7 @@ impl<O, G> Client<Providers<O, Disabled, G>> {
             }),
             gemini_config: self.gemini_config,
             model_cache: self.model_cache,
+            prices: self.prices,
         }
     }
 }
@@ -177,6 +186,7 @@ impl<O, D> Client<Providers<O, D, Disabled>> {
                 ..Default::default()
             }),
             model_cache: self.model_cache,
+            prices: self.prices,
         }
     }
 }
@@ -247,41 +257,62 @@ impl<O, D> Client<Providers<O, D, Enabled>> {
     }
 }
 
+/// A turn of the conversation a semantic node sends.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Turn {
+    User(String),
+    Assistant(String),
+}
+
+/// Appends `turns` to a completion builder with `user`/`assistant` methods.
+macro_rules! with_turns {
+    ($builder:expr, $turns:expr) => {{
+        let mut builder = $builder;
+        for turn in $turns {
+            builder = match turn {
+                Turn::User(content) => builder.user(content),
+                Turn::Assistant(content) => builder.assistant(content),
+            };
+        }
+        builder
+    }};
+}
+
 impl<S: Clone + Send + Sync + 'static> Client<S> {
     /// Internal dispatch method to call the first available provider.
     /// Used by semantic nodes where the provider typestate is erased.
-    pub(crate) async fn dispatch_complete(&self, prompt: &str, model: Option<String>) -> Result<String, LLMError> {
+    pub(crate) async fn dispatch_complete(&self, turns: &[Turn], model: Option<String>) -> Result<Completion, LLMError> {
         if self.deepseek_config.is_some() {
-            return self.execute_deepseek(prompt, model).await;
+            return self.execute_deepseek(turns, model).await;
         }
 
         if self.gemini_config.is_some() {
-            return self.execute_gemini(prompt, model).await;
+            return self.execute_gemini(turns, model).await;
         }
 
         if self.ollama_config.is_some() {
-            return self.execute_ollama(prompt, model).await;
+            return self.execute_ollama(turns, model).await;
         }
 
         Err(LLMError::ProviderNotConfigured("No LLM provider available".to_string()))
     }
 
-    async fn execute_deepseek(&self, prompt: &str, model: Option<String>) -> Result<String, LLMError> {
-        let mut builder = deepseek::DeepSeekCompletionBuilder::new(self).user(prompt).json_mode(true);
+    async fn execute_deepseek(&self, turns: &[Turn], model: Option<String>) -> Result<Completion, LLMError> {
+        let mut builder = with_turns!(deepseek::DeepSeekCompletionBuilder::new(self), turns).json_mode(true);
         if let Some(m) = model { builder = builder.model(m); }
-        builder.execute().await
+        builder.complete().await
     }
 
-    async fn execute_gemini(&self, prompt: &str, model: Option<String>) -> Result<String, LLMError> {
-        let mut builder = gemini::GeminiCompletionBuilder::new(self).user(prompt).json_mode(true);
+    async fn execute_gemini(&self, turns: &[Turn], model: Option<String>) -> Result<Completion, LLMError> {
+        let mut builder = with_turns!(gemini::GeminiCompletionBuilder::new(self), turns).json_mode(true);
         if let Some(m) = model { builder = builder.model(m); }
-        builder.execute().await
+        builder.complete().await
     }
 
-    async fn execute_ollama(&self, prompt: &str, model: Option<String>) -> Result<String, LLMError> {
-        let mut builder = ollama::OllamaCompletionBuilder::new(self).user(prompt).json_mode(true);
+    async fn execute_ollama(&self, turns: &[Turn], model: Option<String>) -> Result<Completion, LLMError> {
+        let mut builder = with_turns!(ollama::OllamaCompletionBuilder::new(self), turns).json_mode(true);
         if let Some(m) = model { builder = builder.model(m); }
-        builder.execute().await
+        builder.complete().await
     }
 }
 
@@ -4,7 +4,8 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::llm::{error::LLMError, Client, HasProvider};
+use crate::core::telemetry::TokenUsage;
+use crate::llm::{error::LLMError, Client, Completion, HasProvider};
 
 /// Marker type for Ollama provider
 pub struct Ollama;
@@ -130,8 +131,18 @@ pub struct OllamaChatResponse {
     pub eval_duration: u64,
 }
 
+impl OllamaChatResponse {
+    /// Token usage from `prompt_eval_count`/`eval_count`, `None` if Ollama reported neither.
+    pub fn usage(&self) -> Option<TokenUsage> {
+        (self.prompt_eval_count > 0 || self.eval_count > 0).then(|| {
+            TokenUsage::new(self.prompt_eval_count as u64, self.eval_count as u64)
+        })
+    }
+}
+
 use std::future::IntoFuture;
 use std::pin::Pin;
+use std::time::Instant;
 
 /// Builder for Ollama chat completions
 pub struct OllamaCompletionBuilder<'a, S> {
@@ -233,6 +244,10 @@ where
     }
 
     pub(crate) async fn execute(self) -> Result<String, LLMError> {
+        self.complete().await.map(|completion| completion.text)
+    }
+
+    pub(crate) async fn complete(self) -> Result<Completion, LLMError> {
         let config = self.client.ollama_config.as_ref().ok_or_else(|| {
             LLMError::ProviderNotConfigured("Ollama not configured".to_string())
         })?;
@@ -266,11 +281,25 @@ where
             stop: self.stop_sequences,
         });
 
+        let started = Instant::now();
         let response = self
             .client
-            .call_ollama_chat(model_to_use, self.messages, options, self.json_mode)
+            .call_ollama_chat(model_to_use.clone(), self.messages, options, self.json_mode)
             .await?;
-        Ok(response.message.content)
+        let usage = response.usage();
+        Ok(self
+            .client
+            .finish_completion(response.message.content, model_to_use, usage, started))
+    }
+}
+
+impl<'a, S> OllamaCompletionBuilder<'a, S>
+where
+    S: HasProvider<Ollama> + Send + Sync + Clone + 'static,
+{
+    /// Run the completion, keeping its token usage, latency and cost
+    pub async fn completion(self) -> Result<Completion, LLMError> {
+        self.complete().await
     }
 }
 
@@ -0,0 +1,142 @@
+//! Usage, latency and cost of completions.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core::telemetry::{usage, TokenUsage};
+use crate::llm::Client;
+
+/// A completion, with what it took to produce it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    /// The model that produced the completion.
+    pub model: String,
+    /// `None` if the provider did not report usage.
+    pub usage: Option<TokenUsage>,
+    /// Wall-clock time of the request.
+    pub latency: Duration,
+    /// `None` if the client has no price for the model.
+    pub cost: Option<f64>,
+}
+
+/// Price of a model, per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+/// Prices of the models a client calls, used to compute the cost of each completion.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the price of `model`, per million input and output tokens.
+    pub fn with(mut self, model: impl Into<String>, input_per_million: f64, output_per_million: f64) -> Self {
+        self.prices.insert(
+            model.into(),
+            ModelPrice {
+                input_per_million,
+                output_per_million,
+            },
+        );
+        self
+    }
+
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).copied()
+    }
+
+    /// The cost of `usage` on `model`, if the model has a price.
+    pub fn cost(&self, model: &str, usage: TokenUsage) -> Option<f64> {
+        let price = self.price(model)?;
+        Some(
+            (usage.input_tokens as f64 * price.input_per_million
+                + usage.output_tokens as f64 * price.output_per_million)
+                / 1_000_000.0,
+        )
+    }
+}
+
+impl<S> Client<S> {
+    /// Prices completions with `prices`.
+    pub fn with_prices(mut self, prices: PriceTable) -> Self {
+        self.prices = Some(Arc::new(prices));
+        self
+    }
+
+    pub fn prices(&self) -> Option<&PriceTable> {
+        self.prices.as_deref()
+    }
+
+    /// Prices a finished request and reports it to the sealed node it was made for, if any.
+    pub(crate) fn finish_completion(
+        &self,
+        text: String,
+        model: String,
+        usage: Option<TokenUsage>,
+        started: Instant,
+    ) -> Completion {
+        let latency = started.elapsed();
+        let cost = usage.and_then(|usage| self.prices.as_ref()?.cost(&model, usage));
+        usage::report(usage, latency, cost);
+        Completion {
+            text,
+            model,
+            usage,
+            latency,
+            cost,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::async_impl::async_flow::AsyncFlow;
+    use crate::core::telemetry::{MemoryTelemetry, SpanKind};
+    use crate::llm::mock;
+
+    #[test]
+    fn test_cost_from_price_table() {
+        let prices = PriceTable::new().with("phi4", 0.5, 2.0);
+        assert_eq!(prices.cost("phi4", TokenUsage::new(2_000_000, 500_000)), Some(2.0));
+        assert_eq!(prices.cost("other", TokenUsage::new(1, 1)), None);
+    }
+
+    #[tokio::test]
+    async fn test_semantic_node_traces_usage_and_cost() {
+        let client = Client::new()
+            .with_ollama_at(mock::ollama(|_| r#"{"answer": "42"}"#.to_string()))
+            .with_prices(PriceTable::new().with("phi4", 1.0, 4.0));
+        let node = client
+            .semantic_node()
+            .signature(crate::signature!("question -> answer"))
+            .instruction("Answer the question.")
+            .task_id("answer")
+            .seal();
+
+        let telemetry = Arc::new(MemoryTelemetry::new());
+        let mut shared = HashMap::from([("question".to_string(), "why?".into())]);
+        AsyncFlow::new(node)
+            .run_with_telemetry(&mut shared, Some(telemetry.clone()))
+            .await;
+
+        assert_eq!(shared.get("answer"), Some(&"42".into()));
+        let traces = telemetry.get_traces();
+        let trace = traces.iter().find(|t| t.kind == SpanKind::Sealed).unwrap();
+        assert_eq!(trace.usage, Some(TokenUsage::new(1200, 300)));
+        assert_eq!(trace.cost, Some(0.0024));
+        assert!(trace.llm_latency_us.unwrap() <= trace.duration_us);
+    }
+}
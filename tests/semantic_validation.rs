@@ -128,3 +128,131 @@ async fn test_telemetry_recording() {
     assert_eq!(traces[0].task_id, "test_telemetry");
     assert_eq!(traces[0].inputs, json!({"in": "data"}));
 }
+
+#[test]
+fn test_scoped_sub_flow_validation() {
+    let summarize = || {
+        AsyncFlow::new(Executable::Sync(Node::new(MockSealableLogic {
+            task_id: "summarize".to_string(),
+            signature: "text -> summary".parse().unwrap(),
+        })))
+    };
+
+    let ok = summarize().scoped(
+        FlowScope::new()
+            .input("article", "text")
+            .output("summary", "article_summary"),
+    );
+    assert!(ok.validate(vec!["article".to_string()]).is_safe());
+
+    // The parent key is missing, and the child key is never produced by the sub-flow.
+    let broken = summarize().scoped(
+        FlowScope::new()
+            .input("article", "text")
+            .output("headline", "article_headline"),
+    );
+    let result = broken.validate(vec![]);
+    assert!(!result.is_safe());
+    assert!(result.has_warnings());
+
+    // Nested usage: the parent only gets the mapped output back.
+    let consumer = Node::new(MockSealableLogic {
+        task_id: "consumer".to_string(),
+        signature: "article_summary -> done".parse().unwrap(),
+    });
+    let start = match Executable::from(summarize().scoped(
+        FlowScope::new()
+            .input("article", "text")
+            .output("summary", "article_summary"),
+    )) {
+        Executable::Async(node) => node.next(Executable::Sync(consumer)),
+        _ => unreachable!(),
+    };
+    let parent = AsyncFlow::new(Executable::Async(start));
+    assert!(parent.validate(vec!["article".to_string()]).is_safe());
+    assert!(!parent.validate(vec![]).is_safe());
+}
+
+#[test]
+fn test_reused_sub_flow_is_validated_at_each_call_site() {
+    let summarize = |input: &str, output: &str| {
+        AsyncFlow::new(Executable::Sync(Node::new(MockSealableLogic {
+            task_id: "summarize".to_string(),
+            signature: "text -> summary".parse().unwrap(),
+        })))
+        .scoped(FlowScope::new().input(input, "text").output("summary", output))
+    };
+    // The same task id also runs at the top level, on the parent's keys.
+    let top_level = Node::new(MockSealableLogic {
+        task_id: "summarize".to_string(),
+        signature: "article_summary, notes_summary -> summary".parse().unwrap(),
+    });
+    let second = match Executable::from(summarize("notes", "notes_summary")) {
+        Executable::Async(node) => node.next(Executable::Sync(top_level)),
+        _ => unreachable!(),
+    };
+    let start = match Executable::from(summarize("article", "article_summary")) {
+        Executable::Async(node) => node.next(Executable::Async(second)),
+        _ => unreachable!(),
+    };
+    let flow = AsyncFlow::new(Executable::Async(start));
+
+    assert!(flow.validate(vec!["article".to_string(), "notes".to_string()]).is_safe());
+
+    let result = flow.validate(vec!["article".to_string()]);
+    let errors: Vec<String> = result
+        .issues
+        .iter()
+        .filter_map(|issue| match issue {
+            ValidationIssue::Error(message) => Some(message.clone()),
+            ValidationIssue::Warning(_) => None,
+        })
+        .collect();
+    assert_eq!(
+        errors,
+        vec!["Scoped sub-flow maps 'notes' to input 'text', but 'notes' is missing from the shared state."]
+    );
+}
+
+#[tokio::test]
+async fn test_seal_with_registry_records_applied_version() {
+    let node = || {
+        Node::new(MockSealableLogic {
+            task_id: "optimized".to_string(),
+            signature: "in -> out".parse().unwrap(),
+        })
+    };
+    let base = node().seal().unwrap();
+
+    let registry = OptimizationRegistry::new();
+    let version = registry
+        .register_and_promote(OptimizationRecord {
+            task_id: "optimized".to_string(),
+            version: 0,
+            active: false,
+            signature_hash: base.signature_hash().to_string(),
+            instruction_hash: base.instruction_hash().to_string(),
+            optimized_instruction_hash: None,
+            instruction: None,
+            demos: Vec::new(),
+            training_hash: Some("train".to_string()),
+            optimization_config_hash: None,
+            fitness_score: Some(0.8),
+            weights_path: None,
+            created_at: 0,
+            updated_at: 0,
+        })
+        .unwrap();
+
+    let sealed = node().seal_with_registry(&registry).unwrap();
+    assert_eq!(sealed.fitness_score, Some(0.8));
+
+    let telemetry = Arc::new(MemoryTelemetry::new());
+    let mut shared = HashMap::from([("in".to_string(), json!("data"))]);
+    sealed.run(&mut shared, Some(telemetry.as_ref())).await;
+
+    let trace = &telemetry.get_traces()[0];
+    assert_eq!(trace.training_hash.as_deref(), Some("train"));
+    assert_eq!(trace.fitness_score, Some(0.8));
+    assert_eq!(trace.metadata["optimization.version"], version.to_string());
+}